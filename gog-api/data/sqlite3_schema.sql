@@ -1,5 +1,14 @@
-CREATE TABLE IF NOT EXISTS "login_data" ( "login" text NOT NULL PRIMARY KEY, "user_id" text NOT NULL UNIQUE, "salt" text NOT NULL, "hash" text NOT NULL );
-CREATE TABLE IF NOT EXISTS "user_pfp" ( "user_id" text(36) NOT NULL PRIMARY KEY, "data" blob, FOREIGN KEY ("user_id") REFERENCES "login_data" ("user_id") ON DELETE CASCADE );
+CREATE TABLE IF NOT EXISTS "login_data" ( "login" text NOT NULL PRIMARY KEY, "user_id" text NOT NULL UNIQUE, "salt" text NOT NULL, "hash" text NOT NULL, "role" text NOT NULL DEFAULT 'user', "suspended" boolean NOT NULL DEFAULT FALSE, "invited_by" text(36), "pfp_version" text );
+CREATE TABLE IF NOT EXISTS "user_pfp" ( "user_id" text(36) NOT NULL PRIMARY KEY, "data" blob, "blob_key" text, FOREIGN KEY ("user_id") REFERENCES "login_data" ("user_id") ON DELETE CASCADE );
 CREATE TABLE IF NOT EXISTS "posts" ( "post_id" text(36) PRIMARY KEY, "user_id" text(36) NOT NULL, "posted" text NOT NULL, "content" text NOT NULL, FOREIGN KEY ("user_id") REFERENCES "login_data" ("user_id") ON DELETE CASCADE );
 CREATE TABLE IF NOT EXISTS "comments" ( "comment_id" text(36) PRIMARY KEY, "post_id" text(36) NOT NULL, "user_id" text(36) NOT NULL, "posted" text NOT NULL, "content" text NOT NULL, FOREIGN KEY ("user_id") REFERENCES "login_data" ("user_id") ON DELETE CASCADE, FOREIGN KEY ("post_id") REFERENCES "posts" ("post_id") ON DELETE CASCADE );
-CREATE TABLE user_data (user_id text NOT NULL PRIMARY KEY, description text, "created" text, "gender" text DEFAULT 'not given', FOREIGN KEY (user_id) REFERENCES login_data (user_id) ON DELETE CASCADE);
+CREATE TABLE user_data (user_id text NOT NULL PRIMARY KEY, description text, "created" text, "display_name" text, "location" text, "website" text, "profile_fields" text, "banner_key" text, "banner_content_type" text, "banner_uploaded" text, "pronouns" text, "visibility" text, FOREIGN KEY (user_id) REFERENCES login_data (user_id) ON DELETE CASCADE);
+CREATE TABLE IF NOT EXISTS "oauth_clients" ( "client_id" text(36) NOT NULL PRIMARY KEY, "owner_id" text(36) NOT NULL, "name" text NOT NULL, "secret_hash" text, "redirect_uris" text NOT NULL, "created" text NOT NULL, FOREIGN KEY ("owner_id") REFERENCES "login_data" ("user_id") ON DELETE CASCADE );
+CREATE TABLE IF NOT EXISTS "oauth_codes" ( "code_hash" text NOT NULL PRIMARY KEY, "client_id" text(36) NOT NULL, "user_id" text(36) NOT NULL, "redirect_uri" text NOT NULL, "scope" text NOT NULL, "code_challenge" text NOT NULL, "nonce" text, "expires" text NOT NULL, FOREIGN KEY ("client_id") REFERENCES "oauth_clients" ("client_id") ON DELETE CASCADE, FOREIGN KEY ("user_id") REFERENCES "login_data" ("user_id") ON DELETE CASCADE );
+CREATE TABLE IF NOT EXISTS "oauth_tokens" ( "token_hash" text NOT NULL PRIMARY KEY, "kind" text NOT NULL, "client_id" text(36) NOT NULL, "user_id" text(36) NOT NULL, "scope" text NOT NULL, "expires" text NOT NULL, "revoked" boolean NOT NULL DEFAULT FALSE, FOREIGN KEY ("client_id") REFERENCES "oauth_clients" ("client_id") ON DELETE CASCADE, FOREIGN KEY ("user_id") REFERENCES "login_data" ("user_id") ON DELETE CASCADE );
+CREATE TABLE IF NOT EXISTS "reports" ( "report_id" text(36) NOT NULL PRIMARY KEY, "reporter_id" text(36) NOT NULL, "target_kind" text NOT NULL, "target_id" text(36) NOT NULL, "category" text NOT NULL, "reason" text NOT NULL, "created" text NOT NULL, "status" text NOT NULL DEFAULT 'open', "claimed_by" text(36), "resolved_by" text(36), "action" text, "note" text, "resolved" text, FOREIGN KEY ("reporter_id") REFERENCES "login_data" ("user_id") ON DELETE CASCADE );
+CREATE INDEX IF NOT EXISTS "idx-reports-status" ON "reports" ("status", "created");
+CREATE TABLE IF NOT EXISTS "invites" ( "code" text NOT NULL PRIMARY KEY, "created_by" text(36) NOT NULL, "created" text NOT NULL, "expires" text, "max_uses" integer NOT NULL, "uses" integer NOT NULL DEFAULT 0, FOREIGN KEY ("created_by") REFERENCES "login_data" ("user_id") ON DELETE CASCADE );
+CREATE TABLE IF NOT EXISTS "user_pfp_variants" ( "user_id" text(36) NOT NULL, "size" integer NOT NULL, "content_type" text NOT NULL, "blob_key" text, "data" blob, "content_hash" text, "uploaded" text, PRIMARY KEY ("user_id", "size"), FOREIGN KEY ("user_id") REFERENCES "login_data" ("user_id") ON DELETE CASCADE );
+CREATE TABLE IF NOT EXISTS "post_attachments" ( "attachment_id" text(36) NOT NULL PRIMARY KEY, "user_id" text(36) NOT NULL, "post_id" text(36), "position" integer, "alt_text" text, "content_type" text NOT NULL, "width" integer NOT NULL, "height" integer NOT NULL, "blob_key" text NOT NULL, "thumb_content_type" text NOT NULL, "thumb_key" text NOT NULL, "uploaded" text NOT NULL, FOREIGN KEY ("user_id") REFERENCES "login_data" ("user_id") ON DELETE CASCADE, FOREIGN KEY ("post_id") REFERENCES "posts" ("post_id") ON DELETE CASCADE );
+CREATE INDEX IF NOT EXISTS "idx-attachment_post_id" ON "post_attachments" ("post_id");
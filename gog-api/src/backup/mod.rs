@@ -0,0 +1,392 @@
+//! Copies of a live SQLite database, made with `VACUUM INTO` while the server keeps serving.
+//! A copy only gets a backup name once `PRAGMA integrity_check` passed on it, so every file named
+//! like a backup is a complete one. Restoring swaps the database file and needs the server stopped
+
+use std::ffi::OsString;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use chrono::{DateTime, NaiveDateTime, SubsecRound, Utc};
+use flate2::{bufread::GzDecoder, write::GzEncoder, Compression};
+use log::{info, warn};
+use sea_orm::{
+    ConnectOptions, ConnectionTrait, Database, DatabaseConnection, DbBackend, DbErr, Statement,
+};
+use sea_orm_migration::MigratorTrait;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::config::BackupConfig;
+use crate::jobs::JobError;
+use crate::migrator::Migrator;
+
+const PREFIX: &str = "gog-";
+const TIME_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+const EXTENSION: &str = ".db";
+const GZ_EXTENSION: &str = ".db.gz";
+
+#[derive(Debug, Error)]
+pub enum BackupError {
+    #[error("Database error: {source}")]
+    DatabaseError {
+        #[from]
+        source: DbErr,
+    },
+    #[error("IO error: {source}")]
+    IoError {
+        #[from]
+        source: std::io::Error,
+    },
+    #[error("Backups need an SQLite database stored in a file")]
+    Unsupported,
+    #[error("Another backup is being made")]
+    Busy,
+    #[error("Backup `{0}` already exists")]
+    Exists(String),
+    #[error("Backup `{0}` not found")]
+    NotFound(String),
+    #[error("`{0}` failed the integrity check: {1}")]
+    Corrupt(String, String),
+    #[error("{0}")]
+    Incompatible(String),
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct BackupInfo {
+    pub name: String,
+    pub created: DateTime<Utc>,
+    pub bytes: u64,
+    pub compressed: bool,
+}
+
+/// What [`restore`] did
+#[derive(Debug)]
+pub struct Restored {
+    /// The last migration applied to the backup
+    pub migration: Option<String>,
+    /// Migrations the server applies on its next start
+    pub pending: usize,
+    /// Where the replaced database went, `None` when there was none
+    pub previous: Option<PathBuf>,
+}
+
+/// The file behind an SQLite url, `None` for other databases and in memory ones
+pub fn sqlite_path(url: &str) -> Option<PathBuf> {
+    let path = url
+        .strip_prefix("sqlite://")
+        .or_else(|| url.strip_prefix("sqlite:"))?;
+    let path = path.split('?').next().unwrap_or_default();
+    if path.is_empty() || path.starts_with(":memory:") {
+        None
+    } else {
+        Some(PathBuf::from(path))
+    }
+}
+
+/// When a backup was made and whether it is gzipped, `None` for files that are no backups
+fn parse_name(name: &str) -> Option<(DateTime<Utc>, bool)> {
+    let rest = name.strip_prefix(PREFIX)?;
+    let (stamp, compressed) = match rest.strip_suffix(GZ_EXTENSION) {
+        Some(stamp) => (stamp, true),
+        None => (rest.strip_suffix(EXTENSION)?, false),
+    };
+    let created = NaiveDateTime::parse_from_str(stamp, TIME_FORMAT)
+        .ok()?
+        .and_utc();
+    Some((created, compressed))
+}
+
+/// `path` with `suffix` appended to the file name, the way SQLite names its `-wal` files
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(suffix);
+    path.into()
+}
+
+/// Backups in `dir`, oldest first
+pub async fn list(dir: &Path) -> Result<Vec<BackupInfo>, BackupError> {
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut backups = Vec::new();
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if let Some((created, compressed)) = parse_name(&name) {
+            backups.push(BackupInfo {
+                name,
+                created,
+                bytes: entry.metadata().await?.len(),
+                compressed,
+            });
+        }
+    }
+    backups.sort_by_key(|b| b.created);
+    Ok(backups)
+}
+
+/// The last backup made at or before `at`
+pub fn latest_at(backups: &[BackupInfo], at: DateTime<Utc>) -> Option<&BackupInfo> {
+    backups.iter().rev().find(|b| b.created <= at)
+}
+
+/// Runs `PRAGMA integrity_check` on the database at `path`, read only,
+/// and returns the migrations applied to it
+async fn verify(path: &Path) -> Result<Vec<String>, BackupError> {
+    let mut options = ConnectOptions::new(format!("sqlite://{}?mode=ro", path.display()));
+    options.max_connections(1).sqlx_logging(false);
+    let db = Database::connect(options).await?;
+    let checked = async {
+        let corrupt = |message: String| BackupError::Corrupt(path.display().to_string(), message);
+        let mut messages = Vec::new();
+        // a file that is too broken to check fails with an error instead of a message
+        for row in db
+            .query_all(Statement::from_string(
+                DbBackend::Sqlite,
+                "PRAGMA integrity_check",
+            ))
+            .await
+            .map_err(|e| corrupt(e.to_string()))?
+        {
+            messages.push(row.try_get_by_index::<String>(0)?);
+        }
+        if messages != ["ok"] {
+            return Err(corrupt(messages.join("; ")));
+        }
+        let tracked = db
+            .query_one(Statement::from_string(
+                DbBackend::Sqlite,
+                "SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'seaql_migrations'",
+            ))
+            .await?
+            .is_some();
+        let mut migrations = Vec::new();
+        if tracked {
+            for row in db
+                .query_all(Statement::from_string(
+                    DbBackend::Sqlite,
+                    "SELECT version FROM seaql_migrations ORDER BY version",
+                ))
+                .await?
+            {
+                migrations.push(row.try_get::<String>("", "version")?);
+            }
+        }
+        Ok(migrations)
+    }
+    .await;
+    db.close().await?;
+    checked
+}
+
+fn gzip(from: &Path, to: &Path) -> std::io::Result<()> {
+    let mut input = std::fs::File::open(from)?;
+    let output = BufWriter::new(std::fs::File::create(to)?);
+    let mut encoder = GzEncoder::new(output, Compression::default());
+    std::io::copy(&mut input, &mut encoder)?;
+    encoder
+        .finish()?
+        .into_inner()
+        .map_err(|e| e.into_error())?
+        .sync_all()
+}
+
+fn gunzip(from: &Path, to: &Path) -> std::io::Result<()> {
+    let mut decoder = GzDecoder::new(BufReader::new(std::fs::File::open(from)?));
+    let mut output = std::fs::File::create(to)?;
+    std::io::copy(&mut decoder, &mut output)?;
+    output.sync_all()
+}
+
+/// Runs file work that would hold up the other tasks of the server on a thread of its own
+async fn blocking(
+    work: impl FnOnce() -> std::io::Result<()> + Send + 'static,
+) -> std::io::Result<()> {
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(std::io::Error::other)?
+}
+
+async fn remove_if_exists(path: &Path) -> std::io::Result<()> {
+    match tokio::fs::remove_file(path).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Makes backups of the database of the server, one at a time
+pub struct BackupService {
+    db: DatabaseConnection,
+    config: BackupConfig,
+    running: tokio::sync::Mutex<()>,
+}
+
+impl BackupService {
+    pub fn new(db: DatabaseConnection, config: BackupConfig) -> Self {
+        Self {
+            db,
+            config,
+            running: tokio::sync::Mutex::new(()),
+        }
+    }
+    pub async fn list(&self) -> Result<Vec<BackupInfo>, BackupError> {
+        list(Path::new(&self.config.dir)).await
+    }
+    /// Backs the database up and deletes the backups beyond `keep`.
+    /// Fails with [`BackupError::Busy`] instead of waiting while another backup is made
+    pub async fn create(&self) -> Result<BackupInfo, BackupError> {
+        let _running = self.running.try_lock().map_err(|_| BackupError::Busy)?;
+        if self.db.get_database_backend() != DbBackend::Sqlite {
+            return Err(BackupError::Unsupported);
+        }
+        let dir = Path::new(&self.config.dir);
+        tokio::fs::create_dir_all(dir).await?;
+        // as precise as the name, so listing the backup later says the same
+        let created = Utc::now().trunc_subsecs(0);
+        let stem = format!("{}{}", PREFIX, created.format(TIME_FORMAT));
+        let name = match self.config.compress {
+            true => format!("{}{}", stem, GZ_EXTENSION),
+            false => format!("{}{}", stem, EXTENSION),
+        };
+        let path = dir.join(&name);
+        if tokio::fs::try_exists(&path).await? {
+            return Err(BackupError::Exists(name));
+        }
+        // dot files are never taken for backups, whatever is left of a failed attempt included
+        let copy = dir.join(format!(".{}{}.partial", stem, EXTENSION));
+        let packed = dir.join(format!(".{}.partial", name));
+        let made = self.write(&copy, &packed, &path).await;
+        for partial in [&copy, &packed] {
+            if let Err(e) = remove_if_exists(partial).await {
+                warn!("could not remove `{}`: {}", partial.display(), e);
+            }
+        }
+        let migration = made?;
+        info!(
+            "backed up the database at {} to `{}`",
+            migration.as_deref().unwrap_or("no migration"),
+            path.display()
+        );
+        self.rotate().await?;
+        Ok(BackupInfo {
+            name,
+            created,
+            bytes: tokio::fs::metadata(&path).await?.len(),
+            compressed: self.config.compress,
+        })
+    }
+    /// Copies the database to `copy`, checks it and puts it at `path`, gzipped through `packed`
+    async fn write(
+        &self,
+        copy: &Path,
+        packed: &Path,
+        path: &Path,
+    ) -> Result<Option<String>, BackupError> {
+        remove_if_exists(copy).await?;
+        // a consistent snapshot even while other connections write, unlike copying the file
+        self.db
+            .execute_unprepared(&format!(
+                "VACUUM INTO '{}'",
+                copy.display().to_string().replace('\'', "''")
+            ))
+            .await?;
+        let migration = verify(copy).await?.pop();
+        if self.config.compress {
+            let (copy, to) = (copy.to_owned(), packed.to_owned());
+            blocking(move || gzip(&copy, &to)).await?;
+            tokio::fs::rename(packed, path).await?;
+        } else {
+            tokio::fs::rename(copy, path).await?;
+        }
+        Ok(migration)
+    }
+    async fn rotate(&self) -> Result<(), BackupError> {
+        let backups = self.list().await?;
+        let old = backups.len().saturating_sub(self.config.keep);
+        for backup in &backups[..old] {
+            tokio::fs::remove_file(Path::new(&self.config.dir).join(&backup.name)).await?;
+            info!("deleted the old backup `{}`", backup.name);
+        }
+        Ok(())
+    }
+}
+
+/// One scheduled backup, run by the job supervisor every `backup.interval` seconds
+pub async fn scheduled(backups: Arc<BackupService>) -> Result<(), JobError> {
+    match backups.create().await {
+        Ok(_) => Ok(()),
+        Err(e @ BackupError::Unsupported) => Err(JobError::Unsupported(e.to_string())),
+        Err(e) => Err(JobError::Failed(e.to_string())),
+    }
+}
+
+/// Replaces the database at `live` with the backup at `backup`, which has to be at a migration
+/// this version knows. The server must be stopped, it would keep writing to the file it opened.
+/// The replaced database and its `-wal` files are kept next to it as `<file>.before-restore-<time>`
+pub async fn restore(backup: &Path, live: &Path) -> Result<Restored, BackupError> {
+    if !tokio::fs::try_exists(backup).await? {
+        return Err(BackupError::NotFound(backup.display().to_string()));
+    }
+    let restoring = with_suffix(live, ".restoring");
+    remove_if_exists(&restoring).await?;
+    let checked = async {
+        if backup.to_string_lossy().ends_with(".gz") {
+            let (from, to) = (backup.to_owned(), restoring.clone());
+            blocking(move || gunzip(&from, &to)).await?;
+        } else {
+            tokio::fs::copy(backup, &restoring).await?;
+        }
+        let applied = verify(&restoring).await.map_err(|e| match e {
+            BackupError::Corrupt(_, message) => {
+                BackupError::Corrupt(backup.display().to_string(), message)
+            }
+            e => e,
+        })?;
+        let known = Migrator::migrations()
+            .iter()
+            .map(|m| m.name().to_owned())
+            .collect::<Vec<_>>();
+        if let Some(unknown) = applied.iter().find(|m| !known.contains(m)) {
+            return Err(BackupError::Incompatible(format!(
+                "the backup is at migration `{}`, which this version of gog-api does not know, restore it with the version that made it",
+                unknown
+            )));
+        }
+        if known[..applied.len()] != applied[..] {
+            return Err(BackupError::Incompatible(
+                "the backup skipped migrations this version of gog-api applies in between, it cannot be migrated from there".to_owned(),
+            ));
+        }
+        Ok(Restored {
+            pending: known.len() - applied.len(),
+            migration: applied.last().cloned(),
+            previous: None,
+        })
+    }
+    .await;
+    let mut restored = match checked {
+        Ok(restored) => restored,
+        Err(e) => {
+            remove_if_exists(&restoring).await?;
+            return Err(e);
+        }
+    };
+    if tokio::fs::try_exists(live).await? {
+        let previous = with_suffix(
+            live,
+            &format!(".before-restore-{}", Utc::now().format(TIME_FORMAT)),
+        );
+        // the journal files hold writes that belong to the replaced database, they go with it
+        for suffix in ["", "-wal", "-shm", "-journal"] {
+            let file = with_suffix(live, suffix);
+            if tokio::fs::try_exists(&file).await? {
+                tokio::fs::rename(&file, with_suffix(&previous, suffix)).await?;
+            }
+        }
+        restored.previous = Some(previous);
+    }
+    tokio::fs::rename(&restoring, live).await?;
+    Ok(restored)
+}
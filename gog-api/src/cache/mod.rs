@@ -1,14 +1,21 @@
-use std::{collections::HashMap, io};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
 
+use bytes::Bytes;
+use serde::Serialize;
 use thiserror::Error;
-use tokio::io::AsyncReadExt;
 
 type CacheResult<T> = Result<T, CacheError>;
 
 #[derive(Debug, Error)]
 pub enum CacheError {
-    #[error("File at specified path `{path}` is already present in the cache")]
-    AlreadyPresent { path: String },
     #[error("Io error")]
     IoError {
         #[from]
@@ -16,56 +23,161 @@ pub enum CacheError {
     },
 }
 
+/// When a cached file is read from disk again
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Invalidation {
+    /// After it has been cached for this long
+    Ttl(Duration),
+    /// Whenever its modification time changes, costs a `stat` per hit
+    Modified,
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub entries: usize,
+    pub bytes: usize,
+    pub max_bytes: usize,
+}
+
+struct Entry {
+    data: Bytes,
+    modified: Option<SystemTime>,
+    loaded: Instant,
+    /// Key of the entry in [`Entries::recency`]
+    used: u64,
+}
+
+#[derive(Default)]
+struct Entries {
+    by_path: HashMap<String, Entry>,
+    /// Paths by last use, the first one is evicted first
+    recency: BTreeMap<u64, String>,
+    clock: u64,
+    bytes: usize,
+}
+
+impl Entries {
+    fn touch(&mut self, path: &str) -> Option<Bytes> {
+        self.clock += 1;
+        let clock = self.clock;
+        let entry = self.by_path.get_mut(path)?;
+        let path = self
+            .recency
+            .remove(&entry.used)
+            .expect("cache entry missing from recency list");
+        self.recency.insert(clock, path);
+        entry.used = clock;
+        Some(entry.data.clone())
+    }
+    fn remove(&mut self, path: &str) {
+        if let Some(entry) = self.by_path.remove(path) {
+            self.recency.remove(&entry.used);
+            self.bytes -= entry.data.len();
+        }
+    }
+    /// Drops least recently used entries until `incoming` more bytes fit, returns how many went
+    fn make_room(&mut self, incoming: usize, max_bytes: usize) -> u64 {
+        let mut evicted = 0;
+        while self.bytes + incoming > max_bytes {
+            let Some((_, path)) = self.recency.pop_first() else {
+                break;
+            };
+            if let Some(entry) = self.by_path.remove(&path) {
+                self.bytes -= entry.data.len();
+            }
+            evicted += 1;
+        }
+        evicted
+    }
+}
+
+/// Files read from disk, bounded to `max_bytes` and evicted least recently used first.
+/// Hits hand out a reference counted [`Bytes`] instead of a copy.
+/// The lock is only taken between awaits, so a slow disk never blocks other requests
 pub struct ResourceCache {
-    resources: HashMap<String, Vec<u8>>,
+    entries: Mutex<Entries>,
+    max_bytes: usize,
+    invalidation: Invalidation,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
 }
 
 impl ResourceCache {
-    pub fn new() -> Self {
+    pub fn new(max_bytes: usize, invalidation: Invalidation) -> Self {
         Self {
-            resources: HashMap::new(),
+            entries: Mutex::new(Entries::default()),
+            max_bytes,
+            invalidation,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
         }
     }
-    /// Loads a resource from a file path and caches it
-    pub async fn load(&mut self, path: &str) -> CacheResult<()> {
-        if self.resources.contains_key(path) {
-            return Err(CacheError::AlreadyPresent {
-                path: path.to_owned(),
-            });
+    /// The cached contents of the file at `path`, read from disk when missing or outdated
+    pub async fn get_or_load(&self, path: &str) -> CacheResult<Bytes> {
+        let cached = {
+            let entries = self.entries.lock().unwrap();
+            entries
+                .by_path
+                .get(path)
+                .map(|e| (e.modified, e.loaded.elapsed()))
         };
-        let mut buf = vec![];
-        let f = tokio::fs::File::open(path).await?;
-        let mut reader = tokio::io::BufReader::new(f);
-        reader.read_to_end(&mut buf).await?;
-        self.resources.insert(path.to_owned(), buf);
-        Ok(())
-    }
-    pub async fn get_or_load(&mut self, path: &str) -> CacheResult<Vec<u8>> {
-        match self.load(path).await {
-            Err(CacheError::AlreadyPresent { path: _ }) | Ok(_) => {
-                return Ok(self.resources.get(path).unwrap().to_owned())
+        let fresh = match (cached, self.invalidation) {
+            (None, _) => false,
+            (Some((_, age)), Invalidation::Ttl(ttl)) => age < ttl,
+            (Some((modified, _)), Invalidation::Modified) => {
+                modified.is_some() && modified == modification_time(path).await
             }
-            Err(e) => Err(e),
-        }
-    }
-    pub async fn get_or_load_ref(&mut self, path: &str) -> CacheResult<&Vec<u8>> {
-        match self.load(path).await {
-            Err(CacheError::AlreadyPresent { path: _ }) | Ok(_) => {
-                return Ok(&self.resources.get(path).unwrap())
+        };
+        if fresh {
+            // the entry may have been evicted while the file was checked
+            if let Some(data) = self.entries.lock().unwrap().touch(path) {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(data);
             }
-            Err(e) => Err(e),
         }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let modified = modification_time(path).await;
+        let data = Bytes::from(tokio::fs::read(path).await?);
+        let mut entries = self.entries.lock().unwrap();
+        entries.remove(path);
+        if data.len() <= self.max_bytes {
+            let evicted = entries.make_room(data.len(), self.max_bytes);
+            self.evictions.fetch_add(evicted, Ordering::Relaxed);
+            entries.clock += 1;
+            let used = entries.clock;
+            entries.bytes += data.len();
+            entries.recency.insert(used, path.to_owned());
+            entries.by_path.insert(
+                path.to_owned(),
+                Entry {
+                    data: data.clone(),
+                    modified,
+                    loaded: Instant::now(),
+                    used,
+                },
+            );
+        }
+        Ok(data)
     }
-    pub fn get(&self, path: &str) -> Option<Vec<u8>> {
-        self.resources.get(path).map(|s| s.to_owned())
-    }
-    pub fn get_ref(&self, path: &str) -> Option<&Vec<u8>> {
-        self.resources.get(path)
-    }
-    pub fn drop_from_cache(&mut self, path: &str) -> Option<Vec<u8>> {
-        self.resources.remove(path)
-    }
-    pub fn clear_cache(&mut self) {
-        self.resources.clear();
+    pub fn stats(&self) -> CacheStats {
+        let entries = self.entries.lock().unwrap();
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            entries: entries.by_path.len(),
+            bytes: entries.bytes,
+            max_bytes: self.max_bytes,
+        }
     }
 }
+
+async fn modification_time(path: &str) -> Option<SystemTime> {
+    tokio::fs::metadata(path).await.ok()?.modified().ok()
+}
@@ -0,0 +1,793 @@
+//! Subcommands for operators, each opens the database the server would and exits when done.
+//! Passwords are read from the first line of stdin, so they never show up in the process list
+//! or the shell history
+
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use gog_commons::data_structures::{Role, UserCreationData};
+use log::info;
+use sea_orm::{
+    sea_query::Condition, ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait,
+    DatabaseConnection, DbBackend, DbErr, EntityTrait, IntoActiveModel, PaginatorTrait,
+    QueryFilter, TransactionTrait,
+};
+use sea_orm_migration::{MigrationStatus, MigratorTrait};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+use validator::Validate;
+
+use crate::backup::{self, BackupError, BackupService};
+use crate::config::Config;
+use crate::entity::{
+    comments, invites, login_data, oauth_clients, oauth_codes, oauth_tokens, post_attachments,
+    posts, prelude::*, reports, user_data, user_pfp, user_pfp_variants,
+};
+use crate::migrator::{schema, Migrator};
+use crate::service::{self, resources};
+use crate::storage::BlobStore;
+
+/// Bumped whenever the layout of an export changes, not the schema
+const EXPORT_FORMAT: u32 = 1;
+/// Rows per insert, well below the bound parameter limit of SQLite
+const IMPORT_CHUNK: usize = 100;
+const SEED_PASSWORD: &str = "seed-password";
+const ROLES: [&str; 3] = ["user", "moderator", "admin"];
+const SCHEMA_SCRIPT: &str = "data/sqlite3_schema.sql";
+
+#[derive(Debug, Error)]
+pub enum CliError {
+    #[error("Database error: {source}")]
+    DatabaseError {
+        #[from]
+        source: DbErr,
+    },
+    #[error("Transaction error: {source}")]
+    TransactionError {
+        #[from]
+        source: sea_orm::TransactionError<DbErr>,
+    },
+    #[error("Storage error: {source}")]
+    StorageError {
+        #[from]
+        source: resources::ReplacePfpError,
+    },
+    #[error("IO error: {source}")]
+    IoError {
+        #[from]
+        source: std::io::Error,
+    },
+    #[error("Export error: {source}")]
+    JsonError {
+        #[from]
+        source: serde_json::Error,
+    },
+    #[error("Password hashing error: {0}")]
+    HashError(argon2::password_hash::Error),
+    #[error("User `{0}` not found")]
+    UserNotFound(String),
+    #[error("User `{0}` already exists")]
+    UserExists(String),
+    #[error("Invalid user: {0}")]
+    Invalid(String),
+    #[error("Table `{0}` is not empty, imports only go into a fresh database")]
+    NotEmpty(String),
+    #[error("{0}")]
+    Import(String),
+    #[error("{0} problems found")]
+    Check(usize),
+    #[error("A server answers on {0}, stop it first: blobs released now could be taken by an upload in progress")]
+    ServerRunning(String),
+    #[error("Schema checks support SQLite and Postgres, this database is {0:?}")]
+    UnsupportedBackend(DbBackend),
+    #[error("{source}")]
+    BackupError {
+        #[from]
+        source: BackupError,
+    },
+    #[error("No backup was made at or before {0}")]
+    NoBackupAt(DateTime<Utc>),
+}
+
+fn login_arg() -> Arg {
+    Arg::new("login").required(true)
+}
+
+fn role_arg() -> Arg {
+    Arg::new("role").value_parser(ROLES)
+}
+
+fn steps_arg() -> Arg {
+    Arg::new("steps")
+        .long("steps")
+        .value_parser(clap::value_parser!(u32).range(1..))
+}
+
+/// Registered next to the server flags, `main` hands every one of them to [`run`]
+pub fn commands() -> Vec<Command> {
+    vec![
+        Command::new("migrate")
+            .about("Apply or roll back database migrations, then exit")
+            .subcommand_required(true)
+            .subcommand(
+                Command::new("up")
+                    .about("Apply pending migrations, all of them unless --steps says otherwise")
+                    .arg(steps_arg()),
+            )
+            .subcommand(
+                Command::new("down")
+                    .about("Roll back the last applied migration, or the last --steps of them")
+                    .arg(steps_arg()),
+            )
+            .subcommand(Command::new("status").about("List migrations and whether they are applied"))
+            .subcommand(
+                Command::new("check")
+                    .about("Compare the schema of the database with the entities and a schema script, and roll every migration back and forth on a scratch database. SQLite only")
+                    .arg(
+                        Arg::new("schema")
+                            .long("schema")
+                            .help("Script the schema should match")
+                            .default_value(SCHEMA_SCRIPT),
+                    ),
+            ),
+        Command::new("user")
+            .about("Manage accounts, then exit")
+            .subcommand_required(true)
+            .subcommand(
+                Command::new("create")
+                    .about("Create an account, the password is read from stdin")
+                    .arg(login_arg())
+                    .arg(role_arg().long("role").default_value("user")),
+            )
+            .subcommand(
+                Command::new("delete")
+                    .about("Delete an account with everything it posted and uploaded")
+                    .arg(login_arg()),
+            )
+            .subcommand(
+                Command::new("set-password")
+                    .about("Replace the password of an account, the new one is read from stdin")
+                    .arg(login_arg()),
+            )
+            .subcommand(
+                Command::new("set-role")
+                    .about("Change the role of an account")
+                    .arg(login_arg())
+                    .arg(role_arg().required(true)),
+            ),
+        Command::new("session")
+            .about("Manage stored grants, then exit")
+            .subcommand_required(true)
+            .subcommand(
+                Command::new("purge")
+                    .about("Delete expired and revoked OAuth codes and tokens. Login sessions live in the memory of the server and end when it restarts")
+                    .arg(
+                        Arg::new("all")
+                            .long("all")
+                            .help("Delete every OAuth code and token, signing all users out of other apps")
+                            .action(ArgAction::SetTrue),
+                    ),
+            ),
+        Command::new("db")
+            .about("Move the content of the database between instances, back it up or restore it, then exit")
+            .subcommand_required(true)
+            .subcommand(
+                Command::new("export")
+                    .about("Write every row to a JSON file, `-` for stdout. Files in the blob store are not included")
+                    .arg(Arg::new("file").required(true)),
+            )
+            .subcommand(
+                Command::new("import")
+                    .about("Read an export into an empty database at the same migration")
+                    .arg(Arg::new("file").required(true)),
+            )
+            .subcommand(Command::new("backup").about(
+                "Back the database up, the server may keep running meanwhile. SQLite only",
+            ))
+            .subcommand(Command::new("backups").about("List the backups, oldest first"))
+            .subcommand(
+                Command::new("restore")
+                    .about("Replace the database with a backup at a migration this version knows. Stop the server first, it applies the remaining migrations when started again")
+                    .arg(
+                        Arg::new("backup")
+                            .help("Name of a backup in the backup directory, or the path of one")
+                            .required_unless_present("at")
+                            .conflicts_with("at"),
+                    )
+                    .arg(
+                        Arg::new("at")
+                            .long("at")
+                            .help("Restore the last backup made at or before this time, like `2024-05-01T12:00:00Z`")
+                            .value_parser(parse_time),
+                    ),
+            ),
+        Command::new("seed")
+            .about("Fill the database with users, posts and comments to develop against, then exit")
+            .arg(
+                Arg::new("users")
+                    .long("users")
+                    .default_value("3")
+                    .value_parser(clap::value_parser!(u32).range(1..=1000)),
+            ),
+    ]
+}
+
+pub fn handles(name: &str) -> bool {
+    matches!(name, "migrate" | "user" | "session" | "db" | "seed")
+}
+
+pub async fn run(
+    name: &str,
+    args: &ArgMatches,
+    config: &Config,
+    store: &dyn BlobStore,
+) -> Result<(), CliError> {
+    let url = config.database.url.as_deref().expect("validated");
+    let db_name = config.database.name.as_deref().expect("validated");
+    if let ("db", Some((command @ ("backup" | "backups" | "restore"), args))) =
+        (name, args.subcommand())
+    {
+        // a backup taken before the migrations run is what there is to go back to if one fails
+        return backups(command, args, config).await;
+    }
+    if name == "migrate" {
+        // the point is to control the migrations, so they are not all applied up front
+        let db = crate::connect_database(url, db_name).await?;
+        return migrate(&db, args).await;
+    }
+    let db = crate::setup_database(url, db_name, false).await?;
+    match (name, args.subcommand()) {
+        ("user", Some(("create", args))) => {
+            let login = args.get_one::<String>("login").expect("required");
+            let role = parse_role(args);
+            create_user(&db, login, &read_password()?, role).await?;
+            println!("created {} `{}`", role.as_str(), login);
+        }
+        ("user", Some(("delete", args))) => {
+            let login = args.get_one::<String>("login").expect("required");
+            if crate::maintenance::server_running(&config.server) {
+                let server = format!("{}:{}", config.server.address, config.server.port);
+                return Err(CliError::ServerRunning(server));
+            }
+            delete_user(&db, store, login).await?;
+            println!("deleted `{}`", login);
+        }
+        ("user", Some(("set-password", args))) => {
+            let login = args.get_one::<String>("login").expect("required");
+            set_password(&db, login, &read_password()?).await?;
+            println!("changed the password of `{}`", login);
+        }
+        ("user", Some(("set-role", args))) => {
+            let login = args.get_one::<String>("login").expect("required");
+            let role = parse_role(args);
+            let mut model: login_data::ActiveModel = find_user(&db, login).await?.into();
+            model.role = ActiveValue::Set(role.as_str().to_owned());
+            LoginData::update(model).exec(&db).await?;
+            println!("`{}` is now {}", login, role.as_str());
+        }
+        ("session", Some(("purge", args))) => {
+            let (codes, tokens) = purge_grants(&db, args.get_flag("all")).await?;
+            println!("deleted {} OAuth codes and {} tokens", codes, tokens);
+        }
+        ("db", Some(("export", args))) => {
+            let file = args.get_one::<String>("file").expect("required");
+            let export = export(&db).await?;
+            if file == "-" {
+                serde_json::to_writer(std::io::stdout().lock(), &export)?;
+            } else {
+                let out = std::io::BufWriter::new(std::fs::File::create(file)?);
+                serde_json::to_writer(out, &export)?;
+                eprintln!(
+                    "exported the database at {} to `{}`",
+                    export.migration, file
+                );
+            }
+        }
+        ("db", Some(("import", args))) => {
+            let file = args.get_one::<String>("file").expect("required");
+            let input = std::io::BufReader::new(std::fs::File::open(file)?);
+            let export: Export = serde_json::from_reader(input)?;
+            let rows = import(&db, export).await?;
+            println!("imported {} rows", rows);
+        }
+        ("seed", _) => {
+            let users = *args.get_one::<u32>("users").expect("defaulted");
+            seed(&db, users).await?;
+        }
+        _ => unreachable!("clap only accepts the commands above"),
+    }
+    Ok(())
+}
+
+fn parse_role(args: &ArgMatches) -> Role {
+    let role = args
+        .get_one::<String>("role")
+        .expect("required or defaulted");
+    Role::from_str(role).expect("checked by clap")
+}
+
+/// RFC 3339, or a date and time in UTC like `2024-05-01 12:00:00`
+fn parse_time(time: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(time) {
+        return Ok(time.to_utc());
+    }
+    ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(time, format).ok())
+        .map(|time| time.and_utc())
+        .ok_or_else(|| format!("`{}` is not a time like `2024-05-01T12:00:00Z`", time))
+}
+
+async fn backups(command: &str, args: &ArgMatches, config: &Config) -> Result<(), CliError> {
+    let url = config.database.url.as_deref().expect("validated");
+    let dir = Path::new(&config.backup.dir);
+    match command {
+        "backup" => {
+            let db_name = config.database.name.as_deref().expect("validated");
+            let db = crate::connect_database(url, db_name).await?;
+            let made = BackupService::new(db, config.backup.clone())
+                .create()
+                .await?;
+            println!(
+                "backed up the database to `{}`, {} bytes",
+                dir.join(&made.name).display(),
+                made.bytes
+            );
+        }
+        "backups" => {
+            for made in backup::list(dir).await? {
+                println!(
+                    "{}  {:>12}  {}",
+                    made.created.format("%Y-%m-%d %H:%M:%S"),
+                    made.bytes,
+                    made.name
+                );
+            }
+        }
+        _ => {
+            let live = backup::sqlite_path(url).ok_or(BackupError::Unsupported)?;
+            let file = match (
+                args.get_one::<String>("backup"),
+                args.get_one::<DateTime<Utc>>("at"),
+            ) {
+                (Some(name), _) if dir.join(name).is_file() => dir.join(name),
+                (Some(path), _) => PathBuf::from(path),
+                (None, Some(at)) => {
+                    let made = backup::list(dir).await?;
+                    let found = backup::latest_at(&made, *at).ok_or(CliError::NoBackupAt(*at))?;
+                    dir.join(&found.name)
+                }
+                (None, None) => unreachable!("clap requires one of them"),
+            };
+            let restored = backup::restore(&file, &live).await?;
+            println!(
+                "restored `{}` at {}",
+                file.display(),
+                restored.migration.as_deref().unwrap_or("no migration")
+            );
+            if restored.pending > 0 {
+                println!(
+                    "{} migrations are applied when the server starts",
+                    restored.pending
+                );
+            }
+            if let Some(previous) = restored.previous {
+                println!("the replaced database was kept as `{}`", previous.display());
+            }
+            info!("restored the database from `{}`", file.display());
+        }
+    }
+    Ok(())
+}
+
+/// Prompts on stderr when typed by hand, piped input is taken as it is
+fn read_password() -> Result<String, CliError> {
+    let stdin = std::io::stdin();
+    if stdin.is_terminal() {
+        eprint!("password: ");
+        std::io::stderr().flush()?;
+    }
+    let mut password = String::new();
+    stdin.lock().read_line(&mut password)?;
+    Ok(password.trim_end_matches(['\r', '\n']).to_owned())
+}
+
+/// Holds passwords to the rules registration does
+fn validate_user(login: &str, password: &str) -> Result<(), CliError> {
+    UserCreationData {
+        login: login.to_owned(),
+        password: password.to_owned(),
+        invite_code: None,
+        pow: None,
+    }
+    .validate()
+    .map_err(|e| CliError::Invalid(e.to_string()))
+}
+
+async fn migrate(db: &DatabaseConnection, args: &ArgMatches) -> Result<(), CliError> {
+    match args.subcommand() {
+        Some(("up", args)) => {
+            Migrator::up(db, args.get_one::<u32>("steps").copied()).await?;
+        }
+        Some(("down", args)) => {
+            let steps = args.get_one::<u32>("steps").copied().unwrap_or(1);
+            Migrator::down(db, Some(steps)).await?;
+        }
+        Some(("check", args)) => return check_schema(db, args).await,
+        _ => (),
+    }
+    let applied = Migrator::get_migration_models(db).await?;
+    for migration in Migrator::get_migration_with_status(db).await? {
+        let when = applied
+            .iter()
+            .find(|m| m.version == migration.name())
+            .and_then(|m| chrono::DateTime::from_timestamp(m.applied_at, 0));
+        match (migration.status(), when) {
+            (MigrationStatus::Applied, Some(when)) => {
+                println!(
+                    "applied {}  {}",
+                    when.format("%Y-%m-%d %H:%M:%S"),
+                    migration.name()
+                )
+            }
+            (MigrationStatus::Applied, None) => println!("applied {:19}  {}", "", migration.name()),
+            (MigrationStatus::Pending, _) => println!("pending {:19}  {}", "", migration.name()),
+        }
+    }
+    Ok(())
+}
+
+async fn check_schema(db: &DatabaseConnection, args: &ArgMatches) -> Result<(), CliError> {
+    let backend = db.get_database_backend();
+    if backend == DbBackend::MySql {
+        return Err(CliError::UnsupportedBackend(backend));
+    }
+    let mut problems = 0;
+    let mut report = |what: &str, found: Vec<String>| {
+        if found.is_empty() {
+            println!("{}: ok", what);
+        } else {
+            println!("{}:", what);
+            for problem in &found {
+                println!("  {}", problem);
+            }
+        }
+        problems += found.len();
+    };
+    let pending = Migrator::get_pending_migrations(db)
+        .await?
+        .iter()
+        .map(|m| format!("{} is pending", m.name()))
+        .collect();
+    report("migrations", pending);
+    let live = schema::read_schema(db).await?;
+    report("entities", schema::diff(&schema::entity_schema(), &live));
+    let script = args.get_one::<String>("schema").expect("defaulted");
+    match std::fs::read_to_string(script) {
+        Ok(_) if backend != DbBackend::Sqlite => {
+            println!("{}: written for SQLite, skipped", script)
+        }
+        Ok(sql) => report(
+            script,
+            schema::diff(&schema::script_schema(&sql).await?, &live),
+        ),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound && script == SCHEMA_SCRIPT => {
+            println!("{}: not found, skipped", script)
+        }
+        Err(e) => return Err(e.into()),
+    }
+    report("rollbacks", schema::check_rollbacks().await?);
+    match problems {
+        0 => Ok(()),
+        n => Err(CliError::Check(n)),
+    }
+}
+
+async fn find_user(db: &DatabaseConnection, login: &str) -> Result<login_data::Model, CliError> {
+    LoginData::find_by_id(login)
+        .one(db)
+        .await?
+        .ok_or_else(|| CliError::UserNotFound(login.to_owned()))
+}
+
+async fn create_user(
+    db: &DatabaseConnection,
+    login: &str,
+    password: &str,
+    role: Role,
+) -> Result<login_data::Model, CliError> {
+    validate_user(login, password)?;
+    if LoginData::find_by_id(login).one(db).await?.is_some() {
+        return Err(CliError::UserExists(login.to_owned()));
+    }
+    let (salt, hash) = service::hash_password(password).map_err(CliError::HashError)?;
+    let (login, data) = service::new_user(login, &salt, hash, role, None);
+    let login = db
+        .transaction::<_, login_data::Model, DbErr>(|txn| {
+            Box::pin(async move {
+                let login = login.insert(txn).await?;
+                data.insert(txn).await?;
+                Ok(login)
+            })
+        })
+        .await?;
+    info!(
+        "created {} `{}` from the command line",
+        role.as_str(),
+        login.login
+    );
+    Ok(login)
+}
+
+async fn set_password(
+    db: &DatabaseConnection,
+    login: &str,
+    password: &str,
+) -> Result<(), CliError> {
+    validate_user(login, password)?;
+    let user = find_user(db, login).await?;
+    let (salt, hash) = service::hash_password(password).map_err(CliError::HashError)?;
+    let mut model: login_data::ActiveModel = user.into();
+    model.salt = ActiveValue::Set(salt.as_str().to_owned());
+    model.hash = ActiveValue::Set(hash);
+    LoginData::update(model).exec(db).await?;
+    info!("changed the password of `{}` from the command line", login);
+    Ok(())
+}
+
+/// Every other row of the user goes with the login through `ON DELETE CASCADE`,
+/// the blobs they referred to are released afterwards
+async fn delete_user(
+    db: &DatabaseConnection,
+    store: &dyn BlobStore,
+    login: &str,
+) -> Result<(), CliError> {
+    let user = find_user(db, login).await?;
+    let id = user.user_id;
+    let mut keys = Vec::new();
+    keys.extend(
+        UserPfp::find_by_id(id)
+            .one(db)
+            .await?
+            .and_then(|p| p.blob_key),
+    );
+    keys.extend(
+        UserPfpVariants::find()
+            .filter(user_pfp_variants::Column::UserId.eq(id))
+            .all(db)
+            .await?
+            .into_iter()
+            .filter_map(|v| v.blob_key),
+    );
+    keys.extend(
+        UserData::find_by_id(id)
+            .one(db)
+            .await?
+            .and_then(|d| d.banner_key),
+    );
+    for attachment in PostAttachments::find()
+        .filter(post_attachments::Column::UserId.eq(id))
+        .all(db)
+        .await?
+    {
+        keys.push(attachment.blob_key);
+        keys.push(attachment.thumb_key);
+    }
+    LoginData::delete_by_id(login).exec(db).await?;
+    keys.sort();
+    keys.dedup();
+    for key in keys {
+        resources::release_blob(db, store, &key).await?;
+    }
+    info!("deleted `{}` from the command line", login);
+    Ok(())
+}
+
+async fn purge_grants(db: &DatabaseConnection, all: bool) -> Result<(u64, u64), CliError> {
+    let now = chrono::Utc::now();
+    let mut codes = OAuthCodes::delete_many();
+    let mut tokens = OAuthTokens::delete_many();
+    if !all {
+        codes = codes.filter(oauth_codes::Column::Expires.lt(now));
+        tokens = tokens.filter(
+            Condition::any()
+                .add(oauth_tokens::Column::Expires.lt(now))
+                .add(oauth_tokens::Column::Revoked.eq(true)),
+        );
+    }
+    let codes = codes.exec(db).await?.rows_affected;
+    let tokens = tokens.exec(db).await?.rows_affected;
+    Ok((codes, tokens))
+}
+
+#[derive(Serialize, Deserialize)]
+struct Export {
+    format: u32,
+    /// The last migration applied, an import needs the database at the same one
+    migration: String,
+    exported: chrono::DateTime<chrono::Utc>,
+    tables: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Every table, each one only refers to tables listed before it
+macro_rules! with_tables {
+    ($action:ident) => {
+        $action!(
+            login_data,
+            user_data,
+            user_pfp,
+            user_pfp_variants,
+            posts,
+            comments,
+            post_attachments,
+            oauth_clients,
+            oauth_codes,
+            oauth_tokens,
+            reports,
+            invites
+        )
+    };
+}
+
+async fn last_migration(db: &DatabaseConnection) -> Result<String, CliError> {
+    Ok(Migrator::get_applied_migrations(db)
+        .await?
+        .last()
+        .map(|m| m.name().to_owned())
+        .unwrap_or_default())
+}
+
+async fn export_table<E>(db: &DatabaseConnection) -> Result<serde_json::Value, CliError>
+where
+    E: EntityTrait,
+    E::Model: Serialize,
+{
+    Ok(serde_json::to_value(E::find().all(db).await?)?)
+}
+
+async fn export(db: &DatabaseConnection) -> Result<Export, CliError> {
+    let mut tables = serde_json::Map::new();
+    macro_rules! export_tables {
+        ($($table:ident),*) => {
+            $(
+                tables.insert(
+                    stringify!($table).to_owned(),
+                    export_table::<$table::Entity>(db).await?,
+                );
+            )*
+        };
+    }
+    with_tables!(export_tables);
+    Ok(Export {
+        format: EXPORT_FORMAT,
+        migration: last_migration(db).await?,
+        exported: chrono::Utc::now(),
+        tables,
+    })
+}
+
+async fn import_table<E, A>(
+    db: &impl ConnectionTrait,
+    name: &str,
+    rows: Option<serde_json::Value>,
+) -> Result<usize, CliError>
+where
+    E: EntityTrait,
+    E::Model: DeserializeOwned + IntoActiveModel<A> + Sync,
+    A: ActiveModelTrait<Entity = E> + Send,
+{
+    if E::find().count(db).await? > 0 {
+        return Err(CliError::NotEmpty(name.to_owned()));
+    }
+    let rows: Vec<E::Model> = match rows {
+        Some(rows) => serde_json::from_value(rows)?,
+        None => Vec::new(),
+    };
+    for chunk in rows.chunks(IMPORT_CHUNK) {
+        E::insert_many(
+            chunk
+                .iter()
+                .cloned()
+                .map(IntoActiveModel::into_active_model),
+        )
+        .exec_without_returning(db)
+        .await?;
+    }
+    Ok(rows.len())
+}
+
+/// All or nothing, a failed import leaves the database empty
+async fn import(db: &DatabaseConnection, mut export: Export) -> Result<usize, CliError> {
+    if export.format != EXPORT_FORMAT {
+        return Err(CliError::Import(format!(
+            "export format {} is not supported, expected {}",
+            export.format, EXPORT_FORMAT
+        )));
+    }
+    let migration = last_migration(db).await?;
+    if export.migration != migration {
+        return Err(CliError::Import(format!(
+            "the export was made at migration `{}` but the database is at `{}`, import it with the matching version of gog-api",
+            export.migration, migration
+        )));
+    }
+    let txn = db.begin().await?;
+    let mut rows = 0;
+    macro_rules! import_tables {
+        ($($table:ident),*) => {
+            $(
+                rows += import_table::<$table::Entity, $table::ActiveModel>(
+                    &txn,
+                    stringify!($table),
+                    export.tables.remove(stringify!($table)),
+                )
+                .await?;
+            )*
+        };
+    }
+    with_tables!(import_tables);
+    if let Some(unknown) = export.tables.keys().next() {
+        return Err(CliError::Import(format!("unknown table `{}`", unknown)));
+    }
+    txn.commit().await?;
+    Ok(rows)
+}
+
+/// Users named `seed-<n>`, each with a few posts that the next user comments on.
+/// Users that already exist are left alone, so seeding twice adds nothing
+async fn seed(db: &DatabaseConnection, users: u32) -> Result<(), CliError> {
+    let logins = (1..=users)
+        .map(|n| format!("seed-{}", n))
+        .collect::<Vec<_>>();
+    let mut created = Vec::new();
+    for login in &logins {
+        if LoginData::find_by_id(login.as_str())
+            .one(db)
+            .await?
+            .is_some()
+        {
+            continue;
+        }
+        created.push(create_user(db, login, SEED_PASSWORD, Role::User).await?);
+    }
+    db.transaction::<_, (), DbErr>(|txn| {
+        let created = created.clone();
+        Box::pin(async move {
+            for (n, user) in created.iter().enumerate() {
+                let commenter = &created[(n + 1) % created.len()];
+                for i in 1..=3 {
+                    let post_id = uuid::Uuid::new_v4();
+                    posts::ActiveModel {
+                        post_id: ActiveValue::Set(post_id),
+                        user_id: ActiveValue::Set(user.user_id),
+                        posted: ActiveValue::Set(chrono::Utc::now()),
+                        content: ActiveValue::Set(format!("Post number {} of {}", i, user.login)),
+                    }
+                    .insert(txn)
+                    .await?;
+                    comments::ActiveModel {
+                        comment_id: ActiveValue::Set(uuid::Uuid::new_v4()),
+                        post_id: ActiveValue::Set(post_id),
+                        user_id: ActiveValue::Set(commenter.user_id),
+                        posted: ActiveValue::Set(chrono::Utc::now()),
+                        content: ActiveValue::Set(format!("{} says hi", commenter.login)),
+                    }
+                    .insert(txn)
+                    .await?;
+                }
+            }
+            Ok(())
+        })
+    })
+    .await?;
+    println!(
+        "created {} users with the password `{}` and {} posts",
+        created.len(),
+        SEED_PASSWORD,
+        created.len() * 3
+    );
+    Ok(())
+}
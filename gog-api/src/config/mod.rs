@@ -0,0 +1,737 @@
+//! Settings of the server, layered from lowest to highest priority:
+//! the defaults below, a TOML file, `MAGOG_*` environment variables and command line flags.
+//! Only the last two are handled by clap, [`Config::apply_args`] lays them over the file
+
+use std::path::Path;
+
+use clap::{parser::ValueSource, ArgMatches};
+use gog_commons::{data_structures::RegistrationMode, vars::defaults};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use validator::{Validate, ValidationError, ValidationErrors};
+
+use crate::cors::{self, CorsPolicy};
+use crate::moderation::ContentKind;
+
+/// Read when it exists and no other file was given
+pub const DEFAULT_PATH: &str = "data/gog-api.toml";
+const REDACTED: &str = "<redacted>";
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("failed to read config file `{path}`: {source}")]
+    IoError {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("failed to parse config file `{path}`: {source}")]
+    ParseError {
+        path: String,
+        source: toml::de::Error,
+    },
+    #[error("invalid configuration:\n  {}", .0.join("\n  "))]
+    Invalid(Vec<String>),
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub instance: InstanceConfig,
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub frontend: FrontendConfig,
+    pub oauth: OAuthConfig,
+    pub session: SessionConfig,
+    pub registration: RegistrationConfig,
+    pub moderation: ModerationConfig,
+    pub storage: StorageConfig,
+    pub backup: BackupConfig,
+    pub resources: ResourceConfig,
+    pub limits: Limits,
+    pub cors: CorsConfig,
+    pub logging: LoggingConfig,
+}
+
+/// Shown to visitors, see `GET /instance`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InstanceConfig {
+    pub name: String,
+    pub description: String,
+}
+
+impl Default for InstanceConfig {
+    fn default() -> Self {
+        Self {
+            name: defaults::INSTANCE_NAME.to_owned(),
+            description: String::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub address: String,
+    pub port: u16,
+    /// `/api` when the frontend is served and empty otherwise
+    pub api_prefix: Option<String>,
+    /// Seconds to finish requests and background jobs once asked to stop
+    pub shutdown_timeout: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            address: defaults::BACKEND_ADDRESS.to_owned(),
+            port: defaults::BACKEND_PORT,
+            api_prefix: None,
+            shutdown_timeout: 30,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub url: Option<String>,
+    pub name: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FrontendConfig {
+    pub address: String,
+    pub port: u16,
+    /// Serve the frontend built by `trunk build` from here
+    pub dir: Option<String>,
+}
+
+impl Default for FrontendConfig {
+    fn default() -> Self {
+        Self {
+            address: defaults::FRONTEND_ADDRESS.to_owned(),
+            port: defaults::FRONTEND_PORT,
+            dir: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OAuthConfig {
+    /// The address of the server by default, the api prefix is appended when missing
+    pub issuer: Option<String>,
+    pub key_path: String,
+}
+
+impl Default for OAuthConfig {
+    fn default() -> Self {
+        Self {
+            issuer: None,
+            key_path: defaults::OAUTH_KEY_PATH.to_owned(),
+        }
+    }
+}
+
+/// Lifetimes of signed in sessions, in seconds
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionConfig {
+    pub ttl: u64,
+    /// Added to the lifetime on every request of the session
+    pub extension: u64,
+    /// How often expired sessions are dropped
+    pub cleaning_interval: u64,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            ttl: 600,
+            extension: 300,
+            cleaning_interval: 600,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RegistrationConfig {
+    pub mode: RegistrationMode,
+    pub pow_difficulty: u8,
+    pub bootstrap_admin: Option<String>,
+}
+
+impl Default for RegistrationConfig {
+    fn default() -> Self {
+        Self {
+            mode: RegistrationMode::default(),
+            pow_difficulty: defaults::POW_DIFFICULTY,
+            bootstrap_admin: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModerationConfig {
+    pub config: String,
+}
+
+impl Default for ModerationConfig {
+    fn default() -> Self {
+        Self {
+            config: defaults::MODERATION_CONFIG.to_owned(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    /// `local` or `s3`
+    pub store: String,
+    pub dir: String,
+    pub s3: S3Config,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            store: "local".to_owned(),
+            dir: defaults::BLOB_DIR.to_owned(),
+            s3: S3Config::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct S3Config {
+    pub bucket: Option<String>,
+    pub endpoint: Option<String>,
+    pub region: String,
+    pub access_key: Option<String>,
+    pub secret_key: Option<String>,
+}
+
+impl Default for S3Config {
+    fn default() -> Self {
+        Self {
+            bucket: None,
+            endpoint: None,
+            region: defaults::S3_REGION.to_owned(),
+            access_key: None,
+            secret_key: None,
+        }
+    }
+}
+
+/// Copies of an SQLite database, see [`crate::backup`]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackupConfig {
+    pub dir: String,
+    /// Seconds between scheduled backups, none are made on a schedule when unset
+    pub interval: Option<u64>,
+    /// How many backups to keep, older ones are deleted after each new one
+    pub keep: usize,
+    /// Whether backups are gzipped
+    pub compress: bool,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            dir: "data/backups".to_owned(),
+            interval: None,
+            keep: 7,
+            compress: true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ResourceConfig {
+    /// Shown for users that never uploaded a profile picture
+    pub default_pfp: String,
+    pub cache_max_bytes: usize,
+    /// Seconds until cached files are read again, by default they are whenever they change
+    pub cache_ttl: Option<u64>,
+}
+
+impl Default for ResourceConfig {
+    fn default() -> Self {
+        Self {
+            default_pfp: "data/default_pfp.jpeg".to_owned(),
+            cache_max_bytes: defaults::RESOURCE_CACHE_MAX_BYTES,
+            cache_ttl: None,
+        }
+    }
+}
+
+/// Sizes of what users may send, lengths count characters
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    /// Uploads are re-encoded, so this only bounds what we are willing to decode
+    pub pfp_bytes: usize,
+    pub banner_bytes: usize,
+    pub attachment_bytes: usize,
+    pub post_length: usize,
+    pub comment_length: usize,
+    pub require_alt_text: bool,
+    /// Posts returned by `posts/filter` when it does not ask for a number
+    pub posts_page: u64,
+    /// The most posts a single request may ask for
+    pub posts_page_max: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            pfp_bytes: defaults::PFP_BYTES,
+            banner_bytes: defaults::BANNER_BYTES,
+            attachment_bytes: defaults::ATTACHMENT_BYTES,
+            post_length: defaults::POST_LENGTH,
+            comment_length: defaults::COMMENT_LENGTH,
+            require_alt_text: false,
+            posts_page: 20,
+            posts_page_max: 100,
+        }
+    }
+}
+
+impl Limits {
+    /// `data.validate()`, plus the length of `content` checked against the configured maximum
+    pub fn validate_content(
+        &self,
+        kind: ContentKind,
+        content: &str,
+        data: &impl Validate,
+    ) -> Result<(), ValidationErrors> {
+        let mut errors = data.validate().err().unwrap_or_default();
+        let (max, message) = match kind {
+            ContentKind::Post => (self.post_length, "post content of disallowed size"),
+            ContentKind::Comment => (self.comment_length, "comment content of disallowed size"),
+        };
+        if content.chars().count() > max {
+            let mut error = ValidationError::new("length").with_message(message.into());
+            error.add_param("max".into(), &max);
+            errors.add("content", error);
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+    /// How many posts to load when `requested` were asked for
+    pub fn posts_page(&self, requested: Option<u64>) -> u64 {
+        requested
+            .unwrap_or(self.posts_page)
+            .min(self.posts_page_max)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    /// The frontend address when empty
+    pub origins: Vec<String>,
+    pub origin_patterns: Vec<String>,
+    pub methods: Vec<String>,
+    pub headers: Vec<String>,
+    pub credentials: bool,
+    /// Seconds browsers may cache a preflight response
+    pub max_age: usize,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        let list = |l: &str| l.split(',').map(str::to_owned).collect();
+        Self {
+            origins: vec![],
+            origin_patterns: vec![],
+            methods: list(cors::DEFAULT_METHODS),
+            headers: list(cors::DEFAULT_HEADERS),
+            credentials: true,
+            max_age: cors::DEFAULT_MAX_AGE,
+        }
+    }
+}
+
+/// Where logs and traces go, see [`crate::telemetry`]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// `text` or `json`
+    pub format: String,
+    /// Which events to log when `RUST_LOG` is not set, in the same syntax
+    pub filter: String,
+    /// OTLP/HTTP address traces are sent to, like `http://localhost:4318/v1/traces`.
+    /// Nothing is exported when unset
+    pub otlp_endpoint: Option<String>,
+    pub service_name: String,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            format: "text".to_owned(),
+            filter: "debug".to_owned(),
+            otlp_endpoint: None,
+            service_name: "gog-api".to_owned(),
+        }
+    }
+}
+
+/// The value of `id` when it was set on the command line or in the environment, clap defaults are
+/// left out so they do not override the file
+fn given<T: Clone + Send + Sync + 'static>(args: &ArgMatches, id: &str) -> Option<T> {
+    match args.value_source(id) {
+        Some(ValueSource::CommandLine | ValueSource::EnvVariable) => args.get_one::<T>(id).cloned(),
+        _ => None,
+    }
+}
+
+fn given_many(args: &ArgMatches, id: &str) -> Option<Vec<String>> {
+    match args.value_source(id) {
+        Some(ValueSource::CommandLine | ValueSource::EnvVariable) => args
+            .get_many::<String>(id)
+            .map(|values| values.cloned().collect()),
+        _ => None,
+    }
+}
+
+impl Config {
+    /// Reads `path`, or [`DEFAULT_PATH`] when none is given and it exists
+    pub fn load(path: Option<&str>) -> Result<Self, ConfigError> {
+        let path = match path {
+            Some(path) => path,
+            None if Path::new(DEFAULT_PATH).is_file() => DEFAULT_PATH,
+            None => return Ok(Self::default()),
+        };
+        let text = std::fs::read_to_string(path).map_err(|source| ConfigError::IoError {
+            path: path.to_owned(),
+            source,
+        })?;
+        toml::from_str(&text).map_err(|source| ConfigError::ParseError {
+            path: path.to_owned(),
+            source,
+        })
+    }
+    /// Lays the flags and `MAGOG_*` variables given to clap over the file
+    pub fn apply_args(&mut self, args: &ArgMatches) {
+        macro_rules! apply {
+            ($($id:literal => $field:expr),* $(,)?) => {
+                $(if let Some(value) = given(args, $id) {
+                    $field = value;
+                })*
+            };
+        }
+        macro_rules! apply_some {
+            ($($id:literal => $field:expr),* $(,)?) => {
+                $(if let Some(value) = given(args, $id) {
+                    $field = Some(value);
+                })*
+            };
+        }
+        apply! {
+            "address" => self.server.address,
+            "port" => self.server.port,
+            "shutdown_timeout" => self.server.shutdown_timeout,
+            "frontend_address" => self.frontend.address,
+            "frontend_port" => self.frontend.port,
+            "oauth_key_path" => self.oauth.key_path,
+            "pow_difficulty" => self.registration.pow_difficulty,
+            "moderation_config" => self.moderation.config,
+            "blob_store" => self.storage.store,
+            "blob_dir" => self.storage.dir,
+            "s3_region" => self.storage.s3.region,
+            "resource_cache_max_bytes" => self.resources.cache_max_bytes,
+            "require_alt_text" => self.limits.require_alt_text,
+            "cors_credentials" => self.cors.credentials,
+            "cors_max_age" => self.cors.max_age,
+            "log_format" => self.logging.format,
+        }
+        apply_some! {
+            "api_prefix" => self.server.api_prefix,
+            "db" => self.database.url,
+            "db_name" => self.database.name,
+            "frontend_dir" => self.frontend.dir,
+            "oauth_issuer" => self.oauth.issuer,
+            "bootstrap_admin" => self.registration.bootstrap_admin,
+            "s3_bucket" => self.storage.s3.bucket,
+            "s3_endpoint" => self.storage.s3.endpoint,
+            "s3_access_key" => self.storage.s3.access_key,
+            "s3_secret_key" => self.storage.s3.secret_key,
+            "resource_cache_ttl" => self.resources.cache_ttl,
+            "otlp_endpoint" => self.logging.otlp_endpoint,
+        }
+        if let Some(mode) = given::<String>(args, "registration_mode") {
+            self.registration.mode = mode.parse().expect("validated by clap");
+        }
+        for (id, field) in [
+            ("cors_origins", &mut self.cors.origins),
+            ("cors_origin_patterns", &mut self.cors.origin_patterns),
+            ("cors_methods", &mut self.cors.methods),
+            ("cors_headers", &mut self.cors.headers),
+        ] {
+            if let Some(values) = given_many(args, id) {
+                *field = values;
+            }
+        }
+    }
+    /// Where the frontend is reachable, the api's own origin when it serves the frontend
+    pub fn frontend_origin(&self) -> String {
+        if self.frontend.dir.is_some() {
+            let issuer = self.issuer();
+            let prefix = self.api_prefix();
+            issuer.strip_suffix(&prefix).unwrap_or(&issuer).to_owned()
+        } else {
+            format!("http://{}:{}", self.frontend.address, self.frontend.port)
+        }
+    }
+    /// The api's url with its prefix, the discovery document and `/oauth` are below it
+    pub fn issuer(&self) -> String {
+        let issuer = self
+            .oauth
+            .issuer
+            .clone()
+            .unwrap_or_else(|| format!("http://{}:{}", self.server.address, self.server.port));
+        let issuer = issuer.trim_end_matches('/');
+        let prefix = self.api_prefix();
+        if issuer.ends_with(&prefix) {
+            issuer.to_owned()
+        } else {
+            format!("{}{}", issuer, prefix)
+        }
+    }
+    /// Normalized, see [`crate::frontend::normalize_prefix`]
+    pub fn api_prefix(&self) -> String {
+        let default = if self.frontend.dir.is_some() {
+            defaults::API_PREFIX
+        } else {
+            ""
+        };
+        crate::frontend::normalize_prefix(self.server.api_prefix.as_deref().unwrap_or(default))
+    }
+    pub fn cors_policy(&self) -> Result<CorsPolicy, Vec<String>> {
+        fn parse_all<T>(
+            field: &str,
+            values: &[String],
+            parse: fn(&str) -> Result<T, String>,
+            errors: &mut Vec<String>,
+        ) -> Vec<T> {
+            values
+                .iter()
+                .filter_map(|v| {
+                    parse(v)
+                        .map_err(|e| errors.push(format!("cors.{}: {}", field, e)))
+                        .ok()
+                })
+                .collect()
+        }
+        let mut errors = vec![];
+        let origins = if self.cors.origins.is_empty() {
+            vec![self.frontend_origin()]
+        } else {
+            parse_all(
+                "origins",
+                &self.cors.origins,
+                cors::parse_origin,
+                &mut errors,
+            )
+        };
+        let policy = CorsPolicy {
+            origins,
+            patterns: parse_all(
+                "origin_patterns",
+                &self.cors.origin_patterns,
+                cors::parse_origin_pattern,
+                &mut errors,
+            ),
+            methods: parse_all(
+                "methods",
+                &self.cors.methods,
+                cors::parse_method,
+                &mut errors,
+            ),
+            headers: parse_all(
+                "headers",
+                &self.cors.headers,
+                cors::parse_header,
+                &mut errors,
+            ),
+            credentials: self.cors.credentials,
+            max_age: Some(self.cors.max_age),
+        };
+        if errors.is_empty() {
+            Ok(policy)
+        } else {
+            Err(errors)
+        }
+    }
+    /// Everything wrong with the configuration at once, so it can be fixed in one go
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut errors = vec![];
+        let mut check = |ok: bool, message: &str| {
+            if !ok {
+                errors.push(message.to_owned());
+            }
+        };
+        check(
+            self.database.url.is_some(),
+            "database.url is required, set it in the file, with `--database-url` or `MAGOG_BACKEND_DB_URL`",
+        );
+        check(
+            self.database.name.is_some(),
+            "database.name is required, set it in the file, with `--database-name` or `MAGOG_BACKEND_DB_NAME`",
+        );
+        check(!self.server.address.is_empty(), "server.address is empty");
+        check(
+            !self.instance.name.trim().is_empty(),
+            "instance.name is empty",
+        );
+        check(
+            self.registration.pow_difficulty <= 32,
+            "registration.pow_difficulty must be at most 32",
+        );
+        check(self.session.ttl > 0, "session.ttl must be positive");
+        check(
+            self.session.cleaning_interval > 0,
+            "session.cleaning_interval must be positive",
+        );
+        check(self.backup.keep > 0, "backup.keep must be positive");
+        check(
+            self.backup.interval != Some(0),
+            "backup.interval must be positive",
+        );
+        check(
+            Path::new(&self.resources.default_pfp).is_file(),
+            &format!(
+                "resources.default_pfp: `{}` is not a file",
+                self.resources.default_pfp
+            ),
+        );
+        for (name, value) in [
+            ("limits.pfp_bytes", self.limits.pfp_bytes),
+            ("limits.banner_bytes", self.limits.banner_bytes),
+            ("limits.attachment_bytes", self.limits.attachment_bytes),
+            ("limits.post_length", self.limits.post_length),
+            ("limits.comment_length", self.limits.comment_length),
+        ] {
+            check(value > 0, &format!("{} must be positive", name));
+        }
+        check(
+            self.limits.posts_page > 0 && self.limits.posts_page <= self.limits.posts_page_max,
+            "limits.posts_page must be between 1 and limits.posts_page_max",
+        );
+        if let Some(dir) = &self.frontend.dir {
+            check(
+                Path::new(dir).join("index.html").is_file(),
+                &format!("frontend.dir: `{}` has no index.html", dir),
+            );
+        }
+        match self.storage.store.as_str() {
+            "local" => (),
+            "s3" => {
+                let s3 = &self.storage.s3;
+                check(
+                    s3.bucket.is_some(),
+                    "storage.s3.bucket is required for s3 storage",
+                );
+                check(
+                    s3.access_key.is_some(),
+                    "storage.s3.access_key is required for s3 storage",
+                );
+                check(
+                    s3.secret_key.is_some(),
+                    "storage.s3.secret_key is required for s3 storage",
+                );
+            }
+            other => check(
+                false,
+                &format!("storage.store must be `local` or `s3`, not `{}`", other),
+            ),
+        }
+        check(
+            matches!(self.logging.format.as_str(), "text" | "json"),
+            &format!(
+                "logging.format must be `text` or `json`, not `{}`",
+                self.logging.format
+            ),
+        );
+        check(
+            tracing_subscriber::EnvFilter::try_new(&self.logging.filter).is_ok(),
+            &format!(
+                "logging.filter: `{}` is not a valid filter",
+                self.logging.filter
+            ),
+        );
+        if let Some(endpoint) = &self.logging.otlp_endpoint {
+            check(
+                url::Url::parse(endpoint)
+                    .map_or(false, |url| matches!(url.scheme(), "http" | "https")),
+                &format!(
+                    "logging.otlp_endpoint: `{}` is not an http or https url",
+                    endpoint
+                ),
+            );
+        }
+        if let Err(cors_errors) = self.cors_policy() {
+            errors.extend(cors_errors);
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(errors))
+        }
+    }
+    /// A copy that is safe to print, without keys or passwords
+    pub fn redacted(&self) -> Self {
+        let mut config = self.clone();
+        let s3 = &mut config.storage.s3;
+        for secret in [&mut s3.access_key, &mut s3.secret_key] {
+            if secret.is_some() {
+                *secret = Some(REDACTED.to_owned());
+            }
+        }
+        if let Some(url) = &mut config.database.url {
+            if let Ok(mut parsed) = url::Url::parse(url) {
+                if parsed.password().is_some() && parsed.set_password(Some("redacted")).is_ok() {
+                    *url = parsed.to_string();
+                }
+            }
+        }
+        config
+    }
+    pub fn to_toml(&self) -> String {
+        toml::to_string_pretty(self).expect("config serializes to toml")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oauth_urls_follow_the_served_frontend() {
+        let mut config = Config::default();
+        let server = format!("http://{}:{}", config.server.address, config.server.port);
+        assert_eq!(config.issuer(), server);
+
+        config.frontend.dir = Some("dist".to_owned());
+        assert_eq!(config.issuer(), format!("{}/api", server));
+        assert_eq!(config.frontend_origin(), server);
+
+        config.oauth.issuer = Some("https://gog.example.com/".to_owned());
+        assert_eq!(config.issuer(), "https://gog.example.com/api");
+        assert_eq!(config.frontend_origin(), "https://gog.example.com");
+        config.oauth.issuer = Some("https://gog.example.com/api".to_owned());
+        assert_eq!(config.issuer(), "https://gog.example.com/api");
+    }
+}
@@ -0,0 +1,228 @@
+//! Which origins may call the api from a browser, and with what.
+//! Requests from any other origin are refused outright instead of just missing the CORS headers,
+//! the session cookie makes every allowed origin able to act as the signed in user
+
+use std::str::FromStr;
+
+use actix_cors::Cors;
+use actix_web::{
+    dev::RequestHead,
+    http::{
+        header::{self, HeaderName},
+        Method,
+    },
+};
+use regex::Regex;
+
+pub const DEFAULT_METHODS: &str = "GET,POST,DELETE";
+pub const DEFAULT_HEADERS: &str = "Content-Type,Authorization,X-CSRF-Token";
+/// How long a browser may reuse a preflight response, in seconds
+pub const DEFAULT_MAX_AGE: usize = 60 * 60;
+
+#[derive(Clone, Debug)]
+pub struct CorsPolicy {
+    /// Origins allowed as they are, like `https://example.com`
+    pub origins: Vec<String>,
+    /// Origins allowed by [`parse_origin_pattern`]
+    pub patterns: Vec<Regex>,
+    pub methods: Vec<Method>,
+    pub headers: Vec<HeaderName>,
+    /// Whether browsers send the session cookie along
+    pub credentials: bool,
+    pub max_age: Option<usize>,
+}
+
+impl CorsPolicy {
+    pub fn allows(&self, origin: &str) -> bool {
+        self.origins.iter().any(|o| o == origin) || self.patterns.iter().any(|p| p.is_match(origin))
+    }
+    pub fn build(&self) -> Cors {
+        let policy = self.clone();
+        let mut cors = Cors::default()
+            .allowed_origin_fn(move |origin, req| {
+                origin
+                    .to_str()
+                    .map_or(false, |o| policy.allows(o) || same_origin(o, req))
+            })
+            .allowed_methods(self.methods.clone())
+            .allowed_headers(self.headers.clone())
+            .expose_headers([crate::csrf::HEADER, crate::telemetry::REQUEST_ID_HEADER])
+            .max_age(self.max_age)
+            .block_on_origin_mismatch(true);
+        if self.credentials {
+            cors = cors.supports_credentials();
+        }
+        cors
+    }
+}
+
+/// The frontend served by the api itself sends its origin along with every POST,
+/// that origin is the `Host` the request went to
+fn same_origin(origin: &str, req: &RequestHead) -> bool {
+    let Some(host) = req
+        .headers()
+        .get(header::HOST)
+        .and_then(|h| h.to_str().ok())
+    else {
+        return false;
+    };
+    origin
+        .split_once("://")
+        .map_or(false, |(_, authority)| authority.eq_ignore_ascii_case(host))
+}
+
+/// Trims the trailing slash an origin copied from the address bar tends to have
+pub fn parse_origin(origin: &str) -> Result<String, String> {
+    let origin = origin.trim().trim_end_matches('/');
+    match origin.split_once("://") {
+        Some(("http" | "https", authority))
+            if !authority.is_empty() && !authority.contains('/') =>
+        {
+            Ok(origin.to_owned())
+        }
+        _ => Err(format!(
+            "`{}` is not an origin like `https://example.com`",
+            origin
+        )),
+    }
+}
+
+/// An origin where `*` stands for any number of subdomain labels, like `https://*.example.com`.
+/// It never matches across the scheme or the port
+pub fn parse_origin_pattern(pattern: &str) -> Result<Regex, String> {
+    let pattern = parse_origin(pattern)?;
+    let regex = regex::escape(&pattern).replace(r"\*", "[a-zA-Z0-9-]+(\\.[a-zA-Z0-9-]+)*");
+    Regex::new(&format!("^{}$", regex)).map_err(|e| e.to_string())
+}
+
+pub fn parse_method(method: &str) -> Result<Method, String> {
+    Method::from_str(&method.trim().to_ascii_uppercase()).map_err(|e| e.to_string())
+}
+
+pub fn parse_header(name: &str) -> Result<HeaderName, String> {
+    HeaderName::from_str(name.trim()).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{
+        dev::Service,
+        test::{self, TestRequest},
+        web, App, HttpResponse,
+    };
+
+    fn policy() -> CorsPolicy {
+        CorsPolicy {
+            origins: vec!["https://app.example.com".to_owned()],
+            patterns: vec![parse_origin_pattern("https://*.example.org").unwrap()],
+            methods: vec![Method::GET, Method::POST],
+            headers: vec![header::CONTENT_TYPE],
+            credentials: true,
+            max_age: None,
+        }
+    }
+
+    /// A cookie carrying POST from `origin`, as a browser sends it
+    fn post_from(origin: &str) -> TestRequest {
+        TestRequest::post()
+            .uri("/")
+            .insert_header((header::HOST, "api.example.com"))
+            .insert_header((header::ORIGIN, origin))
+            .insert_header((header::COOKIE, "id=session"))
+    }
+
+    #[actix_web::test]
+    async fn allowed_origin_gets_credentials() {
+        let app = test::init_service(
+            App::new()
+                .wrap(policy().build())
+                .route("/", web::post().to(HttpResponse::Ok)),
+        )
+        .await;
+        for origin in ["https://app.example.com", "https://a.b.example.org"] {
+            let res = test::call_service(&app, post_from(origin).to_request()).await;
+            assert!(res.status().is_success());
+            let headers = res.headers();
+            assert_eq!(
+                headers.get(header::ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(),
+                origin
+            );
+            assert_eq!(
+                headers
+                    .get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS)
+                    .unwrap(),
+                "true"
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn unknown_origin_is_rejected() {
+        let app = test::init_service(
+            App::new()
+                .wrap(policy().build())
+                .route("/", web::post().to(HttpResponse::Ok)),
+        )
+        .await;
+        for origin in [
+            "https://evil.example.net",
+            "https://app.example.com.evil.net",
+            "http://app.example.com",
+            "https://example.org",
+        ] {
+            let status = match app.call(post_from(origin).to_request()).await {
+                Ok(res) => res.status(),
+                Err(e) => e.as_response_error().status_code(),
+            };
+            assert_eq!(status, 400, "{} was let through", origin);
+        }
+        let preflight = TestRequest::default()
+            .method(Method::OPTIONS)
+            .uri("/")
+            .insert_header((header::ORIGIN, "https://evil.example.net"))
+            .insert_header((header::ACCESS_CONTROL_REQUEST_METHOD, "POST"))
+            .to_request();
+        match app.call(preflight).await {
+            Ok(res) => {
+                assert!(!res.status().is_success());
+                assert!(!res
+                    .headers()
+                    .contains_key(header::ACCESS_CONTROL_ALLOW_CREDENTIALS));
+            }
+            Err(e) => assert!(!e.error_response().status().is_success()),
+        }
+    }
+
+    #[test]
+    fn patterns_match_subdomains_only() {
+        let pattern = parse_origin_pattern("https://*.example.org/").unwrap();
+        assert!(pattern.is_match("https://a.example.org"));
+        assert!(pattern.is_match("https://a-1.b.example.org"));
+        for origin in [
+            "https://example.org",
+            "http://a.example.org",
+            "https://a.example.org:8443",
+            "https://aexample.org",
+            "https://a.example.org.evil.net",
+            "https://evil.net/.example.org",
+        ] {
+            assert!(!pattern.is_match(origin), "{} matched", origin);
+        }
+        assert!(parse_origin_pattern("*.example.org").is_err());
+        assert!(parse_origin_pattern("https://*.example.org/path").is_err());
+    }
+
+    #[test]
+    fn same_origin_compares_with_host() {
+        let req = TestRequest::default()
+            .insert_header((header::HOST, "Gog.Example.com:8081"))
+            .to_srv_request();
+        assert!(same_origin("https://gog.example.com:8081", req.head()));
+        assert!(!same_origin("https://gog.example.com", req.head()));
+        assert!(!same_origin("https://evil.example.com:8081", req.head()));
+        assert!(!same_origin("gog.example.com:8081", req.head()));
+        let req = TestRequest::default().to_srv_request();
+        assert!(!same_origin("https://gog.example.com", req.head()));
+    }
+}
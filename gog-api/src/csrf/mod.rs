@@ -0,0 +1,159 @@
+//! Synchronizer tokens for requests authenticated by the session cookie.
+//! A token is issued at login and kept in the session, every request that changes state
+//! has to echo it back in [`HEADER`]. Other sites can make the browser send the cookie, but cannot
+//! read the token or set the header
+
+use actix_session::{Session, SessionExt, SessionInsertError};
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::Method,
+    middleware::Next,
+    web::Data,
+    Error,
+};
+
+use crate::errors::CsrfError;
+use crate::service::{bearer_token, validate_bearer, DbConnection, SESSION_ID};
+
+pub const HEADER: &str = "X-CSRF-Token";
+const SESSION_KEY: &str = "csrf";
+
+/// Replaces the token of the session, done on every login
+pub fn issue(session: &Session) -> Result<String, SessionInsertError> {
+    let token = crate::oauth::random_token();
+    session.insert(SESSION_KEY, &token)?;
+    Ok(token)
+}
+
+/// The token of the session, a new one when it has none yet
+pub fn get_or_issue(session: &Session) -> Result<String, SessionInsertError> {
+    match session.get::<String>(SESSION_KEY) {
+        Ok(Some(token)) => Ok(token),
+        _ => issue(session),
+    }
+}
+
+pub fn forget(session: &Session) {
+    session.remove(SESSION_KEY);
+}
+
+/// Compares in constant time, so the token cannot be guessed byte by byte
+fn tokens_equal(expected: &str, given: &str) -> bool {
+    expected.len() == given.len()
+        && expected
+            .bytes()
+            .zip(given.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Whether the request carries a valid access token, the mere header proves nothing
+async fn bearer_authenticated(req: &ServiceRequest) -> bool {
+    if bearer_token(req.request()).is_none() {
+        return false;
+    }
+    let Some(db) = req.app_data::<Data<DbConnection>>() else {
+        return false;
+    };
+    validate_bearer(req.request(), db).await.is_ok()
+}
+
+/// Refuses state changing requests of a signed in session that do not carry its token.
+/// Requests with a valid bearer token and no session cookie are let through, browsers never
+/// attach those on their own and CORS keeps other sites from adding the header
+pub async fn protect(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let safe = matches!(
+        *req.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE
+    );
+    if safe {
+        return next.call(req).await;
+    }
+    let session = req.get_session();
+    if session.entries().is_empty() && bearer_authenticated(&req).await {
+        return next.call(req).await;
+    }
+    let signed_in = matches!(session.get::<String>(SESSION_ID), Ok(Some(_)));
+    if signed_in {
+        let Ok(Some(expected)) = session.get::<String>(SESSION_KEY) else {
+            return Err(CsrfError::Missing.into());
+        };
+        let given = req.headers().get(HEADER).and_then(|h| h.to_str().ok());
+        match given {
+            None => return Err(CsrfError::Missing.into()),
+            Some(given) if !tokens_equal(&expected, given) => {
+                log::warn!("csrf token mismatch on {} {}", req.method(), req.path());
+                return Err(CsrfError::Mismatch.into());
+            }
+            Some(_) => (),
+        }
+    }
+    next.call(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+    use actix_web::{
+        cookie::{Cookie, Key},
+        dev::Service,
+        http::{header, StatusCode},
+        middleware::from_fn,
+        test::{self, TestRequest},
+        web, App, HttpResponse,
+    };
+
+    /// Signs in like the login handler does and answers with the CSRF token
+    async fn login(session: Session) -> HttpResponse {
+        session.insert(SESSION_ID, "session").unwrap();
+        HttpResponse::Ok().body(issue(&session).unwrap())
+    }
+
+    fn status_of<B>(result: Result<ServiceResponse<B>, Error>) -> StatusCode {
+        match result {
+            Ok(resp) => resp.status(),
+            Err(e) => e.error_response().status(),
+        }
+    }
+
+    #[actix_web::test]
+    async fn bogus_bearer_does_not_skip_the_session_check() {
+        let app = test::init_service(
+            App::new()
+                .route("/login", web::post().to(login))
+                .route("/change", web::post().to(HttpResponse::Ok))
+                .wrap(from_fn(protect))
+                .wrap(SessionMiddleware::new(
+                    CookieSessionStore::default(),
+                    Key::generate(),
+                )),
+        )
+        .await;
+        let resp = test::call_service(&app, TestRequest::post().uri("/login").to_request()).await;
+        let cookie: Cookie<'static> = resp.response().cookies().next().unwrap().into_owned();
+        let token = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+
+        let bogus = TestRequest::post()
+            .uri("/change")
+            .cookie(cookie.clone())
+            .insert_header((header::AUTHORIZATION, "Bearer bogus"));
+        assert_eq!(
+            status_of(app.call(bogus.to_request()).await),
+            StatusCode::FORBIDDEN
+        );
+
+        let with_token = TestRequest::post()
+            .uri("/change")
+            .cookie(cookie)
+            .insert_header((HEADER, token));
+        assert_eq!(
+            status_of(app.call(with_token.to_request()).await),
+            StatusCode::OK
+        );
+    }
+}
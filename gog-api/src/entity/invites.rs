@@ -0,0 +1,52 @@
+use gog_commons::data_structures::InviteData;
+use sea_orm::entity::prelude::*;
+
+/// Registration invites, a code stays usable until it expires or runs out of uses
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, serde::Serialize, serde::Deserialize)]
+#[sea_orm(table_name = "invites")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub code: String,
+    pub created_by: Uuid,
+    pub created: DateTimeUtc,
+    pub expires: Option<DateTimeUtc>,
+    pub max_uses: i32,
+    pub uses: i32,
+}
+
+impl Model {
+    pub fn is_usable(&self) -> bool {
+        self.uses < self.max_uses && self.expires.map_or(true, |e| e > chrono::Utc::now())
+    }
+    pub fn into_invite_data(self, created_by: String) -> InviteData {
+        InviteData {
+            usable: self.is_usable(),
+            code: self.code,
+            created_by,
+            created: self.created,
+            expires: self.expires,
+            max_uses: self.max_uses,
+            uses: self.uses,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::login_data::Entity",
+        from = "Column::CreatedBy",
+        to = "super::login_data::Column::UserId",
+        on_update = "NoAction",
+        on_delete = "Cascade"
+    )]
+    LoginData,
+}
+
+impl Related<super::login_data::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::LoginData.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -2,7 +2,7 @@
 
 use sea_orm::entity::prelude::*;
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, serde::Serialize, serde::Deserialize)]
 #[sea_orm(table_name = "login_data")]
 pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
@@ -11,6 +11,17 @@ pub struct Model {
     pub user_id: Uuid,
     pub salt: String,
     pub hash: String,
+    pub role: String,
+    pub suspended: bool,
+    pub invited_by: Option<Uuid>,
+    /// Changes with every uploaded profile picture, `None` until the first one
+    pub pfp_version: Option<String>,
+}
+
+impl Model {
+    pub fn role(&self) -> gog_commons::data_structures::Role {
+        self.role.parse().unwrap_or_default()
+    }
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -3,7 +3,14 @@
 pub mod prelude;
 
 pub mod comments;
+pub mod invites;
 pub mod login_data;
+pub mod oauth_clients;
+pub mod oauth_codes;
+pub mod oauth_tokens;
+pub mod post_attachments;
 pub mod posts;
+pub mod reports;
 pub mod user_data;
 pub mod user_pfp;
+pub mod user_pfp_variants;
@@ -0,0 +1,40 @@
+use sea_orm::entity::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, serde::Serialize, serde::Deserialize)]
+#[sea_orm(table_name = "oauth_clients")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub client_id: Uuid,
+    pub owner_id: Uuid,
+    pub name: String,
+    pub secret_hash: Option<String>,
+    /// Newline separated list of allowed redirect uris
+    pub redirect_uris: String,
+    pub created: DateTimeUtc,
+}
+
+impl Model {
+    pub fn allows_redirect(&self, uri: &str) -> bool {
+        self.redirect_uris.lines().any(|r| r == uri)
+    }
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::login_data::Entity",
+        from = "Column::OwnerId",
+        to = "super::login_data::Column::UserId",
+        on_update = "NoAction",
+        on_delete = "Cascade"
+    )]
+    LoginData,
+}
+
+impl Related<super::login_data::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::LoginData.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,49 @@
+use sea_orm::entity::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, serde::Serialize, serde::Deserialize)]
+#[sea_orm(table_name = "oauth_codes")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub code_hash: String,
+    pub client_id: Uuid,
+    pub user_id: Uuid,
+    pub redirect_uri: String,
+    pub scope: String,
+    pub code_challenge: String,
+    pub nonce: Option<String>,
+    pub expires: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::oauth_clients::Entity",
+        from = "Column::ClientId",
+        to = "super::oauth_clients::Column::ClientId",
+        on_update = "NoAction",
+        on_delete = "Cascade"
+    )]
+    OAuthClients,
+    #[sea_orm(
+        belongs_to = "super::login_data::Entity",
+        from = "Column::UserId",
+        to = "super::login_data::Column::UserId",
+        on_update = "NoAction",
+        on_delete = "Cascade"
+    )]
+    LoginData,
+}
+
+impl Related<super::oauth_clients::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::OAuthClients.def()
+    }
+}
+
+impl Related<super::login_data::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::LoginData.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
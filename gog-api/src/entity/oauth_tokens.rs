@@ -0,0 +1,58 @@
+use sea_orm::entity::prelude::*;
+
+pub const KIND_ACCESS: &str = "access";
+pub const KIND_REFRESH: &str = "refresh";
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, serde::Serialize, serde::Deserialize)]
+#[sea_orm(table_name = "oauth_tokens")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub token_hash: String,
+    /// Either [`KIND_ACCESS`] or [`KIND_REFRESH`]
+    pub kind: String,
+    pub client_id: Uuid,
+    pub user_id: Uuid,
+    pub scope: String,
+    pub expires: DateTimeUtc,
+    pub revoked: bool,
+}
+
+impl Model {
+    pub fn is_valid(&self) -> bool {
+        !self.revoked && chrono::Utc::now() < self.expires
+    }
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::oauth_clients::Entity",
+        from = "Column::ClientId",
+        to = "super::oauth_clients::Column::ClientId",
+        on_update = "NoAction",
+        on_delete = "Cascade"
+    )]
+    OAuthClients,
+    #[sea_orm(
+        belongs_to = "super::login_data::Entity",
+        from = "Column::UserId",
+        to = "super::login_data::Column::UserId",
+        on_update = "NoAction",
+        on_delete = "Cascade"
+    )]
+    LoginData,
+}
+
+impl Related<super::oauth_clients::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::OAuthClients.def()
+    }
+}
+
+impl Related<super::login_data::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::LoginData.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,55 @@
+use gog_commons::data_structures::AttachmentData;
+use sea_orm::entity::prelude::*;
+
+/// Images uploaded for a post, the bytes of the image and its thumbnail live in the blob store
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, serde::Serialize, serde::Deserialize)]
+#[sea_orm(table_name = "post_attachments")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub attachment_id: Uuid,
+    pub user_id: Uuid,
+    /// `None` while the upload is not part of a post yet
+    pub post_id: Option<Uuid>,
+    pub position: Option<i32>,
+    pub alt_text: Option<String>,
+    pub content_type: String,
+    pub width: i32,
+    pub height: i32,
+    pub blob_key: String,
+    pub thumb_content_type: String,
+    pub thumb_key: String,
+    pub uploaded: DateTimeUtc,
+}
+
+impl Model {
+    pub fn into_attachment_data(self) -> AttachmentData {
+        AttachmentData {
+            attachment_id: self.attachment_id,
+            alt_text: self.alt_text,
+            width: self.width as u32,
+            height: self.height as u32,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::login_data::Entity",
+        from = "Column::UserId",
+        to = "super::login_data::Column::UserId",
+        on_update = "NoAction",
+        on_delete = "Cascade"
+    )]
+    LoginData,
+    #[sea_orm(
+        belongs_to = "super::posts::Entity",
+        from = "Column::PostId",
+        to = "super::posts::Column::PostId",
+        on_update = "NoAction",
+        on_delete = "Cascade"
+    )]
+    Posts,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -1,5 +1,12 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.14
 
+pub use super::invites::Entity as Invites;
 pub use super::login_data::Entity as LoginData;
+pub use super::oauth_clients::Entity as OAuthClients;
+pub use super::oauth_codes::Entity as OAuthCodes;
+pub use super::oauth_tokens::Entity as OAuthTokens;
+pub use super::post_attachments::Entity as PostAttachments;
+pub use super::reports::Entity as Reports;
 pub use super::user_data::Entity as UserData;
 pub use super::user_pfp::Entity as UserPfp;
+pub use super::user_pfp_variants::Entity as UserPfpVariants;
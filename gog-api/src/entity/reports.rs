@@ -0,0 +1,73 @@
+use gog_commons::data_structures::{
+    ReportAction, ReportCategory, ReportData, ReportStatus, ReportTargetKind,
+};
+use sea_orm::entity::prelude::*;
+
+/// Moderation reports, resolved reports are kept as a record of what was done
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, serde::Serialize, serde::Deserialize)]
+#[sea_orm(table_name = "reports")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub report_id: Uuid,
+    pub reporter_id: Uuid,
+    pub target_kind: String,
+    pub target_id: Uuid,
+    pub category: String,
+    pub reason: String,
+    pub created: DateTimeUtc,
+    pub status: String,
+    pub claimed_by: Option<Uuid>,
+    pub resolved_by: Option<Uuid>,
+    pub action: Option<String>,
+    pub note: Option<String>,
+    pub resolved: Option<DateTimeUtc>,
+}
+
+impl Model {
+    pub fn status(&self) -> ReportStatus {
+        self.status.parse().unwrap_or(ReportStatus::Open)
+    }
+    pub fn target_kind(&self) -> ReportTargetKind {
+        self.target_kind.parse().unwrap_or(ReportTargetKind::User)
+    }
+    pub fn into_report_data(self, reporter: String) -> ReportData {
+        ReportData {
+            report_id: self.report_id,
+            reporter,
+            target_kind: self.target_kind(),
+            target_id: self.target_id,
+            category: self.category.parse().unwrap_or(ReportCategory::Other),
+            status: self.status(),
+            action: self
+                .action
+                .as_deref()
+                .and_then(|a| a.parse::<ReportAction>().ok()),
+            reason: self.reason,
+            created: self.created,
+            claimed_by: self.claimed_by,
+            resolved_by: self.resolved_by,
+            note: self.note,
+            resolved: self.resolved,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::login_data::Entity",
+        from = "Column::ReporterId",
+        to = "super::login_data::Column::UserId",
+        on_update = "NoAction",
+        on_delete = "Cascade"
+    )]
+    LoginData,
+}
+
+impl Related<super::login_data::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::LoginData.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
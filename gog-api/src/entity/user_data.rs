@@ -1,15 +1,78 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.14
 
+use gog_commons::data_structures::{ProfileField, ProfileVisibility, UserDataResponse, Visibility};
 use sea_orm::entity::prelude::*;
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+/// Length of the blob key prefix used as the banner version
+const BANNER_VERSION_LENGTH: usize = 16;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, serde::Serialize, serde::Deserialize)]
 #[sea_orm(table_name = "user_data")]
 pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
     pub user_id: Uuid,
     pub description: Option<String>,
     pub created: Option<DateTimeUtc>,
-    pub gender: Option<String>,
+    pub display_name: Option<String>,
+    pub location: Option<String>,
+    pub website: Option<String>,
+    /// Json array of [`ProfileField`]s
+    pub profile_fields: Option<String>,
+    pub banner_key: Option<String>,
+    pub banner_content_type: Option<String>,
+    pub banner_uploaded: Option<DateTimeUtc>,
+    pub pronouns: Option<String>,
+    /// Json [`ProfileVisibility`], everything is public when missing
+    pub visibility: Option<String>,
+}
+
+impl Model {
+    pub fn profile_fields(&self) -> Vec<ProfileField> {
+        self.profile_fields
+            .as_deref()
+            .and_then(|f| serde_json::from_str(f).ok())
+            .unwrap_or_default()
+    }
+    /// Blob keys are content hashes, so a new banner always gets a new version
+    pub fn banner_version(&self) -> Option<String> {
+        self.banner_key
+            .as_deref()
+            .and_then(|k| k.get(..BANNER_VERSION_LENGTH))
+            .map(str::to_owned)
+    }
+    pub fn visibility(&self) -> ProfileVisibility {
+        self.visibility
+            .as_deref()
+            .and_then(|v| serde_json::from_str(v).ok())
+            .unwrap_or_default()
+    }
+    /// The profile as seen by its owner, or by anyone else when `owner` is false,
+    /// in which case only public fields are filled in
+    pub fn into_user_data_response(
+        self,
+        login: super::login_data::Model,
+        owner: bool,
+    ) -> UserDataResponse {
+        let visibility = self.visibility();
+        let shown = |v: Visibility| owner || v == Visibility::Public;
+        UserDataResponse {
+            fields: shown(visibility.fields)
+                .then(|| self.profile_fields())
+                .unwrap_or_default(),
+            banner_version: self.banner_version(),
+            role: login.role(),
+            pfp_version: login.pfp_version,
+            login: login.login,
+            id: self.user_id,
+            description: self.description.unwrap_or_default(),
+            pronouns: self.pronouns.filter(|_| shown(visibility.pronouns)),
+            created: self.created,
+            display_name: self.display_name,
+            location: self.location.filter(|_| shown(visibility.location)),
+            website: self.website.filter(|_| shown(visibility.website)),
+            visibility: owner.then_some(visibility),
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -0,0 +1,39 @@
+use sea_orm::entity::prelude::*;
+
+/// Resized profile pictures, one row per size in [`crate::images::PFP_SIZES`]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, serde::Serialize, serde::Deserialize)]
+#[sea_orm(table_name = "user_pfp_variants")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub user_id: Uuid,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub size: i32,
+    pub content_type: String,
+    /// Key in the blob store, rows without one still carry their `data`
+    pub blob_key: Option<String>,
+    #[sea_orm(column_type = "Blob", nullable)]
+    pub data: Option<Vec<u8>>,
+    /// Hex sha256 of the encoded picture, served as its ETag
+    pub content_hash: Option<String>,
+    pub uploaded: Option<DateTimeUtc>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::login_data::Entity",
+        from = "Column::UserId",
+        to = "super::login_data::Column::UserId",
+        on_update = "NoAction",
+        on_delete = "Cascade"
+    )]
+    LoginData,
+}
+
+impl Related<super::login_data::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::LoginData.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
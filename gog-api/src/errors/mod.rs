@@ -1,6 +1,9 @@
 use std::error::Error;
 
+use crate::backup::BackupError;
+use crate::moderation::ModerationError;
 use actix_web::{HttpResponse, ResponseError};
+use gog_commons::data_structures::ValidationErrorResponse;
 use sea_orm::DbErr;
 use thiserror::Error;
 
@@ -12,6 +15,15 @@ pub enum ServiceError {
     #[error("Wrong password")]
     WrongPassword,
 
+    #[error("Insufficient permissions")]
+    Forbidden,
+
+    #[error("Account suspended")]
+    Suspended,
+
+    #[error("Not found")]
+    NotFound,
+
     #[error("Session error")]
     SessionError {
         #[from]
@@ -47,6 +59,31 @@ pub enum ServiceError {
         #[from]
         source: sea_orm::TransactionError<DbErr>,
     },
+    #[error("Moderation error")]
+    ModerationError {
+        #[from]
+        source: crate::moderation::ModerationError,
+    },
+    #[error("Storage error: {source}")]
+    StorageError {
+        #[from]
+        source: crate::storage::StorageError,
+    },
+    #[error("Backup error: {source}")]
+    BackupError {
+        #[from]
+        source: crate::backup::BackupError,
+    },
+}
+
+impl From<crate::service::resources::ReplacePfpError> for ServiceError {
+    fn from(e: crate::service::resources::ReplacePfpError) -> Self {
+        use crate::service::resources::ReplacePfpError;
+        match e {
+            ReplacePfpError::DatabaseError { source } => source.into(),
+            ReplacePfpError::StorageError { source } => source.into(),
+        }
+    }
 }
 
 impl ResponseError for ServiceError {
@@ -58,6 +95,13 @@ impl ResponseError for ServiceError {
             ServiceError::UserNotFound => {
                 HttpResponse::BadRequest().reason("user not found").finish()
             }
+            ServiceError::Forbidden => HttpResponse::Forbidden()
+                .reason("insufficient permissions")
+                .finish(),
+            ServiceError::Suspended => HttpResponse::Forbidden()
+                .reason("account suspended")
+                .finish(),
+            ServiceError::NotFound => HttpResponse::NotFound().finish(),
             ServiceError::SessionError { source: _ } => HttpResponse::InternalServerError()
                 .reason("session error")
                 .finish(),
@@ -69,6 +113,36 @@ impl ResponseError for ServiceError {
             Self::ServerError { source } => HttpResponse::InternalServerError().finish(),
             Self::CacheError { source: _ } => HttpResponse::InternalServerError().finish(),
             Self::TransactionError { source: _ } => HttpResponse::InternalServerError().finish(),
+            Self::ModerationError {
+                source: ModerationError::Rejected(errors),
+            } => HttpResponse::BadRequest().reason("content rejected").json(
+                ValidationErrorResponse {
+                    reason: "content rejected by moderation".to_owned(),
+                    errors: errors.clone(),
+                },
+            ),
+            Self::ModerationError { source: _ } => HttpResponse::InternalServerError().finish(),
+            Self::StorageError { source: _ } => HttpResponse::InternalServerError()
+                .reason("storage error")
+                .finish(),
+            Self::BackupError {
+                source: BackupError::Busy,
+            } => HttpResponse::Conflict()
+                .reason("backup in progress")
+                .finish(),
+            Self::BackupError {
+                source: BackupError::Exists(_),
+            } => HttpResponse::Conflict()
+                .reason("backup of this second exists")
+                .finish(),
+            Self::BackupError {
+                source: BackupError::Unsupported,
+            } => HttpResponse::NotImplemented()
+                .reason("backups need sqlite")
+                .finish(),
+            Self::BackupError { source: _ } => HttpResponse::InternalServerError()
+                .reason("backup error")
+                .finish(),
         }
     }
 }
@@ -125,3 +199,81 @@ impl ResponseError for UserIdError {
         }
     }
 }
+
+#[derive(Error, Debug)]
+pub enum CsrfError {
+    #[error("CSRF token missing")]
+    Missing,
+    #[error("CSRF token mismatch")]
+    Mismatch,
+}
+
+impl ResponseError for CsrfError {
+    fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
+        match self {
+            Self::Missing => HttpResponse::Forbidden()
+                .reason("csrf token missing")
+                .finish(),
+            Self::Mismatch => HttpResponse::Forbidden()
+                .reason("csrf token mismatch")
+                .finish(),
+        }
+    }
+}
+
+/// Errors of the OAuth2 endpoints, reported in the RFC 6749 json format
+#[derive(Error, Debug)]
+pub enum OAuthError {
+    #[error("invalid_request: {0}")]
+    InvalidRequest(&'static str),
+    #[error("invalid_client")]
+    InvalidClient,
+    #[error("invalid_grant: {0}")]
+    InvalidGrant(&'static str),
+    #[error("unsupported_grant_type")]
+    UnsupportedGrantType,
+    #[error("invalid_token")]
+    InvalidToken,
+    #[error("Database error")]
+    DatabaseError {
+        #[from]
+        source: DbErr,
+    },
+    #[error("Key error")]
+    KeyError {
+        #[from]
+        source: crate::oauth::KeyError,
+    },
+}
+
+#[derive(serde::Serialize)]
+struct OAuthErrorBody {
+    error: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error_description: Option<&'static str>,
+}
+
+impl ResponseError for OAuthError {
+    fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
+        let (mut resp, error, error_description) = match self {
+            Self::InvalidRequest(d) => (HttpResponse::BadRequest(), "invalid_request", Some(*d)),
+            Self::InvalidClient => (HttpResponse::Unauthorized(), "invalid_client", None),
+            Self::InvalidGrant(d) => (HttpResponse::BadRequest(), "invalid_grant", Some(*d)),
+            Self::UnsupportedGrantType => {
+                (HttpResponse::BadRequest(), "unsupported_grant_type", None)
+            }
+            Self::InvalidToken => {
+                let mut resp = HttpResponse::Unauthorized();
+                resp.append_header(("WWW-Authenticate", "Bearer error=\"invalid_token\""));
+                (resp, "invalid_token", None)
+            }
+            Self::DatabaseError { source: _ } | Self::KeyError { source: _ } => {
+                (HttpResponse::InternalServerError(), "server_error", None)
+            }
+        };
+        resp.json(OAuthErrorBody {
+            error,
+            error_description,
+        })
+    }
+}
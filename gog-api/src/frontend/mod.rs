@@ -0,0 +1,203 @@
+//! Serves the `dist` directory built by Trunk, so the api and the frontend can run as one binary.
+//! Anything that is not a file and does not look like one gets `index.html`,
+//! the frontend router takes it from there
+
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::SystemTime;
+
+use actix_web::{
+    http::{
+        header::{self, CacheDirective},
+        Method,
+    },
+    web::Data,
+    HttpRequest, HttpResponse,
+};
+use regex::Regex;
+
+use crate::cache::ResourceCache;
+use crate::service::resources::{CacheValidators, IMMUTABLE_MAX_AGE};
+
+pub struct Frontend {
+    root: PathBuf,
+    /// Requests under it are left to the api, an unknown api path is a 404 and not `index.html`
+    api_prefix: String,
+}
+
+impl Frontend {
+    pub fn new(root: impl Into<PathBuf>, api_prefix: &str) -> Self {
+        Self {
+            root: root.into(),
+            api_prefix: api_prefix.to_owned(),
+        }
+    }
+}
+
+/// Turns `api`, `/api/` and the like into `/api`, an empty prefix stays empty
+pub fn normalize_prefix(prefix: &str) -> String {
+    let prefix = prefix.trim_matches('/');
+    if prefix.is_empty() {
+        String::new()
+    } else {
+        format!("/{}", prefix)
+    }
+}
+
+/// Trunk appends a 16 digit hash to the names of everything it builds except `index.html`
+fn is_hashed(name: &str) -> bool {
+    static HASHED: OnceLock<Regex> = OnceLock::new();
+    HASHED
+        .get_or_init(|| Regex::new(r"-[0-9a-f]{16}(_bg)?\.[a-z0-9]+$").unwrap())
+        .is_match(name)
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("js" | "mjs") => "text/javascript; charset=utf-8",
+        Some("wasm") => "application/wasm",
+        Some("css") => "text/css; charset=utf-8",
+        Some("json" | "map") => "application/json",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("ico") => "image/x-icon",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        Some("txt") => "text/plain; charset=utf-8",
+        _ => "application/octet-stream",
+    }
+}
+
+/// The file a request path points to, `None` when it tries to leave the root
+fn resolve(root: &Path, path: &str) -> Option<PathBuf> {
+    let relative = Path::new(path.trim_start_matches('/'));
+    let mut resolved = root.to_path_buf();
+    for component in relative.components() {
+        match component {
+            Component::Normal(part) if !part.to_string_lossy().starts_with('.') => {
+                resolved.push(part)
+            }
+            _ => return None,
+        }
+    }
+    Some(resolved)
+}
+
+fn accepts(req: &HttpRequest, encoding: &str) -> bool {
+    req.headers()
+        .get_all(header::ACCEPT_ENCODING)
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|v| v.split(';').next().map(str::trim) == Some(encoding))
+}
+
+async fn is_file(path: &Path) -> bool {
+    tokio::fs::metadata(path)
+        .await
+        .map_or(false, |m| m.is_file())
+}
+
+async fn send_file(
+    req: &HttpRequest,
+    cache: &ResourceCache,
+    path: &Path,
+    cache_control: header::CacheControl,
+) -> HttpResponse {
+    // a `.br` or `.gz` next to the file is sent instead when the client takes it
+    let mut file = (path.to_path_buf(), None);
+    for (encoding, suffix) in [("br", "br"), ("gzip", "gz")] {
+        let mut compressed = path.as_os_str().to_owned();
+        compressed.push(".");
+        compressed.push(suffix);
+        let compressed = PathBuf::from(compressed);
+        if accepts(req, encoding) && is_file(&compressed).await {
+            file = (compressed, Some(encoding));
+            break;
+        }
+    }
+    let (file, encoding) = file;
+    let Ok(metadata) = tokio::fs::metadata(&file).await else {
+        return HttpResponse::NotFound().finish();
+    };
+    let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+    let since_epoch = modified
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    let etag = format!(
+        "{:x}-{:x}{}",
+        metadata.len(),
+        since_epoch.as_secs(),
+        encoding.map_or(String::new(), |e| format!("-{}", e))
+    );
+    let validators = CacheValidators::new(&etag, Some(chrono::DateTime::from(modified)));
+    if validators.is_fresh(req) {
+        return validators.not_modified(cache_control);
+    }
+    let data = match cache.get_or_load(&file.to_string_lossy()).await {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("failed to read `{}`: {}", file.display(), e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    let mut res = validators.respond(req, cache_control, content_type(path), data);
+    res.headers_mut().insert(
+        header::VARY,
+        header::HeaderValue::from_static("accept-encoding"),
+    );
+    if let Some(encoding) = encoding {
+        res.headers_mut().insert(
+            header::CONTENT_ENCODING,
+            header::HeaderValue::from_static(encoding),
+        );
+    }
+    res
+}
+
+/// Default service of the app, everything the api does not handle ends up here
+pub async fn serve(
+    req: HttpRequest,
+    frontend: Data<Frontend>,
+    cache: Data<ResourceCache>,
+) -> HttpResponse {
+    let path = req.path();
+    let under_api = !frontend.api_prefix.is_empty()
+        && (path == frontend.api_prefix || path.starts_with(&format!("{}/", frontend.api_prefix)));
+    if under_api || !matches!(*req.method(), Method::GET | Method::HEAD) {
+        return HttpResponse::NotFound().finish();
+    }
+    let Some(file) = resolve(&frontend.root, path) else {
+        return HttpResponse::NotFound().finish();
+    };
+    if file != frontend.root && is_file(&file).await {
+        let hashed = file
+            .file_name()
+            .map_or(false, |n| is_hashed(&n.to_string_lossy()));
+        let cache_control = if hashed {
+            header::CacheControl(vec![
+                CacheDirective::Public,
+                CacheDirective::MaxAge(IMMUTABLE_MAX_AGE),
+                CacheDirective::Extension("immutable".to_owned(), None),
+            ])
+        } else {
+            header::CacheControl(vec![CacheDirective::NoCache])
+        };
+        return send_file(&req, &cache, &file, cache_control).await;
+    }
+    // a missing asset is a 404, anything else is a route of the frontend
+    if file.extension().is_some() {
+        return HttpResponse::NotFound().finish();
+    }
+    let index = frontend.root.join("index.html");
+    send_file(
+        &req,
+        &cache,
+        &index,
+        header::CacheControl(vec![CacheDirective::NoCache]),
+    )
+    .await
+}
@@ -0,0 +1,186 @@
+use std::io::Cursor;
+
+use image::{
+    codecs::jpeg::JpegEncoder, imageops::FilterType, DynamicImage, ImageDecoder, ImageFormat,
+    ImageReader, Limits,
+};
+use thiserror::Error;
+
+/// Square sizes every profile picture is stored in, smallest first
+pub const PFP_SIZES: [u32; 3] = [64, 128, 512];
+pub const DEFAULT_PFP_SIZE: u32 = 128;
+/// Post attachments are scaled down to fit these, keeping their aspect ratio
+pub const ATTACHMENT_MAX_SIDE: u32 = 2048;
+pub const THUMBNAIL_MAX_SIDE: u32 = 320;
+/// Profile banners are cropped to this size, 3:1
+pub const BANNER_WIDTH: u32 = 1500;
+pub const BANNER_HEIGHT: u32 = 500;
+
+const ACCEPTED_FORMATS: [ImageFormat; 4] = [
+    ImageFormat::Jpeg,
+    ImageFormat::Png,
+    ImageFormat::WebP,
+    ImageFormat::Gif,
+];
+/// Content types of [`ACCEPTED_FORMATS`], for telling clients what to send
+pub fn accepted_content_types() -> Vec<String> {
+    ACCEPTED_FORMATS
+        .iter()
+        .map(|f| f.to_mime_type().to_owned())
+        .collect()
+}
+/// Refuse to decode anything larger, the upload size limit alone does not stop decompression bombs
+const MAX_DIMENSION: u32 = 8192;
+const MAX_DECODE_ALLOC: u64 = 256 * 1024 * 1024;
+const JPEG_QUALITY: u8 = 85;
+
+#[derive(Debug, Error)]
+pub enum ImageProcessingError {
+    #[error("unsupported image format")]
+    UnsupportedFormat,
+    #[error("image error: {source}")]
+    ImageError {
+        #[from]
+        source: image::ImageError,
+    },
+    #[error("Io error")]
+    IoError {
+        #[from]
+        source: std::io::Error,
+    },
+}
+
+pub struct ImageVariant {
+    pub size: u32,
+    pub content_type: &'static str,
+    pub data: Vec<u8>,
+}
+
+/// Decodes an uploaded image upright, only the formats we accept get through
+pub fn decode(bytes: &[u8]) -> Result<(DynamicImage, ImageFormat), ImageProcessingError> {
+    let mut reader = ImageReader::new(Cursor::new(bytes)).with_guessed_format()?;
+    let format = reader
+        .format()
+        .filter(|f| ACCEPTED_FORMATS.contains(f))
+        .ok_or(ImageProcessingError::UnsupportedFormat)?;
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+    limits.max_alloc = Some(MAX_DECODE_ALLOC);
+    reader.limits(limits);
+    let mut decoder = reader.into_decoder()?;
+    // orientation lives in the EXIF data, which does not survive re-encoding
+    let orientation = decoder.orientation()?;
+    // animated GIFs and WebPs keep only their first frame
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+    Ok((image, format))
+}
+
+/// JPEGs stay JPEGs, everything else becomes PNG so transparency survives.
+/// Nothing but pixels is written, EXIF, XMP and ICC data of the upload are gone
+pub fn encode(
+    image: &DynamicImage,
+    source: ImageFormat,
+) -> Result<(Vec<u8>, &'static str), ImageProcessingError> {
+    let mut out = Cursor::new(Vec::new());
+    let content_type = if source == ImageFormat::Jpeg {
+        let encoder = JpegEncoder::new_with_quality(&mut out, JPEG_QUALITY);
+        image.to_rgb8().write_with_encoder(encoder)?;
+        "image/jpeg"
+    } else {
+        image.write_to(&mut out, ImageFormat::Png)?;
+        "image/png"
+    };
+    Ok((out.into_inner(), content_type))
+}
+
+fn crop_square(image: &DynamicImage) -> DynamicImage {
+    let side = image.width().min(image.height());
+    let x = (image.width() - side) / 2;
+    let y = (image.height() - side) / 2;
+    image.crop_imm(x, y, side, side)
+}
+
+/// Turns an upload into center cropped squares of every [`PFP_SIZES`] size.
+/// This is slow, run it off the async executor
+pub fn process_pfp(bytes: &[u8]) -> Result<Vec<ImageVariant>, ImageProcessingError> {
+    let (image, format) = decode(bytes)?;
+    let square = crop_square(&image);
+    PFP_SIZES
+        .iter()
+        .map(|&size| {
+            let resized = square.resize_exact(size, size, FilterType::Lanczos3);
+            let (data, content_type) = encode(&resized, format)?;
+            Ok(ImageVariant {
+                size,
+                content_type,
+                data,
+            })
+        })
+        .collect()
+}
+
+pub struct ProcessedAttachment {
+    pub width: u32,
+    pub height: u32,
+    pub image: ImageVariant,
+    pub thumbnail: ImageVariant,
+}
+
+/// Never scales up, small images are only re-encoded
+fn fit_within(image: &DynamicImage, side: u32) -> DynamicImage {
+    if image.width() <= side && image.height() <= side {
+        image.clone()
+    } else {
+        image.resize(side, side, FilterType::Lanczos3)
+    }
+}
+
+/// Re-encodes an attached image at most [`ATTACHMENT_MAX_SIDE`] pixels large
+/// and makes a [`THUMBNAIL_MAX_SIDE`] thumbnail of it.
+/// This is slow, run it off the async executor
+pub fn process_attachment(bytes: &[u8]) -> Result<ProcessedAttachment, ImageProcessingError> {
+    let (image, format) = decode(bytes)?;
+    let full = fit_within(&image, ATTACHMENT_MAX_SIDE);
+    let thumb = fit_within(&full, THUMBNAIL_MAX_SIDE);
+    let (data, content_type) = encode(&full, format)?;
+    let (thumb_data, thumb_content_type) = encode(&thumb, format)?;
+    Ok(ProcessedAttachment {
+        width: full.width(),
+        height: full.height(),
+        image: ImageVariant {
+            size: full.width().max(full.height()),
+            content_type,
+            data,
+        },
+        thumbnail: ImageVariant {
+            size: thumb.width().max(thumb.height()),
+            content_type: thumb_content_type,
+            data: thumb_data,
+        },
+    })
+}
+
+/// Crops a banner to [`BANNER_WIDTH`]:[`BANNER_HEIGHT`] around its center and scales it to that size.
+/// This is slow, run it off the async executor
+pub fn process_banner(bytes: &[u8]) -> Result<ImageVariant, ImageProcessingError> {
+    let (image, format) = decode(bytes)?;
+    let banner = image.resize_to_fill(BANNER_WIDTH, BANNER_HEIGHT, FilterType::Lanczos3);
+    let (data, content_type) = encode(&banner, format)?;
+    Ok(ImageVariant {
+        size: BANNER_WIDTH,
+        content_type,
+        data,
+    })
+}
+
+/// The stored size to serve for a requested one, the smallest that is at least as large
+pub fn pfp_size_for(requested: Option<u32>) -> u32 {
+    let requested = requested.unwrap_or(DEFAULT_PFP_SIZE);
+    PFP_SIZES
+        .iter()
+        .copied()
+        .find(|s| *s >= requested)
+        .unwrap_or(PFP_SIZES[PFP_SIZES.len() - 1])
+}
@@ -0,0 +1,117 @@
+//! Work that runs next to the server, each job on its own interval. A run that fails or panics
+//! is tried again after a delay that doubles with every failure in a row, up to the interval.
+//! When the server stops, jobs get to finish the run they are in and are not started again
+
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::time::Duration;
+
+use futures_util::FutureExt;
+use log::{debug, info, warn};
+use thiserror::Error;
+use tokio::{sync::watch, task::JoinHandle};
+
+/// Delay after the first failure in a row
+const FIRST_RETRY: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Error)]
+pub enum JobError {
+    /// Tried again after the backoff
+    #[error("{0}")]
+    Failed(String),
+    /// Nothing would change by trying again, the job is not run anymore
+    #[error("{0}")]
+    Unsupported(String),
+}
+
+/// Owns every job, they stop with [`Supervisor::shutdown`]
+pub struct Supervisor {
+    stop: watch::Sender<bool>,
+    jobs: Vec<(&'static str, JoinHandle<()>)>,
+}
+
+/// The delay before trying again after `failures` failed runs in a row
+fn backoff(failures: u32, interval: Duration) -> Duration {
+    FIRST_RETRY
+        .saturating_mul(2u32.saturating_pow(failures.saturating_sub(1)))
+        .min(interval)
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown cause")
+}
+
+impl Supervisor {
+    pub fn new() -> Self {
+        Self {
+            stop: watch::channel(false).0,
+            jobs: Vec::new(),
+        }
+    }
+
+    /// Runs `job` every `interval`, the first time an interval after the start
+    pub fn every<F, Fut>(&mut self, name: &'static str, interval: Duration, job: F)
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(), JobError>> + Send + 'static,
+    {
+        let mut stop = self.stop.subscribe();
+        let handle = tokio::spawn(async move {
+            let mut failures = 0;
+            let mut wait = interval;
+            loop {
+                tokio::select! {
+                    _ = tokio::time::sleep(wait) => (),
+                    _ = stop.changed() => break,
+                }
+                let failure = match AssertUnwindSafe(job()).catch_unwind().await {
+                    Ok(Ok(())) => None,
+                    Ok(Err(JobError::Unsupported(e))) => {
+                        warn!("job `{}` stopped: {}", name, e);
+                        break;
+                    }
+                    Ok(Err(JobError::Failed(e))) => Some(e),
+                    Err(payload) => Some(format!("panicked: {}", panic_message(&*payload))),
+                };
+                match failure {
+                    Some(e) => {
+                        failures += 1;
+                        wait = backoff(failures, interval);
+                        warn!(
+                            "job `{}` failed {} time(s) in a row, trying again in {:?}: {}",
+                            name, failures, wait, e
+                        );
+                    }
+                    None => {
+                        if failures > 0 {
+                            info!("job `{}` recovered after {} failure(s)", name, failures);
+                        }
+                        failures = 0;
+                        wait = interval;
+                    }
+                }
+            }
+            debug!("job `{}` stopped", name);
+        });
+        self.jobs.push((name, handle));
+    }
+
+    /// Lets runs in progress finish for up to `timeout` and cancels the ones still going after it
+    pub async fn shutdown(self, timeout: Duration) {
+        let _ = self.stop.send(true);
+        let deadline = tokio::time::Instant::now() + timeout;
+        for (name, mut handle) in self.jobs {
+            if tokio::time::timeout_at(deadline, &mut handle)
+                .await
+                .is_err()
+            {
+                warn!("job `{}` did not stop in time and was cancelled", name);
+                handle.abort();
+            }
+        }
+    }
+}
@@ -1,33 +1,49 @@
 #![allow(clippy::all)]
 
+mod backup;
 mod cache;
+mod cli;
+mod config;
+mod cors;
+mod csrf;
 mod entity;
 mod errors;
+mod frontend;
+mod images;
+mod jobs;
+mod maintenance;
+mod metrics;
 mod migrator;
+mod moderation;
+mod oauth;
+mod pow;
 mod service;
 mod session;
-use actix_cors::Cors;
+mod storage;
+mod telemetry;
 use std::sync::Mutex;
+use std::time::Duration;
 
 use actix_session::{storage::CookieSessionStore, SessionMiddleware};
-use actix_web::{self, cookie::Key, dev::Server, middleware::Logger, web, App, HttpServer};
+use actix_web::{self, cookie::Key, web, App, HttpServer};
 use log::{log, Level};
 use sea_orm::{ConnectOptions, Database, DatabaseConnection, DbBackend, DbErr, Statement};
 use service::DbConnection;
 use session::TokenSession;
 
-fn configure_services(cfg: &mut web::ServiceConfig) {
-    service::configure_service(cfg);
-    service::posts::configure_service(cfg);
+fn configure_services(cfg: &mut web::ServiceConfig, limits: &config::Limits) {
+    service::configure_service(cfg, limits);
+    service::posts::configure_service(cfg, limits);
     service::comments::configure_service(cfg);
+    service::oauth::configure_service(cfg);
+    service::admin::configure_service(cfg);
+    service::reports::configure_service(cfg);
+    service::invites::configure_service(cfg);
 }
 
-async fn setup_database(
-    db_url: &str,
-    db_name: &str,
-    refresh: bool,
-) -> Result<DatabaseConnection, DbErr> {
-    use sea_orm_migration::prelude::*;
+/// Connects without touching the migrations
+async fn connect_database(db_url: &str, db_name: &str) -> Result<DatabaseConnection, DbErr> {
+    use sea_orm::ConnectionTrait;
 
     let mut c_opt = ConnectOptions::new(db_url);
     c_opt.sqlx_logging(false);
@@ -36,8 +52,6 @@ async fn setup_database(
         .await
         .expect("failed to connect to database");
 
-    let _schema_manager = SchemaManager::new(&db);
-
     let db = match db.get_database_backend() {
         DbBackend::MySql => {
             db.execute(Statement::from_string(
@@ -62,6 +76,17 @@ async fn setup_database(
         }
         DbBackend::Sqlite => db,
     };
+    Ok(db)
+}
+
+async fn setup_database(
+    db_url: &str,
+    db_name: &str,
+    refresh: bool,
+) -> Result<DatabaseConnection, DbErr> {
+    use sea_orm_migration::prelude::*;
+
+    let db = connect_database(db_url, db_name).await?;
     if refresh {
         migrator::Migrator::fresh(&db).await?;
     }
@@ -70,18 +95,83 @@ async fn setup_database(
     Ok(db)
 }
 
+/// The server still starts, queries on the columns that differ fail on their own
+async fn warn_on_drift(db: &DatabaseConnection) {
+    use migrator::schema;
+    use sea_orm::ConnectionTrait;
+
+    if db.get_database_backend() == DbBackend::MySql {
+        log::warn!(
+            "schema drift is not checked, schema checks support SQLite and Postgres and this database is {:?}",
+            db.get_database_backend()
+        );
+        return;
+    }
+    match schema::read_schema(db).await {
+        Ok(live) => {
+            for problem in schema::diff(&schema::entity_schema(), &live) {
+                log::warn!("schema drift: {}", problem);
+            }
+        }
+        Err(e) => log::warn!("could not read the database schema: {}", e),
+    }
+}
+
+/// Promotes `login` to admin, so that a fresh instance has someone to hand out roles
+async fn bootstrap_admin(db: &DatabaseConnection, login: &str) -> Result<(), DbErr> {
+    use entity::{login_data, prelude::LoginData};
+    use gog_commons::data_structures::Role;
+    use sea_orm::{ActiveValue, EntityTrait};
+
+    match LoginData::find_by_id(login).one(db).await? {
+        Some(model) if model.role() != Role::Admin => {
+            let mut model: login_data::ActiveModel = model.into();
+            model.role = ActiveValue::Set(Role::Admin.as_str().to_owned());
+            LoginData::update(model).exec(db).await?;
+            log::info!("promoted `{}` to admin", login);
+        }
+        Some(_) => (),
+        None => log::warn!("bootstrap admin `{}` does not exist", login),
+    }
+    Ok(())
+}
+
+fn blob_store(config: &config::StorageConfig) -> std::sync::Arc<dyn storage::BlobStore> {
+    match config.store.as_str() {
+        "s3" => {
+            let s3 = &config.s3;
+            let required = |value: &Option<String>| value.clone().expect("validated");
+            let config = storage::S3Config {
+                bucket: required(&s3.bucket),
+                endpoint: s3.endpoint.clone(),
+                region: s3.region.clone(),
+                access_key: required(&s3.access_key),
+                secret_key: required(&s3.secret_key),
+            };
+            log::info!("storing blobs in s3 bucket `{}`", config.bucket);
+            std::sync::Arc::new(storage::S3BlobStore::new(config))
+        }
+        _ => {
+            log::info!("storing blobs in `{}`", config.dir);
+            std::sync::Arc::new(storage::LocalBlobStore::new(config.dir.clone()))
+        }
+    }
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    env_logger::init_from_env(env_logger::Env::new().default_filter_or("debug"));
-
-    dotenvy::dotenv()
-        .map_err(|_e| {
-            log::warn!(".env file not present");
-        })
-        .unwrap_or_default();
+    // read before the arguments, its variables can set them
+    let dotenv = dotenvy::dotenv();
 
     let args = clap::Command::new("gog-magog-api")
         // .version(clap::crate_version!())
+        .arg(
+            clap::Arg::new("config")
+                .env(gog_commons::vars::CONFIG_PATH_ENV)
+                .short('c')
+                .long("config")
+                .help("TOML file with the settings, `data/gog-api.toml` when it exists. Environment variables and flags override it"),
+        )
         .arg(
             clap::Arg::new("address")
                 .env(gog_commons::vars::BACKEND_ADDRESS_ENV)
@@ -92,7 +182,8 @@ async fn main() -> std::io::Result<()> {
             clap::Arg::new("port")
                 .env(gog_commons::vars::BACKEND_PORT_ENV)
                 .short('p')
-                .long("port"),
+                .long("port")
+                .value_parser(clap::value_parser!(u16)),
         )
         .arg(
             clap::Arg::new("db")
@@ -104,71 +195,485 @@ async fn main() -> std::io::Result<()> {
                 .env(gog_commons::vars::BACKEND_DATABASE_NAME_ENV)
                 .long("database-name"),
         )
+        .arg(
+            clap::Arg::new("oauth_issuer")
+                .env(gog_commons::vars::OAUTH_ISSUER_ENV)
+                .long("oauth-issuer"),
+        )
+        .arg(
+            clap::Arg::new("oauth_key_path")
+                .env(gog_commons::vars::OAUTH_KEY_PATH_ENV)
+                .long("oauth-key-path"),
+        )
+        .arg(
+            clap::Arg::new("bootstrap_admin")
+                .env(gog_commons::vars::BOOTSTRAP_ADMIN_ENV)
+                .long("bootstrap-admin"),
+        )
+        .arg(
+            clap::Arg::new("moderation_config")
+                .env(gog_commons::vars::MODERATION_CONFIG_ENV)
+                .long("moderation-config"),
+        )
+        .arg(
+            clap::Arg::new("registration_mode")
+                .env(gog_commons::vars::REGISTRATION_MODE_ENV)
+                .long("registration-mode")
+                .value_parser(["open", "invite_only", "closed"]),
+        )
+        .arg(
+            clap::Arg::new("pow_difficulty")
+                .env(gog_commons::vars::POW_DIFFICULTY_ENV)
+                .long("pow-difficulty")
+                .value_parser(clap::value_parser!(u8).range(0..=32)),
+        )
+        .arg(
+            clap::Arg::new("blob_store")
+                .env(gog_commons::vars::BLOB_STORE_ENV)
+                .long("blob-store")
+                .value_parser(["local", "s3"]),
+        )
+        .arg(
+            clap::Arg::new("blob_dir")
+                .env(gog_commons::vars::BLOB_DIR_ENV)
+                .long("blob-dir"),
+        )
+        .arg(
+            clap::Arg::new("s3_bucket")
+                .env(gog_commons::vars::S3_BUCKET_ENV)
+                .long("s3-bucket"),
+        )
+        .arg(
+            clap::Arg::new("s3_endpoint")
+                .env(gog_commons::vars::S3_ENDPOINT_ENV)
+                .long("s3-endpoint"),
+        )
+        .arg(
+            clap::Arg::new("s3_region")
+                .env(gog_commons::vars::S3_REGION_ENV)
+                .long("s3-region"),
+        )
+        .arg(
+            clap::Arg::new("s3_access_key")
+                .env(gog_commons::vars::S3_ACCESS_KEY_ENV)
+                .long("s3-access-key"),
+        )
+        .arg(
+            clap::Arg::new("s3_secret_key")
+                .env(gog_commons::vars::S3_SECRET_KEY_ENV)
+                .long("s3-secret-key")
+                .hide_env_values(true),
+        )
+        .arg(
+            clap::Arg::new("require_alt_text")
+                .env(gog_commons::vars::REQUIRE_ALT_TEXT_ENV)
+                .long("require-alt-text")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            clap::Arg::new("resource_cache_max_bytes")
+                .env(gog_commons::vars::RESOURCE_CACHE_MAX_BYTES_ENV)
+                .long("resource-cache-max-bytes")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            clap::Arg::new("resource_cache_ttl")
+                .env(gog_commons::vars::RESOURCE_CACHE_TTL_ENV)
+                .long("resource-cache-ttl")
+                .help("Seconds until cached files are read again, by default they are whenever they change")
+                .value_parser(clap::value_parser!(u64)),
+        )
+        .arg(
+            clap::Arg::new("frontend_dir")
+                .env(gog_commons::vars::FRONTEND_DIR_ENV)
+                .long("frontend-dir")
+                .help("Serve the frontend built by `trunk build` from this directory"),
+        )
+        .arg(
+            clap::Arg::new("api_prefix")
+                .env(gog_commons::vars::API_PREFIX_ENV)
+                .long("api-prefix")
+                .help("Path the api is mounted under, `/api` when the frontend is served and `/` otherwise"),
+        )
+        .arg(
+            clap::Arg::new("frontend_address")
+                .env(gog_commons::vars::FRONTENT_ADDRESS_ENV)
+                .long("frontend-address"),
+        )
+        .arg(
+            clap::Arg::new("frontend_port")
+                .env(gog_commons::vars::FRONTEND_PORT_ENV)
+                .long("frontend-port")
+                .value_parser(clap::value_parser!(u16)),
+        )
+        .arg(
+            clap::Arg::new("cors_origins")
+                .env(gog_commons::vars::CORS_ORIGINS_ENV)
+                .long("cors-origin")
+                .help("Origin allowed to call the api from a browser, the frontend address by default")
+                .action(clap::ArgAction::Append)
+                .value_delimiter(','),
+        )
+        .arg(
+            clap::Arg::new("cors_origin_patterns")
+                .env(gog_commons::vars::CORS_ORIGIN_PATTERNS_ENV)
+                .long("cors-origin-pattern")
+                .help("Allowed origins where `*` stands for any subdomain, like `https://*.example.com`")
+                .action(clap::ArgAction::Append)
+                .value_delimiter(','),
+        )
+        .arg(
+            clap::Arg::new("cors_methods")
+                .env(gog_commons::vars::CORS_METHODS_ENV)
+                .long("cors-method")
+                .action(clap::ArgAction::Append)
+                .value_delimiter(','),
+        )
+        .arg(
+            clap::Arg::new("cors_headers")
+                .env(gog_commons::vars::CORS_HEADERS_ENV)
+                .long("cors-header")
+                .action(clap::ArgAction::Append)
+                .value_delimiter(','),
+        )
+        .arg(
+            clap::Arg::new("cors_credentials")
+                .env(gog_commons::vars::CORS_CREDENTIALS_ENV)
+                .long("cors-credentials")
+                .help("Whether allowed origins may send the session cookie")
+                .value_parser(clap::value_parser!(bool)),
+        )
+        .arg(
+            clap::Arg::new("cors_max_age")
+                .env(gog_commons::vars::CORS_MAX_AGE_ENV)
+                .long("cors-max-age")
+                .help("Seconds browsers may cache a preflight response")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            clap::Arg::new("log_format")
+                .env(gog_commons::vars::LOG_FORMAT_ENV)
+                .long("log-format")
+                .value_parser(["text", "json"]),
+        )
+        .arg(
+            clap::Arg::new("otlp_endpoint")
+                .env(gog_commons::vars::OTLP_ENDPOINT_ENV)
+                .long("otlp-endpoint")
+                .help("Send traces to this OTLP/HTTP address, like `http://localhost:4318/v1/traces`"),
+        )
+        .arg(
+            clap::Arg::new("shutdown_timeout")
+                .env(gog_commons::vars::SHUTDOWN_TIMEOUT_ENV)
+                .long("shutdown-timeout")
+                .help("Seconds requests in flight and background jobs get to finish on SIGTERM")
+                .value_parser(clap::value_parser!(u64)),
+        )
         .arg(
             clap::Arg::new("fresh")
                 .long("fresh")
                 .action(clap::ArgAction::SetTrue),
         )
+        .subcommand(
+            clap::Command::new("serve").about("Run the server, the same as giving no subcommand"),
+        )
+        .subcommand(
+            clap::Command::new("config")
+                .about("Inspect the configuration")
+                .subcommand_required(true)
+                .subcommand(clap::Command::new("check").about(
+                    "Validate the configuration and print it with secrets redacted, then exit",
+                )),
+        )
+        .subcommand(
+            clap::Command::new("sanitize-images")
+                .about("Re-encode stored profile pictures, dropping their metadata, then exit"),
+        )
+        .subcommand(
+            clap::Command::new("migrate-blobs")
+                .about("Move image data kept in the database into the blob store, then exit"),
+        )
+        .subcommands(cli::commands())
         .get_matches();
-    use gog_commons::vars::defaults;
-    let address = args
-        .get_one::<&str>("address")
-        .map_or(defaults::BACKEND_ADDRESS, |a| &a);
-    let port = args
-        .get_one::<u16>("port")
-        .map_or(defaults::BACKEND_PORT, |p| *p);
-    let db = args.get_one::<String>("db").expect("db expected");
-    let db_name = args.get_one::<String>("db_name").expect("db_name expected");
+    let mut config = config::Config::load(args.get_one::<String>("config").map(|p| p.as_str()))
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1)
+        });
+    config.apply_args(&args);
+    let valid = config.validate();
+    if let Some(("config", _)) = args.subcommand() {
+        print!("{}", config.redacted().to_toml());
+        return match valid {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1)
+            }
+        };
+    }
+    if let Err(e) = valid {
+        eprintln!("{}", e);
+        std::process::exit(1)
+    }
+    let _telemetry = telemetry::init(&config.logging).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1)
+    });
+    if dotenv.is_err() {
+        log::warn!(".env file not present");
+    }
+    let db = config.database.url.as_deref().expect("validated");
+    let db_name = config.database.name.as_deref().expect("validated");
+    let store = blob_store(&config.storage);
+    if matches!(
+        args.subcommand_name(),
+        Some("sanitize-images" | "migrate-blobs")
+    ) && maintenance::server_running(&config.server)
+    {
+        eprintln!(
+            "a server answers on {}:{}, stop it first: blobs released now could be taken by an upload in progress",
+            config.server.address, config.server.port
+        );
+        std::process::exit(1)
+    }
+    match args.subcommand() {
+        Some(("sanitize-images", _)) => {
+            let db = setup_database(db, db_name, false)
+                .await
+                .unwrap_or_else(|e| panic!("database setup error: {}", e));
+            maintenance::sanitize_images(&db, store.as_ref())
+                .await
+                .unwrap_or_else(|e| panic!("image sanitizing error: {}", e));
+            return Ok(());
+        }
+        Some(("migrate-blobs", _)) => {
+            let db = setup_database(db, db_name, false)
+                .await
+                .unwrap_or_else(|e| panic!("database setup error: {}", e));
+            maintenance::migrate_blobs(&db, store.as_ref())
+                .await
+                .unwrap_or_else(|e| panic!("blob migration error: {}", e));
+            return Ok(());
+        }
+        Some((name, matches)) if cli::handles(name) => {
+            if let Err(e) = cli::run(name, matches, &config, store.as_ref()).await {
+                eprintln!("{}", e);
+                std::process::exit(1)
+            }
+            return Ok(());
+        }
+        _ => (),
+    }
     log!(
         Level::Info,
         "Running gog-magog server on {}:{}\nwith database url: {} and database name: {}",
-        address,
-        port,
-        db,
+        config.server.address,
+        config.server.port,
+        config.redacted().database.url.unwrap_or_default(),
         db_name
     );
-    create_and_run_server(&address, port, &db, &db_name, args.get_flag("fresh"))
-        .await?
-        .await?;
+    let oauth = oauth::OAuthProvider::load_or_generate(
+        &config.issuer(),
+        &config.frontend_origin(),
+        &config.oauth.key_path,
+    )
+    .unwrap_or_else(|e| panic!("oauth key setup error: {}", e));
+    let moderation = moderation::ModerationService::load(&config.moderation.config)
+        .unwrap_or_else(|e| panic!("moderation config error: {}", e));
+    log::info!("registration mode: {}", config.registration.mode.as_str());
+    let pow_guard = pow::PowGuard::new(config.registration.pow_difficulty);
+    let invalidation = match config.resources.cache_ttl {
+        Some(secs) => cache::Invalidation::Ttl(std::time::Duration::from_secs(secs)),
+        None => cache::Invalidation::Modified,
+    };
+    let cache = cache::ResourceCache::new(config.resources.cache_max_bytes, invalidation);
+    let api_prefix = config.api_prefix();
+    if let Some(dir) = &config.frontend.dir {
+        log::info!("serving the frontend from `{}`", dir);
+    }
+    if !api_prefix.is_empty() {
+        log::info!("api mounted under `{}`", api_prefix);
+    }
+    let frontend = config
+        .frontend
+        .dir
+        .as_ref()
+        .map(|dir| frontend::Frontend::new(dir, &api_prefix));
+    let cors = config.cors_policy().expect("validated");
+    log::info!("cors origins: {:?} {:?}", cors.origins, cors.patterns);
+    create_and_run_server(
+        &config,
+        args.get_flag("fresh"),
+        oauth,
+        moderation,
+        pow_guard,
+        store,
+        cache,
+        api_prefix,
+        frontend,
+        cors,
+    )
+    .await?;
     Ok(())
 }
 
+/// Resolves with the name of the first of SIGTERM and SIGINT to arrive
+async fn shutdown_signal() -> &'static str {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut terminate = signal(SignalKind::terminate()).expect("failed to listen for SIGTERM");
+        tokio::select! {
+            _ = terminate.recv() => "SIGTERM",
+            _ = tokio::signal::ctrl_c() => "SIGINT",
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        "Ctrl-C"
+    }
+}
+
 async fn create_and_run_server(
-    address: &str,
-    port: u16,
-    db: &str,
-    db_name: &str,
+    config: &config::Config,
     fresh: bool,
-) -> std::io::Result<Server> {
+    oauth: oauth::OAuthProvider,
+    moderation: moderation::ModerationService,
+    pow_guard: pow::PowGuard,
+    store: std::sync::Arc<dyn storage::BlobStore>,
+    cache: cache::ResourceCache,
+    api_prefix: String,
+    frontend: Option<frontend::Frontend>,
+    cors: cors::CorsPolicy,
+) -> std::io::Result<()> {
     let secret_key = Key::generate();
-    let db = setup_database(db, db_name, fresh)
-        .await
-        .unwrap_or_else(|e| panic!("database setup error: {}", e));
+    let mut db = setup_database(
+        config.database.url.as_deref().expect("validated"),
+        config.database.name.as_deref().expect("validated"),
+        fresh,
+    )
+    .await
+    .unwrap_or_else(|e| panic!("database setup error: {}", e));
+    let metrics = web::Data::new(metrics::Metrics::default());
+    {
+        // only connections cloned from here on report their queries
+        let metrics = metrics.clone();
+        db.set_metric_callback(move |info| metrics.record_query(info));
+    }
+    warn_on_drift(&db).await;
+    if let Some(admin) = &config.registration.bootstrap_admin {
+        bootstrap_admin(&db, admin)
+            .await
+            .unwrap_or_else(|e| panic!("bootstrap admin error: {}", e));
+    }
+    let mut jobs = jobs::Supervisor::new();
+    let backups = web::Data::new(backup::BackupService::new(
+        db.clone(),
+        config.backup.clone(),
+    ));
+    if let Some(interval) = config.backup.interval {
+        log::info!(
+            "backing up the database to `{}` every {} seconds",
+            config.backup.dir,
+            interval
+        );
+        let backups = backups.clone().into_inner();
+        jobs.every("backup", Duration::from_secs(interval), move || {
+            backup::scheduled(backups.clone())
+        });
+    }
 
+    let closing = db.clone();
     let db = DbConnection::new(db.clone());
     use std::sync::Arc;
 
+    let session_config = &config.session;
     let token_session: Arc<Mutex<dyn TokenSession>> =
-        Arc::new(Mutex::new(session::DefaultTokenSession::new(Some(600))));
+        Arc::new(Mutex::new(session::DefaultTokenSession::new().with_expiry(
+            Duration::from_secs(session_config.ttl),
+            Duration::from_secs(session_config.extension),
+        )));
+    {
+        let token_session = token_session.clone();
+        jobs.every(
+            "session cleaner",
+            Duration::from_secs(session_config.cleaning_interval),
+            move || session::cleaner_task(token_session.clone()),
+        );
+    }
 
     let token_session = web::Data::from(token_session);
 
-    let cache = Arc::new(Mutex::new(cache::ResourceCache::new()));
-    let cache = web::Data::from(cache);
-    Ok(HttpServer::new(move || {
-        let cors = Cors::permissive();
-        App::new()
-            .configure(configure_services)
+    let cache = web::Data::new(cache);
+    let oauth = web::Data::new(oauth);
+    let moderation = web::Data::new(moderation);
+    let pow_guard = web::Data::new(pow_guard);
+    let store = web::Data::from(store);
+    let frontend = frontend.map(web::Data::new);
+    let limits = web::Data::new(config.limits.clone());
+    let resource_config = web::Data::new(config.resources.clone());
+    let instance = web::Data::new(config.instance.clone());
+    let registration = config.registration.mode;
+    let alt_text = service::attachments::AltTextPolicy {
+        required: config.limits.require_alt_text,
+    };
+    let server = HttpServer::new(move || {
+        let mut app = App::new()
+            .configure(metrics::configure_service)
+            .service(web::scope(&api_prefix).configure(|cfg| configure_services(cfg, &limits)))
             .app_data(web::Data::new(db.clone()))
             .app_data(token_session.clone())
             .app_data(cache.clone())
+            .app_data(oauth.clone())
+            .app_data(moderation.clone())
+            .app_data(web::Data::new(registration))
+            .app_data(pow_guard.clone())
+            .app_data(store.clone())
+            .app_data(web::Data::new(alt_text))
+            .app_data(limits.clone())
+            .app_data(resource_config.clone())
+            .app_data(instance.clone())
+            .app_data(backups.clone())
+            .app_data(metrics.clone());
+        if let Some(frontend) = &frontend {
+            app = app
+                .app_data(frontend.clone())
+                .default_service(web::to(frontend::serve));
+        }
+        app.wrap(actix_web::middleware::from_fn(csrf::protect))
             .wrap(SessionMiddleware::new(
                 CookieSessionStore::default(),
                 secret_key.clone(),
             ))
-            .wrap(Logger::default())
-            .wrap(cors)
+            .wrap(cors.build())
+            .wrap(actix_web::middleware::from_fn(metrics::track))
+            .wrap(actix_web::middleware::from_fn(telemetry::trace))
     })
-    .bind((address, port))?
-    .run())
+    // signals are handled below, so that jobs are stopped along with the server
+    .disable_signals()
+    .shutdown_timeout(config.server.shutdown_timeout)
+    .bind((config.server.address.as_str(), config.server.port))?
+    .run();
+    let handle = server.handle();
+    let mut server = tokio::spawn(server);
+    tokio::select! {
+        stopped = &mut server => return stopped.expect("server task panicked"),
+        signal = shutdown_signal() => log::info!(
+            "{} received, finishing requests and jobs for up to {} seconds",
+            signal,
+            config.server.shutdown_timeout
+        ),
+    }
+    // new connections are refused from here on, requests in flight get the timeout to finish
+    let timeout = Duration::from_secs(config.server.shutdown_timeout);
+    tokio::join!(handle.stop(true), jobs.shutdown(timeout));
+    server.await.expect("server task panicked")?;
+    // waits for the connections to be returned, SQLite writes out its journal when the last closes
+    if let Err(e) = closing.close().await {
+        log::warn!("failed to close the database: {}", e);
+    }
+    log::info!("stopped");
+    Ok(())
 }
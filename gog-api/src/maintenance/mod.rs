@@ -0,0 +1,193 @@
+use log::{info, warn};
+use sea_orm::{
+    sea_query::Condition, ActiveValue, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr,
+    EntityTrait, QueryFilter, QuerySelect,
+};
+use thiserror::Error;
+
+use crate::config::ServerConfig;
+use crate::entity::{prelude::*, user_pfp, user_pfp_variants};
+use crate::images;
+use crate::service::resources::{self, ReplacePfpError};
+use crate::storage::{BlobStore, StorageError};
+
+#[derive(Debug, Error)]
+pub enum MaintenanceError {
+    #[error("Database error: {source}")]
+    DatabaseError {
+        #[from]
+        source: DbErr,
+    },
+    #[error("Storage error: {source}")]
+    StorageError {
+        #[from]
+        source: StorageError,
+    },
+}
+
+impl From<ReplacePfpError> for MaintenanceError {
+    fn from(e: ReplacePfpError) -> Self {
+        match e {
+            ReplacePfpError::DatabaseError { source } => source.into(),
+            ReplacePfpError::StorageError { source } => source.into(),
+        }
+    }
+}
+
+/// Whether something answers on the address the server listens on. [`resources::release_blob`]
+/// only keeps blobs safe from uploads of its own process, so commands that release or rewrite
+/// blobs refuse to run next to a server
+pub fn server_running(server: &ServerConfig) -> bool {
+    use std::net::{TcpStream, ToSocketAddrs};
+    match (server.address.as_str(), server.port).to_socket_addrs() {
+        Ok(mut addrs) => {
+            addrs.any(|a| TcpStream::connect_timeout(&a, std::time::Duration::from_secs(1)).is_ok())
+        }
+        Err(_) => false,
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SanitizeReport {
+    pub converted: usize,
+    pub removed: usize,
+}
+
+/// Re-encodes profile pictures that were stored byte for byte before uploads got processed.
+/// Pictures that no longer decode are removed rather than served with their metadata intact
+pub async fn sanitize_images(
+    db: &DatabaseConnection,
+    store: &dyn BlobStore,
+) -> Result<SanitizeReport, MaintenanceError> {
+    let mut report = SanitizeReport::default();
+    // one row at a time, the originals can be large
+    let ids: Vec<uuid::Uuid> = UserPfp::find()
+        .select_only()
+        .column(user_pfp::Column::UserId)
+        .into_tuple()
+        .all(db)
+        .await?;
+    for id in ids {
+        let Some(row) = UserPfp::find_by_id(id).one(db).await? else {
+            continue;
+        };
+        let data = match &row.blob_key {
+            Some(key) => store.get(key).await?.or(row.data),
+            None => row.data,
+        };
+        let processed = match data {
+            Some(data) => images::process_pfp(&data).map_err(|e| e.to_string()),
+            None => Err("no data stored".to_owned()),
+        };
+        match processed {
+            Ok(variants) => {
+                resources::replace_pfp(db, store, id, variants).await?;
+                report.converted += 1;
+            }
+            Err(e) => {
+                warn!("removing undecodable profile picture of {}: {}", id, e);
+                UserPfp::delete_by_id(id).exec(db).await?;
+                if let Some(key) = row.blob_key {
+                    resources::release_blob(db, store, &key).await?;
+                }
+                report.removed += 1;
+            }
+        }
+    }
+    info!(
+        "sanitized profile pictures: {} converted, {} removed",
+        report.converted, report.removed
+    );
+    Ok(report)
+}
+
+#[derive(Debug, Default)]
+pub struct MigrateBlobsReport {
+    pub variants: usize,
+    pub originals: usize,
+}
+
+/// Moves image bytes still kept in the database into the blob store, leaving only keys behind.
+/// Each row is written to the store before its data is cleared, so the command can be rerun
+/// after an interruption
+pub async fn migrate_blobs(
+    db: &DatabaseConnection,
+    store: &dyn BlobStore,
+) -> Result<MigrateBlobsReport, MaintenanceError> {
+    let mut report = MigrateBlobsReport::default();
+
+    let variant_ids: Vec<(uuid::Uuid, i32)> = UserPfpVariants::find()
+        .select_only()
+        .column(user_pfp_variants::Column::UserId)
+        .column(user_pfp_variants::Column::Size)
+        .filter(user_pfp_variants::Column::Data.is_not_null())
+        .into_tuple()
+        .all(db)
+        .await?;
+    for id in variant_ids {
+        let Some(user_pfp_variants::Model {
+            content_type,
+            data: Some(data),
+            ..
+        }) = UserPfpVariants::find_by_id(id).one(db).await?
+        else {
+            continue;
+        };
+        let _writing = resources::blob_write_guard().await;
+        let key = store.put(&data, &content_type).await?;
+        UserPfpVariants::update_many()
+            .set(user_pfp_variants::ActiveModel {
+                blob_key: ActiveValue::Set(Some(key)),
+                data: ActiveValue::Set(None),
+                ..Default::default()
+            })
+            .filter(user_pfp_variants::Column::UserId.eq(id.0))
+            .filter(user_pfp_variants::Column::Size.eq(id.1))
+            .exec(db)
+            .await?;
+        report.variants += 1;
+    }
+
+    let original_ids: Vec<uuid::Uuid> = UserPfp::find()
+        .select_only()
+        .column(user_pfp::Column::UserId)
+        .filter(
+            Condition::all()
+                .add(user_pfp::Column::Data.is_not_null())
+                .add(user_pfp::Column::BlobKey.is_null()),
+        )
+        .into_tuple()
+        .all(db)
+        .await?;
+    for id in original_ids {
+        let Some(user_pfp::Model {
+            data: Some(data), ..
+        }) = UserPfp::find_by_id(id).one(db).await?
+        else {
+            continue;
+        };
+        // originals predate processing and were only ever accepted as jpeg
+        let _writing = resources::blob_write_guard().await;
+        let key = store.put(&data, "image/jpeg").await?;
+        UserPfp::update(user_pfp::ActiveModel {
+            user_id: ActiveValue::Unchanged(id),
+            blob_key: ActiveValue::Set(Some(key)),
+            data: ActiveValue::Set(None),
+        })
+        .exec(db)
+        .await?;
+        report.originals += 1;
+    }
+
+    if report.variants + report.originals > 0
+        && db.get_database_backend() == sea_orm::DbBackend::Sqlite
+    {
+        // give the space taken by the blobs back to the filesystem
+        db.execute_unprepared("VACUUM").await?;
+    }
+    info!(
+        "moved {} picture variants and {} originals into the blob store",
+        report.variants, report.originals
+    );
+    Ok(report)
+}
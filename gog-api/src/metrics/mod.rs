@@ -0,0 +1,327 @@
+//! Endpoints for whatever runs and watches the server: `/healthz` answers as long as the process
+//! does, `/readyz` once the database can be used and `/metrics` in the Prometheus text format.
+//! They are mounted next to the api, never under its prefix
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+    web::{self, Data},
+    Error, HttpResponse,
+};
+use sea_orm_migration::MigratorTrait;
+
+use crate::cache::{CacheStats, ResourceCache};
+use crate::migrator::Migrator;
+use crate::service::DbConnection;
+use crate::session::TokenSession;
+
+/// Upper bounds of the latency buckets, in seconds
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+/// The route of requests that matched none, so every unknown path does not get a series of its own
+pub(crate) const UNMATCHED: &str = "unmatched";
+
+pub fn configure_service(cfg: &mut web::ServiceConfig) {
+    cfg.service(health_check)
+        .service(readiness_check)
+        .service(metrics_export);
+}
+
+#[derive(Default)]
+struct Histogram {
+    /// Cumulative, like Prometheus wants them
+    buckets: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        for (bucket, le) in self.buckets.iter_mut().zip(BUCKETS) {
+            if secs <= le {
+                *bucket += 1;
+            }
+        }
+        self.sum += secs;
+        self.count += 1;
+    }
+    fn write(&self, out: &mut String, name: &str, labels: &str) {
+        for (bucket, le) in self.buckets.iter().zip(BUCKETS) {
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{},le=\"{}\"}} {}",
+                name, labels, le, bucket
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{}_bucket{{{},le=\"+Inf\"}} {}",
+            name, labels, self.count
+        );
+        let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, self.sum);
+        let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, self.count);
+    }
+}
+
+#[derive(Default)]
+struct Recorded {
+    /// By method, route and status
+    requests: BTreeMap<(String, String, u16), u64>,
+    /// By method and route
+    latency: BTreeMap<(String, String), Histogram>,
+    /// By the kind of statement, `select`, `insert` and so on
+    queries: BTreeMap<&'static str, Histogram>,
+    query_errors: BTreeMap<&'static str, u64>,
+}
+
+/// Counts requests and queries as they happen, everything else is read when scraped
+#[derive(Default)]
+pub struct Metrics {
+    recorded: Mutex<Recorded>,
+}
+
+impl Metrics {
+    fn record_request(&self, method: &str, route: &str, status: u16, elapsed: Duration) {
+        let mut recorded = self.recorded.lock().unwrap();
+        *recorded
+            .requests
+            .entry((method.to_owned(), route.to_owned(), status))
+            .or_default() += 1;
+        recorded
+            .latency
+            .entry((method.to_owned(), route.to_owned()))
+            .or_default()
+            .observe(elapsed);
+    }
+    /// Meant for [`sea_orm::DatabaseConnection::set_metric_callback`]
+    pub fn record_query(&self, info: &sea_orm::metric::Info<'_>) {
+        let kind = statement_kind(&info.statement.sql);
+        let mut recorded = self.recorded.lock().unwrap();
+        recorded
+            .queries
+            .entry(kind)
+            .or_default()
+            .observe(info.elapsed);
+        if info.failed {
+            *recorded.query_errors.entry(kind).or_default() += 1;
+        }
+    }
+    fn render(&self, sessions: usize, cache: &CacheStats) -> String {
+        let recorded = self.recorded.lock().unwrap();
+        let mut out = String::new();
+        header(
+            &mut out,
+            "gog_http_requests_total",
+            "counter",
+            "Requests answered, by route and status",
+        );
+        for ((method, route, status), count) in &recorded.requests {
+            let _ = writeln!(
+                out,
+                "gog_http_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}",
+                escape(method),
+                escape(route),
+                status,
+                count
+            );
+        }
+        header(
+            &mut out,
+            "gog_http_request_duration_seconds",
+            "histogram",
+            "Time until the response was ready, by route",
+        );
+        for ((method, route), histogram) in &recorded.latency {
+            histogram.write(
+                &mut out,
+                "gog_http_request_duration_seconds",
+                &format!("method=\"{}\",route=\"{}\"", escape(method), escape(route)),
+            );
+        }
+        header(
+            &mut out,
+            "gog_db_query_duration_seconds",
+            "histogram",
+            "Time the database took per statement, by kind of statement",
+        );
+        for (kind, histogram) in &recorded.queries {
+            histogram.write(
+                &mut out,
+                "gog_db_query_duration_seconds",
+                &format!("kind=\"{}\"", kind),
+            );
+        }
+        header(
+            &mut out,
+            "gog_db_query_errors_total",
+            "counter",
+            "Statements that failed, by kind of statement",
+        );
+        for (kind, count) in &recorded.query_errors {
+            let _ = writeln!(
+                out,
+                "gog_db_query_errors_total{{kind=\"{}\"}} {}",
+                kind, count
+            );
+        }
+        drop(recorded);
+        for (name, kind, help, value) in [
+            (
+                "gog_sessions_active",
+                "gauge",
+                "Signed in sessions that have not expired",
+                sessions as f64,
+            ),
+            (
+                "gog_resource_cache_hits_total",
+                "counter",
+                "Files served from the resource cache",
+                cache.hits as f64,
+            ),
+            (
+                "gog_resource_cache_misses_total",
+                "counter",
+                "Files the resource cache had to read",
+                cache.misses as f64,
+            ),
+            (
+                "gog_resource_cache_hit_ratio",
+                "gauge",
+                "Hits out of all lookups since the start",
+                match cache.hits + cache.misses {
+                    0 => 0.0,
+                    lookups => cache.hits as f64 / lookups as f64,
+                },
+            ),
+            (
+                "gog_resource_cache_evictions_total",
+                "counter",
+                "Files dropped from the resource cache to make room",
+                cache.evictions as f64,
+            ),
+            (
+                "gog_resource_cache_entries",
+                "gauge",
+                "Files in the resource cache",
+                cache.entries as f64,
+            ),
+            (
+                "gog_resource_cache_bytes",
+                "gauge",
+                "Size of the files in the resource cache",
+                cache.bytes as f64,
+            ),
+            (
+                "gog_resource_cache_max_bytes",
+                "gauge",
+                "Size the resource cache may grow to",
+                cache.max_bytes as f64,
+            ),
+        ] {
+            header(&mut out, name, kind, help);
+            let _ = writeln!(out, "{} {}", name, value);
+        }
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Label values are quoted, so quotes, backslashes and line breaks in them are escaped
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn statement_kind(sql: &str) -> &'static str {
+    let keyword = sql
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match keyword.as_str() {
+        "select" => "select",
+        "insert" => "insert",
+        "update" => "update",
+        "delete" => "delete",
+        _ => "other",
+    }
+}
+
+/// Times every request, labeled with the route pattern it matched instead of its path,
+/// so `/user/get_pfp/{login}` is one series and not one per user
+pub async fn track(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let started = Instant::now();
+    let metrics = req.app_data::<Data<Metrics>>().cloned();
+    let method = req.method().to_string();
+    let route = req.match_pattern();
+    let res = next.call(req).await;
+    if let Some(metrics) = metrics {
+        let status = match &res {
+            Ok(res) => res.status(),
+            Err(e) => e.as_response_error().status_code(),
+        };
+        metrics.record_request(
+            &method,
+            route.as_deref().unwrap_or(UNMATCHED),
+            status.as_u16(),
+            started.elapsed(),
+        );
+    }
+    res
+}
+
+#[actix_web::get("/healthz")]
+async fn health_check() -> HttpResponse {
+    HttpResponse::Ok().body("ok")
+}
+
+/// `503` until the database answers and every migration is applied
+#[actix_web::get("/readyz")]
+async fn readiness_check(db: Data<DbConnection>) -> HttpResponse {
+    let db = &db.db_connection;
+    let (database, pending) = match db.ping().await {
+        Ok(()) => match Migrator::get_pending_migrations(db).await {
+            Ok(pending) => ("ok".to_owned(), Some(pending.len())),
+            Err(e) => (e.to_string(), None),
+        },
+        Err(e) => (e.to_string(), None),
+    };
+    let body = serde_json::json!({
+        "database": database,
+        "pending_migrations": pending,
+    });
+    if pending == Some(0) {
+        HttpResponse::Ok().json(body)
+    } else {
+        HttpResponse::ServiceUnavailable().json(body)
+    }
+}
+
+#[actix_web::get("/metrics")]
+async fn metrics_export(
+    metrics: Data<Metrics>,
+    token_session: Data<Mutex<dyn TokenSession>>,
+    cache: Data<ResourceCache>,
+) -> HttpResponse {
+    let sessions = token_session.lock().unwrap().active_sessions();
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics.render(sessions, &cache.stats()))
+}
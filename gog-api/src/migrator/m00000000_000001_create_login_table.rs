@@ -24,7 +24,7 @@ impl MigrationTrait for Migration {
                     )
                     .col(
                         ColumnDef::new(LoginData::UserId)
-                            .text()
+                            .uuid()
                             .not_null()
                             .unique_key(),
                     )
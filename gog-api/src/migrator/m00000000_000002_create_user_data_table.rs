@@ -21,7 +21,7 @@ impl MigrationTrait for Migration {
                     .table(UserData::Table)
                     .col(
                         ColumnDef::new(UserData::UserId)
-                            .text()
+                            .uuid()
                             .not_null()
                             .primary_key(), //.unique_key(),
                     )
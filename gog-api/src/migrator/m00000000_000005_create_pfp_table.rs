@@ -47,7 +47,32 @@ impl MigrationTrait for Migration {
             )
             .await
     }
+    /// Puts the pictures back where they were kept before, `user_data.pfp`
     async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserData::Table)
+                    .add_column_if_not_exists(ColumnDef::new(UserData::Pfp).blob())
+                    .to_owned(),
+            )
+            .await?;
+        let pictures = Query::select()
+            .column(UserPfp::Data)
+            .from(UserPfp::Table)
+            .and_where(
+                Expr::col((UserPfp::Table, UserPfp::UserId))
+                    .equals((UserData::Table, UserData::UserId)),
+            )
+            .to_owned();
+        let copy = Query::update()
+            .table(UserData::Table)
+            .value(
+                UserData::Pfp,
+                SimpleExpr::SubQuery(None, Box::new(pictures.into_sub_query_statement())),
+            )
+            .to_owned();
+        manager.exec_stmt(copy).await?;
         manager
             .drop_table(Table::drop().table(UserPfp::Table).to_owned())
             .await
@@ -57,6 +82,7 @@ impl MigrationTrait for Migration {
 #[derive(Iden)]
 pub enum UserData {
     Table,
+    UserId,
     Pfp,
 }
 
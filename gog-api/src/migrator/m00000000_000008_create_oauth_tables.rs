@@ -0,0 +1,165 @@
+use super::m00000000_000001_create_login_table::LoginData;
+use sea_orm_migration::prelude::*;
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m00000000_000008_create_oauth_tables"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .if_not_exists()
+                    .table(OAuthClients::Table)
+                    .col(
+                        ColumnDef::new(OAuthClients::ClientId)
+                            .uuid()
+                            .primary_key()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(OAuthClients::OwnerId).uuid().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-owner_id")
+                            .from(OAuthClients::Table, OAuthClients::OwnerId)
+                            .to(LoginData::Table, LoginData::UserId)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .col(ColumnDef::new(OAuthClients::Name).text().not_null())
+                    .col(ColumnDef::new(OAuthClients::SecretHash).text())
+                    .col(ColumnDef::new(OAuthClients::RedirectUris).text().not_null())
+                    .col(ColumnDef::new(OAuthClients::Created).timestamp().not_null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_table(
+                Table::create()
+                    .if_not_exists()
+                    .table(OAuthCodes::Table)
+                    .col(
+                        ColumnDef::new(OAuthCodes::CodeHash)
+                            .text()
+                            .primary_key()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(OAuthCodes::ClientId).uuid().not_null())
+                    .col(ColumnDef::new(OAuthCodes::UserId).uuid().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-client_id")
+                            .from(OAuthCodes::Table, OAuthCodes::ClientId)
+                            .to(OAuthClients::Table, OAuthClients::ClientId)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-user_id")
+                            .from(OAuthCodes::Table, OAuthCodes::UserId)
+                            .to(LoginData::Table, LoginData::UserId)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .col(ColumnDef::new(OAuthCodes::RedirectUri).text().not_null())
+                    .col(ColumnDef::new(OAuthCodes::Scope).text().not_null())
+                    .col(ColumnDef::new(OAuthCodes::CodeChallenge).text().not_null())
+                    .col(ColumnDef::new(OAuthCodes::Nonce).text())
+                    .col(ColumnDef::new(OAuthCodes::Expires).timestamp().not_null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_table(
+                Table::create()
+                    .if_not_exists()
+                    .table(OAuthTokens::Table)
+                    .col(
+                        ColumnDef::new(OAuthTokens::TokenHash)
+                            .text()
+                            .primary_key()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(OAuthTokens::Kind).text().not_null())
+                    .col(ColumnDef::new(OAuthTokens::ClientId).uuid().not_null())
+                    .col(ColumnDef::new(OAuthTokens::UserId).uuid().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-client_id")
+                            .from(OAuthTokens::Table, OAuthTokens::ClientId)
+                            .to(OAuthClients::Table, OAuthClients::ClientId)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-user_id")
+                            .from(OAuthTokens::Table, OAuthTokens::UserId)
+                            .to(LoginData::Table, LoginData::UserId)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .col(ColumnDef::new(OAuthTokens::Scope).text().not_null())
+                    .col(ColumnDef::new(OAuthTokens::Expires).timestamp().not_null())
+                    .col(
+                        ColumnDef::new(OAuthTokens::Revoked)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(OAuthTokens::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(OAuthCodes::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(OAuthClients::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+pub enum OAuthClients {
+    #[iden = "oauth_clients"]
+    Table,
+    ClientId,
+    OwnerId,
+    Name,
+    SecretHash,
+    RedirectUris,
+    Created,
+}
+
+#[derive(Iden)]
+pub enum OAuthCodes {
+    #[iden = "oauth_codes"]
+    Table,
+    CodeHash,
+    ClientId,
+    UserId,
+    RedirectUri,
+    Scope,
+    CodeChallenge,
+    Nonce,
+    Expires,
+}
+
+#[derive(Iden)]
+pub enum OAuthTokens {
+    #[iden = "oauth_tokens"]
+    Table,
+    TokenHash,
+    Kind,
+    ClientId,
+    UserId,
+    Scope,
+    Expires,
+    Revoked,
+}
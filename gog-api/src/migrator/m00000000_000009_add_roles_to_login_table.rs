@@ -0,0 +1,74 @@
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m00000000_000009_add_roles_to_login_table"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if !manager
+            .has_column(LoginData::Table.to_string(), LoginData::Role.to_string())
+            .await?
+        {
+            let table = Table::alter()
+                .table(LoginData::Table)
+                .add_column_if_not_exists(
+                    ColumnDef::new(LoginData::Role)
+                        .text()
+                        .not_null()
+                        .default(Expr::value("user")),
+                )
+                .to_owned();
+            manager.alter_table(table).await?;
+        }
+        if !manager
+            .has_column(
+                LoginData::Table.to_string(),
+                LoginData::Suspended.to_string(),
+            )
+            .await?
+        {
+            let table = Table::alter()
+                .table(LoginData::Table)
+                .add_column_if_not_exists(
+                    ColumnDef::new(LoginData::Suspended)
+                        .boolean()
+                        .not_null()
+                        .default(false),
+                )
+                .to_owned();
+            manager.alter_table(table).await?;
+        }
+        Ok(())
+    }
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(LoginData::Table)
+                    .drop_column(LoginData::Suspended)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(LoginData::Table)
+                    .drop_column(LoginData::Role)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(Iden)]
+pub enum LoginData {
+    Table,
+    Role,
+    Suspended,
+}
@@ -0,0 +1,87 @@
+use super::m00000000_000001_create_login_table::LoginData;
+use sea_orm_migration::prelude::*;
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m00000000_000010_create_reports_table"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .if_not_exists()
+                    .table(Reports::Table)
+                    .col(
+                        ColumnDef::new(Reports::ReportId)
+                            .uuid()
+                            .primary_key()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Reports::ReporterId).uuid().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-reporter_id")
+                            .from(Reports::Table, Reports::ReporterId)
+                            .to(LoginData::Table, LoginData::UserId)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .col(ColumnDef::new(Reports::TargetKind).text().not_null())
+                    .col(ColumnDef::new(Reports::TargetId).uuid().not_null())
+                    .col(ColumnDef::new(Reports::Category).text().not_null())
+                    .col(ColumnDef::new(Reports::Reason).text().not_null())
+                    .col(ColumnDef::new(Reports::Created).timestamp().not_null())
+                    .col(
+                        ColumnDef::new(Reports::Status)
+                            .text()
+                            .not_null()
+                            .default(Expr::value("open")),
+                    )
+                    .col(ColumnDef::new(Reports::ClaimedBy).uuid())
+                    .col(ColumnDef::new(Reports::ResolvedBy).uuid())
+                    .col(ColumnDef::new(Reports::Action).text())
+                    .col(ColumnDef::new(Reports::Note).text())
+                    .col(ColumnDef::new(Reports::Resolved).timestamp())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx-reports-status")
+                    .table(Reports::Table)
+                    .col(Reports::Status)
+                    .col(Reports::Created)
+                    .to_owned(),
+            )
+            .await
+    }
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Reports::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+pub enum Reports {
+    Table,
+    ReportId,
+    ReporterId,
+    TargetKind,
+    TargetId,
+    Category,
+    Reason,
+    Created,
+    Status,
+    ClaimedBy,
+    ResolvedBy,
+    Action,
+    Note,
+    Resolved,
+}
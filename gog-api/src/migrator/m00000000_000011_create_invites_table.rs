@@ -0,0 +1,91 @@
+use super::m00000000_000001_create_login_table::LoginData;
+use sea_orm_migration::prelude::*;
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m00000000_000011_create_invites_table"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .if_not_exists()
+                    .table(Invites::Table)
+                    .col(
+                        ColumnDef::new(Invites::Code)
+                            .text()
+                            .primary_key()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Invites::CreatedBy).uuid().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-invite_created_by")
+                            .from(Invites::Table, Invites::CreatedBy)
+                            .to(LoginData::Table, LoginData::UserId)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .col(ColumnDef::new(Invites::Created).timestamp().not_null())
+                    .col(ColumnDef::new(Invites::Expires).timestamp())
+                    .col(ColumnDef::new(Invites::MaxUses).integer().not_null())
+                    .col(
+                        ColumnDef::new(Invites::Uses)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        if !manager
+            .has_column(
+                InviteLoginData::Table.to_string(),
+                InviteLoginData::InvitedBy.to_string(),
+            )
+            .await?
+        {
+            let table = Table::alter()
+                .table(InviteLoginData::Table)
+                .add_column_if_not_exists(ColumnDef::new(InviteLoginData::InvitedBy).uuid())
+                .to_owned();
+            manager.alter_table(table).await?;
+        }
+        Ok(())
+    }
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(InviteLoginData::Table)
+                    .drop_column(InviteLoginData::InvitedBy)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Invites::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+pub enum Invites {
+    Table,
+    Code,
+    CreatedBy,
+    Created,
+    Expires,
+    MaxUses,
+    Uses,
+}
+
+#[derive(Iden)]
+pub enum InviteLoginData {
+    #[iden = "login_data"]
+    Table,
+    InvitedBy,
+}
@@ -0,0 +1,57 @@
+use super::m00000000_000001_create_login_table::LoginData;
+use sea_orm_migration::prelude::*;
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m00000000_000012_create_pfp_variants_table"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .if_not_exists()
+                    .table(UserPfpVariants::Table)
+                    .col(ColumnDef::new(UserPfpVariants::UserId).uuid().not_null())
+                    .col(ColumnDef::new(UserPfpVariants::Size).integer().not_null())
+                    .primary_key(
+                        Index::create()
+                            .col(UserPfpVariants::UserId)
+                            .col(UserPfpVariants::Size),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-pfp_variant_user_id")
+                            .from(UserPfpVariants::Table, UserPfpVariants::UserId)
+                            .to(LoginData::Table, LoginData::UserId)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .col(
+                        ColumnDef::new(UserPfpVariants::ContentType)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(UserPfpVariants::Data).blob().not_null())
+                    .to_owned(),
+            )
+            .await
+    }
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(UserPfpVariants::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+pub enum UserPfpVariants {
+    Table,
+    UserId,
+    Size,
+    ContentType,
+    Data,
+}
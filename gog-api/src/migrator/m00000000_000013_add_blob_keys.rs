@@ -0,0 +1,203 @@
+use super::m00000000_000001_create_login_table::LoginData;
+use super::m00000000_000012_create_pfp_variants_table::UserPfpVariants;
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m00000000_000013_add_blob_keys"
+    }
+}
+
+/// Image bytes move to a blob store, rows keep a key and `data` only until `migrate-blobs` ran.
+/// SQLite cannot drop a NOT NULL constraint, so the variants table is rebuilt
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(UserPfpVariantsNext::Table)
+                    .col(ColumnDef::new(UserPfpVariants::UserId).uuid().not_null())
+                    .col(ColumnDef::new(UserPfpVariants::Size).integer().not_null())
+                    .primary_key(
+                        Index::create()
+                            .col(UserPfpVariants::UserId)
+                            .col(UserPfpVariants::Size),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-pfp_variant_user_id")
+                            .from(UserPfpVariantsNext::Table, UserPfpVariants::UserId)
+                            .to(LoginData::Table, LoginData::UserId)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .col(
+                        ColumnDef::new(UserPfpVariants::ContentType)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(UserPfpVariantsNext::BlobKey).text())
+                    .col(ColumnDef::new(UserPfpVariants::Data).blob())
+                    .to_owned(),
+            )
+            .await?;
+        let copy = Query::insert()
+            .into_table(UserPfpVariantsNext::Table)
+            .columns([
+                UserPfpVariants::UserId,
+                UserPfpVariants::Size,
+                UserPfpVariants::ContentType,
+                UserPfpVariants::Data,
+            ])
+            .select_from(
+                Query::select()
+                    .columns([
+                        UserPfpVariants::UserId,
+                        UserPfpVariants::Size,
+                        UserPfpVariants::ContentType,
+                        UserPfpVariants::Data,
+                    ])
+                    .from(UserPfpVariants::Table)
+                    .to_owned(),
+            )
+            .map_err(|e| DbErr::Migration(e.to_string()))?
+            .to_owned();
+        manager.exec_stmt(copy).await?;
+        manager
+            .drop_table(Table::drop().table(UserPfpVariants::Table).to_owned())
+            .await?;
+        manager
+            .rename_table(
+                Table::rename()
+                    .table(UserPfpVariantsNext::Table, UserPfpVariants::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        if !manager
+            .has_column(UserPfp::Table.to_string(), UserPfp::BlobKey.to_string())
+            .await?
+        {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(UserPfp::Table)
+                        .add_column_if_not_exists(ColumnDef::new(UserPfp::BlobKey).text())
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+    /// Refused while pictures only exist in the blob store, the schema before has nowhere
+    /// to keep them. Variants are copied into a rebuilt table that requires `data` again
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        let backend = manager.get_database_backend();
+        let moved_out = |table: &dyn Iden| {
+            Query::select()
+                .expr(Expr::col(Asterisk).count())
+                .from(Alias::new(table.to_string()))
+                // both tables call it `data`
+                .and_where(Expr::col(UserPfpVariants::Data).is_null())
+                .to_owned()
+        };
+        for table in [&UserPfp::Table as &dyn Iden, &UserPfpVariants::Table] {
+            let count = db
+                .query_one(backend.build(&moved_out(table)))
+                .await?
+                .map(|row| row.try_get_by_index::<i64>(0))
+                .transpose()?
+                .unwrap_or_default();
+            if count > 0 {
+                return Err(DbErr::Migration(format!(
+                    "{} rows of `{}` only have their data in the blob store, rolling back would lose them",
+                    count,
+                    table.to_string()
+                )));
+            }
+        }
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(UserPfpVariantsNext::Table)
+                    .col(ColumnDef::new(UserPfpVariants::UserId).uuid().not_null())
+                    .col(ColumnDef::new(UserPfpVariants::Size).integer().not_null())
+                    .primary_key(
+                        Index::create()
+                            .col(UserPfpVariants::UserId)
+                            .col(UserPfpVariants::Size),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-pfp_variant_user_id")
+                            .from(UserPfpVariantsNext::Table, UserPfpVariants::UserId)
+                            .to(LoginData::Table, LoginData::UserId)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .col(
+                        ColumnDef::new(UserPfpVariants::ContentType)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(UserPfpVariants::Data).blob().not_null())
+                    .to_owned(),
+            )
+            .await?;
+        let columns = || {
+            [
+                UserPfpVariants::UserId,
+                UserPfpVariants::Size,
+                UserPfpVariants::ContentType,
+                UserPfpVariants::Data,
+            ]
+        };
+        let copy = Query::insert()
+            .into_table(UserPfpVariantsNext::Table)
+            .columns(columns())
+            .select_from(
+                Query::select()
+                    .columns(columns())
+                    .from(UserPfpVariants::Table)
+                    .to_owned(),
+            )
+            .map_err(|e| DbErr::Migration(e.to_string()))?
+            .to_owned();
+        manager.exec_stmt(copy).await?;
+        manager
+            .drop_table(Table::drop().table(UserPfpVariants::Table).to_owned())
+            .await?;
+        manager
+            .rename_table(
+                Table::rename()
+                    .table(UserPfpVariantsNext::Table, UserPfpVariants::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserPfp::Table)
+                    .drop_column(UserPfp::BlobKey)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum UserPfpVariantsNext {
+    #[iden = "user_pfp_variants_next"]
+    Table,
+    BlobKey,
+}
+
+#[derive(Iden)]
+enum UserPfp {
+    Table,
+    BlobKey,
+}
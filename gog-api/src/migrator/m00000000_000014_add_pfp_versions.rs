@@ -0,0 +1,107 @@
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m00000000_000014_add_pfp_versions"
+    }
+}
+
+/// Content hashes and upload times of pictures, used as HTTP cache validators.
+/// `login_data.pfp_version` goes into picture urls, so a new upload gets a new url
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if !manager
+            .has_column(
+                PfpVariants::Table.to_string(),
+                PfpVariants::ContentHash.to_string(),
+            )
+            .await?
+        {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(PfpVariants::Table)
+                        .add_column(ColumnDef::new(PfpVariants::ContentHash).text())
+                        .to_owned(),
+                )
+                .await?;
+        }
+        if !manager
+            .has_column(
+                PfpVariants::Table.to_string(),
+                PfpVariants::Uploaded.to_string(),
+            )
+            .await?
+        {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(PfpVariants::Table)
+                        .add_column(ColumnDef::new(PfpVariants::Uploaded).timestamp())
+                        .to_owned(),
+                )
+                .await?;
+        }
+        if !manager
+            .has_column(
+                PfpLoginData::Table.to_string(),
+                PfpLoginData::PfpVersion.to_string(),
+            )
+            .await?
+        {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(PfpLoginData::Table)
+                        .add_column(ColumnDef::new(PfpLoginData::PfpVersion).text())
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(PfpLoginData::Table)
+                    .drop_column(PfpLoginData::PfpVersion)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(PfpVariants::Table)
+                    .drop_column(PfpVariants::Uploaded)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(PfpVariants::Table)
+                    .drop_column(PfpVariants::ContentHash)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum PfpVariants {
+    #[iden = "user_pfp_variants"]
+    Table,
+    ContentHash,
+    Uploaded,
+}
+
+#[derive(Iden)]
+enum PfpLoginData {
+    #[iden = "login_data"]
+    Table,
+    PfpVersion,
+}
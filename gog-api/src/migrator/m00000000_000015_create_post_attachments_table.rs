@@ -0,0 +1,100 @@
+use super::m00000000_000001_create_login_table::LoginData;
+use super::m00000000_000006_create_posts_table::Posts;
+use sea_orm_migration::prelude::*;
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m00000000_000015_create_post_attachments_table"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .if_not_exists()
+                    .table(PostAttachments::Table)
+                    .col(
+                        ColumnDef::new(PostAttachments::AttachmentId)
+                            .uuid()
+                            .primary_key()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(PostAttachments::UserId).uuid().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-attachment_user_id")
+                            .from(PostAttachments::Table, PostAttachments::UserId)
+                            .to(LoginData::Table, LoginData::UserId)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    // unset until the post using the upload is created
+                    .col(ColumnDef::new(PostAttachments::PostId).uuid())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-attachment_post_id")
+                            .from(PostAttachments::Table, PostAttachments::PostId)
+                            .to(Posts::Table, Posts::PostId)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .col(ColumnDef::new(PostAttachments::Position).integer())
+                    .col(ColumnDef::new(PostAttachments::AltText).text())
+                    .col(
+                        ColumnDef::new(PostAttachments::ContentType)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(PostAttachments::Width).integer().not_null())
+                    .col(ColumnDef::new(PostAttachments::Height).integer().not_null())
+                    .col(ColumnDef::new(PostAttachments::BlobKey).text().not_null())
+                    .col(
+                        ColumnDef::new(PostAttachments::ThumbContentType)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(PostAttachments::ThumbKey).text().not_null())
+                    .col(
+                        ColumnDef::new(PostAttachments::Uploaded)
+                            .timestamp()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx-attachment_post_id")
+                    .table(PostAttachments::Table)
+                    .col(PostAttachments::PostId)
+                    .to_owned(),
+            )
+            .await
+    }
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(PostAttachments::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+pub enum PostAttachments {
+    Table,
+    AttachmentId,
+    UserId,
+    PostId,
+    Position,
+    AltText,
+    ContentType,
+    Width,
+    Height,
+    BlobKey,
+    ThumbContentType,
+    ThumbKey,
+    Uploaded,
+}
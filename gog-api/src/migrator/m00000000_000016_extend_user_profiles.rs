@@ -0,0 +1,85 @@
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m00000000_000016_extend_user_profiles"
+    }
+}
+
+/// Display name, location, website, custom fields stored as a json array, and the banner image.
+/// `banner_key` points into the blob store
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [
+            UserData::DisplayName,
+            UserData::Location,
+            UserData::Website,
+            UserData::ProfileFields,
+            UserData::BannerKey,
+            UserData::BannerContentType,
+        ] {
+            add_column(manager, ColumnDef::new(column).text().to_owned()).await?;
+        }
+        add_column(
+            manager,
+            ColumnDef::new(UserData::BannerUploaded)
+                .timestamp()
+                .to_owned(),
+        )
+        .await
+    }
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [
+            UserData::BannerUploaded,
+            UserData::BannerContentType,
+            UserData::BannerKey,
+            UserData::ProfileFields,
+            UserData::Website,
+            UserData::Location,
+            UserData::DisplayName,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(UserData::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+async fn add_column(manager: &SchemaManager<'_>, mut column: ColumnDef) -> Result<(), DbErr> {
+    let name = column.get_column_name();
+    if manager
+        .has_column(UserData::Table.to_string(), name)
+        .await?
+    {
+        return Ok(());
+    }
+    manager
+        .alter_table(
+            Table::alter()
+                .table(UserData::Table)
+                .add_column(&mut column)
+                .to_owned(),
+        )
+        .await
+}
+
+#[derive(Iden)]
+enum UserData {
+    Table,
+    DisplayName,
+    Location,
+    Website,
+    ProfileFields,
+    BannerKey,
+    BannerContentType,
+    BannerUploaded,
+}
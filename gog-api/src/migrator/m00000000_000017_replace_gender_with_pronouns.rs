@@ -0,0 +1,103 @@
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m00000000_000017_replace_gender_with_pronouns"
+    }
+}
+
+/// Moves whatever users wrote as their gender into `pronouns` as it is,
+/// only the "not given" default is left behind. `visibility` holds a json object
+/// saying who may see which profile field
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [UserData::Pronouns, UserData::Visibility] {
+            if !manager
+                .has_column(UserData::Table.to_string(), column.to_string())
+                .await?
+            {
+                manager
+                    .alter_table(
+                        Table::alter()
+                            .table(UserData::Table)
+                            .add_column(ColumnDef::new(column).text())
+                            .to_owned(),
+                    )
+                    .await?;
+            }
+        }
+        if manager
+            .has_column(UserData::Table.to_string(), UserData::Gender.to_string())
+            .await?
+        {
+            let copy = Query::update()
+                .table(UserData::Table)
+                .value(
+                    UserData::Pronouns,
+                    Func::cust(Trim).arg(Expr::col(UserData::Gender)),
+                )
+                .and_where(Expr::col(UserData::Gender).is_not_null())
+                .and_where(Expr::col(UserData::Gender).ne(NOT_GIVEN))
+                .and_where(Func::cust(Trim).arg(Expr::col(UserData::Gender)).ne(""))
+                .to_owned();
+            manager.exec_stmt(copy).await?;
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(UserData::Table)
+                        .drop_column(UserData::Gender)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserData::Table)
+                    .add_column(
+                        ColumnDef::new(UserData::Gender)
+                            .text()
+                            .default(Expr::value(NOT_GIVEN)),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        let copy = Query::update()
+            .table(UserData::Table)
+            .value(UserData::Gender, Expr::col(UserData::Pronouns))
+            .and_where(Expr::col(UserData::Pronouns).is_not_null())
+            .to_owned();
+        manager.exec_stmt(copy).await?;
+        for column in [UserData::Visibility, UserData::Pronouns] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(UserData::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+const NOT_GIVEN: &str = "not given";
+
+#[derive(Iden)]
+#[iden = "TRIM"]
+struct Trim;
+
+#[derive(Iden, Clone, Copy)]
+enum UserData {
+    Table,
+    Gender,
+    Pronouns,
+    Visibility,
+}
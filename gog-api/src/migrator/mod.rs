@@ -5,6 +5,17 @@ mod m00000000_000004_expand_user_data_table;
 mod m00000000_000005_create_pfp_table;
 mod m00000000_000006_create_posts_table;
 mod m00000000_000007_create_comments_table;
+mod m00000000_000008_create_oauth_tables;
+mod m00000000_000009_add_roles_to_login_table;
+mod m00000000_000010_create_reports_table;
+mod m00000000_000011_create_invites_table;
+mod m00000000_000012_create_pfp_variants_table;
+mod m00000000_000013_add_blob_keys;
+mod m00000000_000014_add_pfp_versions;
+mod m00000000_000015_create_post_attachments_table;
+mod m00000000_000016_extend_user_profiles;
+mod m00000000_000017_replace_gender_with_pronouns;
+pub mod schema;
 use sea_orm_migration::prelude::*;
 pub struct Migrator;
 
@@ -19,6 +30,16 @@ impl MigratorTrait for Migrator {
             Box::new(m00000000_000005_create_pfp_table::Migration {}),
             Box::new(m00000000_000006_create_posts_table::Migration {}),
             Box::new(m00000000_000007_create_comments_table::Migration {}),
+            Box::new(m00000000_000008_create_oauth_tables::Migration {}),
+            Box::new(m00000000_000009_add_roles_to_login_table::Migration {}),
+            Box::new(m00000000_000010_create_reports_table::Migration {}),
+            Box::new(m00000000_000011_create_invites_table::Migration {}),
+            Box::new(m00000000_000012_create_pfp_variants_table::Migration {}),
+            Box::new(m00000000_000013_add_blob_keys::Migration {}),
+            Box::new(m00000000_000014_add_pfp_versions::Migration {}),
+            Box::new(m00000000_000015_create_post_attachments_table::Migration {}),
+            Box::new(m00000000_000016_extend_user_profiles::Migration {}),
+            Box::new(m00000000_000017_replace_gender_with_pronouns::Migration {}),
         ]
     }
 }
@@ -0,0 +1,520 @@
+//! Compares database schemas as SQLite or Postgres report them, against each other and against
+//! the entities. SQLite is read with `PRAGMA` statements, Postgres from its catalog
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use sea_orm::{
+    ConnectOptions, ConnectionTrait, Database, DatabaseConnection, DbBackend, DbErr, EntityTrait,
+    IdenStatic, Iterable, PrimaryKeyToColumn, Statement,
+};
+use sea_orm_migration::MigratorTrait;
+
+use super::Migrator;
+use crate::entity;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ColumnSchema {
+    /// `None` where it is not known, like for entities
+    pub affinity: Option<&'static str>,
+    pub not_null: bool,
+    pub primary_key: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TableSchema {
+    pub columns: BTreeMap<String, ColumnSchema>,
+    /// `column -> table.column on delete action`, `None` where it is not known
+    pub foreign_keys: Option<BTreeSet<String>>,
+    /// `name(columns)`, only indexes created explicitly
+    pub indexes: Option<BTreeSet<String>>,
+}
+
+pub type Schema = BTreeMap<String, TableSchema>;
+
+/// Declared types map to these the way SQLite decides how to store values
+fn affinity(declared: &str) -> &'static str {
+    let declared = declared.to_ascii_uppercase();
+    if declared.contains("INT") {
+        "integer"
+    } else if ["CHAR", "CLOB", "TEXT"]
+        .iter()
+        .any(|t| declared.contains(t))
+    {
+        "text"
+    } else if declared.is_empty() || declared.contains("BLOB") {
+        "blob"
+    } else if ["REAL", "FLOA", "DOUB"]
+        .iter()
+        .any(|t| declared.contains(t))
+    {
+        "real"
+    } else {
+        "numeric"
+    }
+}
+
+async fn pragma(
+    db: &impl ConnectionTrait,
+    pragma: &str,
+    arg: &str,
+) -> Result<Vec<sea_orm::QueryResult>, DbErr> {
+    db.query_all(Statement::from_string(
+        DbBackend::Sqlite,
+        format!("PRAGMA {}('{}')", pragma, arg.replace('\'', "''")),
+    ))
+    .await
+}
+
+/// Every table except the ones of SQLite and of the migrator
+pub async fn read_schema(db: &impl ConnectionTrait) -> Result<Schema, DbErr> {
+    match db.get_database_backend() {
+        DbBackend::Sqlite => read_sqlite_schema(db).await,
+        DbBackend::Postgres => read_postgres_schema(db).await,
+        backend => Err(DbErr::Custom(format!(
+            "schema checks support SQLite and Postgres, this database is {:?}",
+            backend
+        ))),
+    }
+}
+
+async fn read_sqlite_schema(db: &impl ConnectionTrait) -> Result<Schema, DbErr> {
+    let tables = db
+        .query_all(Statement::from_string(
+            DbBackend::Sqlite,
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' AND name <> 'seaql_migrations'",
+        ))
+        .await?;
+    let mut schema = Schema::new();
+    for table in tables {
+        let name: String = table.try_get("", "name")?;
+        let mut info = TableSchema::default();
+        for column in pragma(db, "table_info", &name).await? {
+            let declared: String = column.try_get("", "type")?;
+            let not_null: i32 = column.try_get("", "notnull")?;
+            let pk: i32 = column.try_get("", "pk")?;
+            info.columns.insert(
+                column.try_get("", "name")?,
+                ColumnSchema {
+                    affinity: Some(affinity(&declared)),
+                    // a primary key holds no NULL as far as the entities are concerned
+                    not_null: not_null != 0 || pk != 0,
+                    primary_key: pk != 0,
+                },
+            );
+        }
+        let mut foreign_keys = BTreeSet::new();
+        for key in pragma(db, "foreign_key_list", &name).await? {
+            let from: String = key.try_get("", "from")?;
+            let table: String = key.try_get("", "table")?;
+            let to: String = key.try_get("", "to")?;
+            let on_delete: String = key.try_get("", "on_delete")?;
+            foreign_keys.insert(format!(
+                "{} -> {}.{} on delete {}",
+                from,
+                table,
+                to,
+                on_delete.to_ascii_lowercase()
+            ));
+        }
+        let mut indexes = BTreeSet::new();
+        for index in pragma(db, "index_list", &name).await? {
+            let origin: String = index.try_get("", "origin")?;
+            if origin != "c" {
+                continue;
+            }
+            let index_name: String = index.try_get("", "name")?;
+            let mut columns = Vec::new();
+            for column in pragma(db, "index_info", &index_name).await? {
+                columns.push(column.try_get::<String>("", "name")?);
+            }
+            indexes.insert(format!("{}({})", index_name, columns.join(", ")));
+        }
+        info.foreign_keys = Some(foreign_keys);
+        info.indexes = Some(indexes);
+        schema.insert(name, info);
+    }
+    Ok(schema)
+}
+
+/// Postgres types grouped like SQLite affinities, so that both read alike
+fn postgres_affinity(data_type: &str) -> &'static str {
+    match data_type {
+        "smallint" | "integer" | "bigint" => "integer",
+        "text" | "character" | "character varying" => "text",
+        "bytea" => "blob",
+        "real" | "double precision" => "real",
+        _ => "numeric",
+    }
+}
+
+fn postgres_on_delete(action: &str) -> &'static str {
+    match action {
+        "r" => "restrict",
+        "c" => "cascade",
+        "n" => "set null",
+        "d" => "set default",
+        _ => "no action",
+    }
+}
+
+/// The tables of the current schema, the one migrations create their tables in
+async fn read_postgres_schema(db: &impl ConnectionTrait) -> Result<Schema, DbErr> {
+    let query =
+        |sql: &str| db.query_all(Statement::from_string(DbBackend::Postgres, sql.to_owned()));
+    let mut schema = Schema::new();
+    for column in query(
+        "SELECT c.table_name::text AS table_name, c.column_name::text AS column_name, \
+            c.data_type::text AS data_type, c.is_nullable = 'YES' AS nullable, \
+            EXISTS (SELECT 1 FROM information_schema.table_constraints tc \
+                JOIN information_schema.key_column_usage k \
+                ON k.constraint_schema = tc.constraint_schema AND k.constraint_name = tc.constraint_name \
+                WHERE tc.constraint_type = 'PRIMARY KEY' AND tc.table_schema = c.table_schema \
+                AND tc.table_name = c.table_name AND k.column_name = c.column_name) AS pk \
+        FROM information_schema.columns c \
+        JOIN information_schema.tables t \
+            ON t.table_schema = c.table_schema AND t.table_name = c.table_name \
+        WHERE c.table_schema = current_schema() AND t.table_type = 'BASE TABLE' \
+            AND c.table_name <> 'seaql_migrations'",
+    )
+    .await?
+    {
+        let table: String = column.try_get("", "table_name")?;
+        let data_type: String = column.try_get("", "data_type")?;
+        let nullable: bool = column.try_get("", "nullable")?;
+        let pk: bool = column.try_get("", "pk")?;
+        let info = schema.entry(table).or_insert_with(|| TableSchema {
+            foreign_keys: Some(BTreeSet::new()),
+            indexes: Some(BTreeSet::new()),
+            ..Default::default()
+        });
+        info.columns.insert(
+            column.try_get("", "column_name")?,
+            ColumnSchema {
+                affinity: Some(postgres_affinity(&data_type)),
+                not_null: !nullable || pk,
+                primary_key: pk,
+            },
+        );
+    }
+    for key in query(
+        "SELECT cl.relname::text AS table_name, a.attname::text AS from_column, \
+            rcl.relname::text AS to_table, ra.attname::text AS to_column, \
+            con.confdeltype::text AS on_delete \
+        FROM pg_constraint con \
+        JOIN pg_class cl ON cl.oid = con.conrelid \
+        JOIN pg_class rcl ON rcl.oid = con.confrelid \
+        JOIN LATERAL unnest(con.conkey, con.confkey) AS k(from_num, to_num) ON true \
+        JOIN pg_attribute a ON a.attrelid = con.conrelid AND a.attnum = k.from_num \
+        JOIN pg_attribute ra ON ra.attrelid = con.confrelid AND ra.attnum = k.to_num \
+        WHERE con.contype = 'f' AND cl.relnamespace = current_schema()::regnamespace",
+    )
+    .await?
+    {
+        let table: String = key.try_get("", "table_name")?;
+        let from: String = key.try_get("", "from_column")?;
+        let to_table: String = key.try_get("", "to_table")?;
+        let to: String = key.try_get("", "to_column")?;
+        let on_delete: String = key.try_get("", "on_delete")?;
+        if let Some(keys) = schema.get_mut(&table).and_then(|t| t.foreign_keys.as_mut()) {
+            keys.insert(format!(
+                "{} -> {}.{} on delete {}",
+                from,
+                to_table,
+                to,
+                postgres_on_delete(&on_delete)
+            ));
+        }
+    }
+    // only indexes created explicitly, not the ones behind primary keys and constraints
+    let mut indexes: BTreeMap<(String, String), Vec<String>> = BTreeMap::new();
+    for column in query(
+        "SELECT t.relname::text AS table_name, i.relname::text AS index_name, \
+            a.attname::text AS column_name \
+        FROM pg_index ix \
+        JOIN pg_class i ON i.oid = ix.indexrelid \
+        JOIN pg_class t ON t.oid = ix.indrelid \
+        JOIN LATERAL unnest(ix.indkey::int2[]) WITH ORDINALITY AS k(attnum, ord) ON true \
+        JOIN pg_attribute a ON a.attrelid = t.oid AND a.attnum = k.attnum \
+        WHERE t.relnamespace = current_schema()::regnamespace \
+            AND NOT EXISTS (SELECT 1 FROM pg_constraint c WHERE c.conindid = ix.indexrelid) \
+        ORDER BY t.relname, i.relname, k.ord",
+    )
+    .await?
+    {
+        indexes
+            .entry((
+                column.try_get("", "table_name")?,
+                column.try_get("", "index_name")?,
+            ))
+            .or_default()
+            .push(column.try_get("", "column_name")?);
+    }
+    for ((table, index), columns) in indexes {
+        if let Some(set) = schema.get_mut(&table).and_then(|t| t.indexes.as_mut()) {
+            set.insert(format!("{}({})", index, columns.join(", ")));
+        }
+    }
+    Ok(schema)
+}
+
+/// A database with nothing but one connection, the memory of each connection is its own database
+pub async fn scratch_database() -> Result<DatabaseConnection, DbErr> {
+    let mut options = ConnectOptions::new("sqlite::memory:");
+    options
+        .max_connections(1)
+        .min_connections(1)
+        .sqlx_logging(false);
+    Database::connect(options).await
+}
+
+/// The schema an SQL script like `data/sqlite3_schema.sql` creates
+pub async fn script_schema(sql: &str) -> Result<Schema, DbErr> {
+    let db = scratch_database().await?;
+    db.execute_unprepared(sql).await?;
+    read_schema(&db).await
+}
+
+fn entity_table<E: EntityTrait>(schema: &mut Schema) {
+    let keys = E::PrimaryKey::iter()
+        .map(|k| k.into_column().as_str().to_owned())
+        .collect::<BTreeSet<_>>();
+    let mut table = TableSchema::default();
+    for column in E::Column::iter() {
+        let name = column.as_str().to_owned();
+        let primary_key = keys.contains(&name);
+        table.columns.insert(
+            name,
+            ColumnSchema {
+                affinity: None,
+                not_null: primary_key || !sea_orm::ColumnTrait::def(&column).is_null(),
+                primary_key,
+            },
+        );
+    }
+    schema.insert(E::default().table_name().to_owned(), table);
+}
+
+/// Columns the entities expect, they know nothing about types, keys to other tables or indexes
+pub fn entity_schema() -> Schema {
+    use entity::*;
+    let mut schema = Schema::new();
+    entity_table::<comments::Entity>(&mut schema);
+    entity_table::<invites::Entity>(&mut schema);
+    entity_table::<login_data::Entity>(&mut schema);
+    entity_table::<oauth_clients::Entity>(&mut schema);
+    entity_table::<oauth_codes::Entity>(&mut schema);
+    entity_table::<oauth_tokens::Entity>(&mut schema);
+    entity_table::<post_attachments::Entity>(&mut schema);
+    entity_table::<posts::Entity>(&mut schema);
+    entity_table::<reports::Entity>(&mut schema);
+    entity_table::<user_data::Entity>(&mut schema);
+    entity_table::<user_pfp::Entity>(&mut schema);
+    entity_table::<user_pfp_variants::Entity>(&mut schema);
+    schema
+}
+
+/// How `actual` differs from `expected`, one line per difference.
+/// Whatever `expected` does not know, like the types of entity columns, is not compared
+pub fn diff(expected: &Schema, actual: &Schema) -> Vec<String> {
+    let mut problems = Vec::new();
+    for name in expected.keys().filter(|t| !actual.contains_key(*t)) {
+        problems.push(format!("table `{}` is missing", name));
+    }
+    for name in actual.keys().filter(|t| !expected.contains_key(*t)) {
+        problems.push(format!("table `{}` is not expected", name));
+    }
+    for (name, want) in expected {
+        let Some(have) = actual.get(name) else {
+            continue;
+        };
+        for (column, want) in &want.columns {
+            let Some(have) = have.columns.get(column) else {
+                problems.push(format!("column `{}.{}` is missing", name, column));
+                continue;
+            };
+            if let (Some(want), Some(have)) = (want.affinity, have.affinity) {
+                if want != have {
+                    problems.push(format!(
+                        "column `{}.{}` stores {} instead of {}",
+                        name, column, have, want
+                    ));
+                }
+            }
+            if want.not_null != have.not_null {
+                problems.push(format!(
+                    "column `{}.{}` is {} but should be {}",
+                    name,
+                    column,
+                    if have.not_null {
+                        "NOT NULL"
+                    } else {
+                        "nullable"
+                    },
+                    if want.not_null {
+                        "NOT NULL"
+                    } else {
+                        "nullable"
+                    },
+                ));
+            }
+            if want.primary_key != have.primary_key {
+                problems.push(format!(
+                    "column `{}.{}` is {}part of the primary key",
+                    name,
+                    column,
+                    if have.primary_key { "" } else { "not " }
+                ));
+            }
+        }
+        for column in have
+            .columns
+            .keys()
+            .filter(|c| !want.columns.contains_key(*c))
+        {
+            problems.push(format!("column `{}.{}` is not expected", name, column));
+        }
+        let mut sets =
+            |kind: &str, want: &Option<BTreeSet<String>>, have: &Option<BTreeSet<String>>| {
+                let (Some(want), Some(have)) = (want, have) else {
+                    return;
+                };
+                for missing in want.difference(have) {
+                    problems.push(format!("{} `{}: {}` is missing", kind, name, missing));
+                }
+                for extra in have.difference(want) {
+                    problems.push(format!("{} `{}: {}` is not expected", kind, name, extra));
+                }
+            };
+        sets("foreign key", &want.foreign_keys, &have.foreign_keys);
+        sets("index", &want.indexes, &have.indexes);
+    }
+    problems
+}
+
+/// Applies every migration on a scratch database, rolling each one back and applying it again
+/// right away. Each rollback has to restore the schema from before, each second run has to
+/// arrive at the same schema as the first
+pub async fn check_rollbacks() -> Result<Vec<String>, DbErr> {
+    check_rollbacks_on(&scratch_database().await?).await
+}
+
+/// [`check_rollbacks`] on `db`, which has to be empty
+pub async fn check_rollbacks_on(db: &DatabaseConnection) -> Result<Vec<String>, DbErr> {
+    let mut problems = Vec::new();
+    for migration in Migrator::get_pending_migrations(db).await? {
+        let name = migration.name().to_owned();
+        let before = read_schema(db).await?;
+        Migrator::up(db, Some(1)).await?;
+        let after = read_schema(db).await?;
+        if let Err(e) = Migrator::down(db, Some(1)).await {
+            problems.push(format!("{}: rolling back failed: {}", name, e));
+            break;
+        }
+        problems.extend(
+            diff(&before, &read_schema(db).await?)
+                .into_iter()
+                .map(|p| format!("{}: after rolling back {}", name, p)),
+        );
+        Migrator::up(db, Some(1)).await?;
+        problems.extend(
+            diff(&after, &read_schema(db).await?)
+                .into_iter()
+                .map(|p| format!("{}: after applying it again {}", name, p)),
+        );
+    }
+    Ok(problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn migrations_roll_back_and_apply_again() {
+        let db = scratch_database().await.unwrap();
+        Migrator::up(&db, None).await.unwrap();
+        let first = read_schema(&db).await.unwrap();
+        Migrator::down(&db, None).await.unwrap();
+        assert_eq!(read_schema(&db).await.unwrap(), Schema::new());
+        Migrator::up(&db, None).await.unwrap();
+        assert_eq!(
+            diff(&first, &read_schema(&db).await.unwrap()),
+            Vec::<String>::new()
+        );
+    }
+
+    #[actix_web::test]
+    async fn every_rollback_restores_the_schema() {
+        assert_eq!(check_rollbacks().await.unwrap(), Vec::<String>::new());
+    }
+
+    /// A fresh schema on the Postgres of `MAGOG_TEST_POSTGRES_URL`, dropped by [`drop_postgres`]
+    async fn postgres_database() -> Option<(DatabaseConnection, String)> {
+        let Ok(url) = std::env::var("MAGOG_TEST_POSTGRES_URL") else {
+            eprintln!("MAGOG_TEST_POSTGRES_URL is not set, skipping the Postgres test");
+            return None;
+        };
+        let name = format!("gog_test_{}", uuid::Uuid::new_v4().simple());
+        let admin = Database::connect(&url).await.unwrap();
+        admin
+            .execute_unprepared(&format!("CREATE SCHEMA {}", name))
+            .await
+            .unwrap();
+        let mut options = ConnectOptions::new(url);
+        options
+            .max_connections(1)
+            .sqlx_logging(false)
+            .set_schema_search_path(name.clone());
+        Some((Database::connect(options).await.unwrap(), name))
+    }
+
+    async fn drop_postgres(db: DatabaseConnection, name: &str) {
+        db.execute_unprepared(&format!("DROP SCHEMA {} CASCADE", name))
+            .await
+            .unwrap();
+    }
+
+    #[actix_web::test]
+    async fn postgres_migrations_roll_back_and_apply_again() {
+        let Some((db, name)) = postgres_database().await else {
+            return;
+        };
+        Migrator::up(&db, None).await.unwrap();
+        let first = read_schema(&db).await.unwrap();
+        assert_eq!(diff(&entity_schema(), &first), Vec::<String>::new());
+        // types are named differently, the keys and indexes have to be the same
+        let sqlite = scratch_database().await.unwrap();
+        Migrator::up(&sqlite, None).await.unwrap();
+        let sqlite = read_schema(&sqlite).await.unwrap();
+        for (name, table) in &first {
+            assert_eq!(table.foreign_keys, sqlite[name].foreign_keys, "{}", name);
+            assert_eq!(table.indexes, sqlite[name].indexes, "{}", name);
+        }
+        Migrator::down(&db, None).await.unwrap();
+        assert_eq!(read_schema(&db).await.unwrap(), Schema::new());
+        Migrator::up(&db, None).await.unwrap();
+        assert_eq!(
+            diff(&first, &read_schema(&db).await.unwrap()),
+            Vec::<String>::new()
+        );
+        drop_postgres(db, &name).await;
+    }
+
+    #[actix_web::test]
+    async fn postgres_every_rollback_restores_the_schema() {
+        let Some((db, name)) = postgres_database().await else {
+            return;
+        };
+        let problems = check_rollbacks_on(&db).await;
+        drop_postgres(db, &name).await;
+        assert_eq!(problems.unwrap(), Vec::<String>::new());
+    }
+
+    #[actix_web::test]
+    async fn migrated_schema_matches_the_entities() {
+        let db = scratch_database().await.unwrap();
+        Migrator::up(&db, None).await.unwrap();
+        let live = read_schema(&db).await.unwrap();
+        assert_eq!(diff(&entity_schema(), &live), Vec::<String>::new());
+    }
+}
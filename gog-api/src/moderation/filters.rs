@@ -0,0 +1,182 @@
+use std::borrow::Cow;
+
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use regex::{Regex, RegexBuilder};
+use sea_orm::{ColumnTrait, DbErr, EntityTrait, QueryFilter, QueryOrder, QuerySelect};
+use validator::ValidationError;
+
+use super::{ContentContext, ContentFilter, ContentKind, FilterOutcome, MatchAction};
+use crate::entity::{comments, posts};
+
+/// How many recent posts or comments of a user are compared against new content
+const DUPLICATE_LOOKBACK: u64 = 50;
+
+fn rejection(code: &'static str, message: impl Into<Cow<'static, str>>) -> ValidationError {
+    ValidationError::new(code).with_message(message.into())
+}
+
+/// Blocks or masks everything matching a regex, word lists compile to one as well
+pub struct PatternFilter {
+    pattern: Regex,
+    action: MatchAction,
+}
+
+impl PatternFilter {
+    pub fn from_words(words: &[String], action: MatchAction) -> Result<Self, regex::Error> {
+        let alternatives = words
+            .iter()
+            .map(|w| regex::escape(w.trim()))
+            .filter(|w| !w.is_empty())
+            .collect::<Vec<_>>()
+            .join("|");
+        if alternatives.is_empty() {
+            // an empty alternation would match at every word boundary
+            return Self::from_pattern(r"[^\s\S]", action);
+        }
+        Self::from_pattern(&format!(r"\b(?:{alternatives})\b"), action)
+    }
+    pub fn from_pattern(pattern: &str, action: MatchAction) -> Result<Self, regex::Error> {
+        let pattern = RegexBuilder::new(pattern).case_insensitive(true).build()?;
+        Ok(Self { pattern, action })
+    }
+}
+
+#[async_trait]
+impl ContentFilter for PatternFilter {
+    fn name(&self) -> &'static str {
+        "pattern"
+    }
+    async fn check(
+        &self,
+        content: &str,
+        _ctx: &ContentContext<'_>,
+    ) -> Result<FilterOutcome, DbErr> {
+        if !self.pattern.is_match(content) {
+            return Ok(FilterOutcome::Accept);
+        }
+        Ok(match self.action {
+            MatchAction::Block => FilterOutcome::Reject(rejection(
+                "blocked_content",
+                "content contains disallowed words",
+            )),
+            MatchAction::Mask => FilterOutcome::Replace(
+                self.pattern
+                    .replace_all(content, |caps: &regex::Captures| {
+                        "*".repeat(caps[0].chars().count())
+                    })
+                    .into_owned(),
+            ),
+        })
+    }
+}
+
+pub struct LinkLimitFilter {
+    max: usize,
+    link: Regex,
+}
+
+impl LinkLimitFilter {
+    pub fn new(max: usize) -> Self {
+        Self {
+            max,
+            link: Regex::new(r"(?i)\b(?:https?://|www\.)\S+").unwrap(),
+        }
+    }
+}
+
+#[async_trait]
+impl ContentFilter for LinkLimitFilter {
+    fn name(&self) -> &'static str {
+        "links"
+    }
+    async fn check(
+        &self,
+        content: &str,
+        _ctx: &ContentContext<'_>,
+    ) -> Result<FilterOutcome, DbErr> {
+        let count = self.link.find_iter(content).count();
+        if count <= self.max {
+            return Ok(FilterOutcome::Accept);
+        }
+        let mut error = rejection(
+            "too_many_links",
+            format!("content may contain at most {} links", self.max),
+        );
+        error.add_param("max".into(), &self.max);
+        Ok(FilterOutcome::Reject(error))
+    }
+}
+
+/// Rejects content the same user already posted within the window
+pub struct DuplicateFilter {
+    window: Duration,
+}
+
+impl DuplicateFilter {
+    pub fn new(window_minutes: i64) -> Self {
+        Self {
+            window: Duration::minutes(window_minutes),
+        }
+    }
+}
+
+fn normalize(content: &str) -> String {
+    content
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+#[async_trait]
+impl ContentFilter for DuplicateFilter {
+    fn name(&self) -> &'static str {
+        "duplicates"
+    }
+    async fn check(&self, content: &str, ctx: &ContentContext<'_>) -> Result<FilterOutcome, DbErr> {
+        let since = Utc::now() - self.window;
+        let db = ctx.db;
+        let recent: Vec<(uuid::Uuid, String)> = match ctx.kind {
+            ContentKind::Post => {
+                posts::Entity::find()
+                    .select_only()
+                    .column(posts::Column::PostId)
+                    .column(posts::Column::Content)
+                    .filter(posts::Column::UserId.eq(ctx.user_id))
+                    .filter(posts::Column::Posted.gt(since))
+                    .order_by_desc(posts::Column::Posted)
+                    .limit(DUPLICATE_LOOKBACK)
+                    .into_tuple()
+                    .all(db)
+                    .await?
+            }
+            ContentKind::Comment => {
+                comments::Entity::find()
+                    .select_only()
+                    .column(comments::Column::CommentId)
+                    .column(comments::Column::Content)
+                    .filter(comments::Column::UserId.eq(ctx.user_id))
+                    .filter(comments::Column::Posted.gt(since))
+                    .order_by_desc(comments::Column::Posted)
+                    .limit(DUPLICATE_LOOKBACK)
+                    .into_tuple()
+                    .all(db)
+                    .await?
+            }
+        };
+        let content = normalize(content);
+        let duplicate = recent
+            .iter()
+            .filter(|(id, _)| Some(*id) != ctx.editing)
+            .any(|(_, c)| normalize(c) == content);
+        Ok(if duplicate {
+            FilterOutcome::Reject(rejection(
+                "duplicate_content",
+                "you have already posted this recently",
+            ))
+        } else {
+            FilterOutcome::Accept
+        })
+    }
+}
@@ -0,0 +1,211 @@
+mod filters;
+
+use std::sync::{Arc, RwLock};
+
+use async_trait::async_trait;
+use log::{info, warn};
+use sea_orm::{DatabaseConnection, DbErr};
+use serde::Deserialize;
+use thiserror::Error;
+use uuid::Uuid;
+use validator::{ValidationError, ValidationErrors};
+
+pub use filters::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContentKind {
+    Post,
+    Comment,
+}
+
+/// What a filter knows about the content it is checking
+pub struct ContentContext<'a> {
+    pub kind: ContentKind,
+    pub user_id: Uuid,
+    /// Set when an existing post or comment is being edited
+    pub editing: Option<Uuid>,
+    pub db: &'a DatabaseConnection,
+}
+
+pub enum FilterOutcome {
+    Accept,
+    /// Accept the content in a changed form, e.g. with masked words
+    Replace(String),
+    Reject(ValidationError),
+}
+
+#[async_trait]
+pub trait ContentFilter: Send + Sync {
+    fn name(&self) -> &'static str;
+    async fn check(&self, content: &str, ctx: &ContentContext<'_>) -> Result<FilterOutcome, DbErr>;
+}
+
+#[derive(Debug, Error)]
+pub enum ModerationError {
+    #[error("Content rejected")]
+    Rejected(ValidationErrors),
+    #[error("Database error")]
+    DatabaseError {
+        #[from]
+        source: DbErr,
+    },
+}
+
+#[derive(Debug, Error)]
+pub enum ModerationConfigError {
+    #[error("Io error")]
+    IoError {
+        #[from]
+        source: std::io::Error,
+    },
+    #[error("Config parse error: {source}")]
+    ParseError {
+        #[from]
+        source: toml::de::Error,
+    },
+    #[error("Invalid pattern: {source}")]
+    RegexError {
+        #[from]
+        source: regex::Error,
+    },
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchAction {
+    Block,
+    Mask,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ModerationConfig {
+    #[serde(default)]
+    pub wordlist: Vec<WordlistConfig>,
+    #[serde(default)]
+    pub regex: Vec<RegexConfig>,
+    pub links: Option<LinksConfig>,
+    pub duplicates: Option<DuplicatesConfig>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct WordlistConfig {
+    pub words: Vec<String>,
+    pub action: MatchAction,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RegexConfig {
+    pub pattern: String,
+    pub action: MatchAction,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct LinksConfig {
+    pub max: usize,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct DuplicatesConfig {
+    pub window_minutes: i64,
+}
+
+/// An ordered list of filters, each one sees the output of the previous one
+#[derive(Default)]
+pub struct ModerationPipeline {
+    filters: Vec<Box<dyn ContentFilter>>,
+}
+
+impl ModerationPipeline {
+    pub fn from_config(config: &ModerationConfig) -> Result<Self, ModerationConfigError> {
+        let mut filters: Vec<Box<dyn ContentFilter>> = vec![];
+        for list in &config.wordlist {
+            filters.push(Box::new(PatternFilter::from_words(
+                &list.words,
+                list.action,
+            )?));
+        }
+        for re in &config.regex {
+            filters.push(Box::new(PatternFilter::from_pattern(
+                &re.pattern,
+                re.action,
+            )?));
+        }
+        if let Some(links) = &config.links {
+            filters.push(Box::new(LinkLimitFilter::new(links.max)));
+        }
+        if let Some(dup) = &config.duplicates {
+            filters.push(Box::new(DuplicateFilter::new(dup.window_minutes)));
+        }
+        Ok(Self { filters })
+    }
+    pub async fn run(
+        &self,
+        content: &str,
+        ctx: &ContentContext<'_>,
+    ) -> Result<String, ModerationError> {
+        let mut content = content.to_owned();
+        let mut errors = ValidationErrors::new();
+        for filter in &self.filters {
+            match filter.check(&content, ctx).await? {
+                FilterOutcome::Accept => (),
+                FilterOutcome::Replace(c) => content = c,
+                FilterOutcome::Reject(e) => {
+                    log::debug!("content rejected by the {} filter", filter.name());
+                    errors.add("content", e);
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(content)
+        } else {
+            Err(ModerationError::Rejected(errors))
+        }
+    }
+}
+
+/// Holds the active pipeline and rebuilds it from the config file on demand
+pub struct ModerationService {
+    config_path: String,
+    pipeline: RwLock<Arc<ModerationPipeline>>,
+}
+
+impl ModerationService {
+    /// A missing config file means no filtering, a broken one is an error
+    pub fn load(config_path: &str) -> Result<Self, ModerationConfigError> {
+        let service = Self {
+            config_path: config_path.to_owned(),
+            pipeline: RwLock::new(Arc::new(ModerationPipeline::default())),
+        };
+        service.reload()?;
+        Ok(service)
+    }
+    pub fn reload(&self) -> Result<(), ModerationConfigError> {
+        let config = match std::fs::read_to_string(&self.config_path) {
+            Ok(text) => toml::from_str::<ModerationConfig>(&text)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                warn!(
+                    "no moderation config at `{}`, content will not be filtered",
+                    self.config_path
+                );
+                ModerationConfig::default()
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let pipeline = ModerationPipeline::from_config(&config)?;
+        info!(
+            "loaded moderation pipeline with {} filters",
+            pipeline.filters.len()
+        );
+        *self.pipeline.write().unwrap() = Arc::new(pipeline);
+        Ok(())
+    }
+    /// The lock is released before the pipeline runs, reloads never wait on requests
+    pub fn pipeline(&self) -> Arc<ModerationPipeline> {
+        self.pipeline.read().unwrap().clone()
+    }
+}
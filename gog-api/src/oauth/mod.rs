@@ -0,0 +1,176 @@
+use std::path::Path;
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use log::{info, warn};
+use rsa::{
+    pkcs1::{DecodeRsaPrivateKey, EncodeRsaPrivateKey},
+    pkcs8::LineEnding,
+    traits::PublicKeyParts,
+    RsaPrivateKey,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+pub const ACCESS_TOKEN_TTL: i64 = 60 * 60;
+pub const REFRESH_TOKEN_TTL: i64 = 60 * 60 * 24 * 30;
+pub const AUTHORIZATION_CODE_TTL: i64 = 60;
+pub const SUPPORTED_SCOPES: [&str; 2] = ["openid", "profile"];
+
+#[derive(Debug, Error)]
+pub enum KeyError {
+    #[error("Io error")]
+    IoError {
+        #[from]
+        source: std::io::Error,
+    },
+    #[error("RSA error")]
+    RsaError {
+        #[from]
+        source: rsa::Error,
+    },
+    #[error("PKCS#1 error")]
+    Pkcs1Error {
+        #[from]
+        source: rsa::pkcs1::Error,
+    },
+    #[error("JWT error")]
+    JwtError {
+        #[from]
+        source: jsonwebtoken::errors::Error,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Jwk {
+    pub kty: String,
+    #[serde(rename = "use")]
+    pub use_: String,
+    pub alg: String,
+    pub kid: String,
+    pub n: String,
+    pub e: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct IdTokenClaims {
+    pub iss: String,
+    pub sub: String,
+    pub aud: String,
+    pub exp: i64,
+    pub iat: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    pub preferred_username: String,
+}
+
+/// Signing keys and issuer identity of the OAuth2 / OpenID Connect provider
+pub struct OAuthProvider {
+    issuer: String,
+    /// The consent screen lives in gog-front, not in the api
+    authorization_endpoint: String,
+    encoding_key: EncodingKey,
+    jwk: Jwk,
+}
+
+impl OAuthProvider {
+    ///
+    /// Loads the RSA signing key from `key_path`, generating and saving
+    /// a new one when the file does not exist yet.
+    ///
+    pub fn load_or_generate(
+        issuer: &str,
+        frontend: &str,
+        key_path: &str,
+    ) -> Result<Self, KeyError> {
+        let key = if Path::new(key_path).exists() {
+            let pem = std::fs::read_to_string(key_path)?;
+            RsaPrivateKey::from_pkcs1_pem(&pem)?
+        } else {
+            warn!(
+                "no oauth signing key at `{}`, generating a new one",
+                key_path
+            );
+            let key = RsaPrivateKey::new(&mut OsRng, 2048)?;
+            let pem = key.to_pkcs1_pem(LineEnding::LF)?;
+            std::fs::write(key_path, pem.as_bytes())?;
+            key
+        };
+        let pem = key.to_pkcs1_pem(LineEnding::LF)?;
+        let encoding_key = EncodingKey::from_rsa_pem(pem.as_bytes())?;
+
+        let n = key.n().to_bytes_be();
+        let e = key.e().to_bytes_be();
+        let kid = URL_SAFE_NO_PAD.encode(&Sha256::digest(&n)[..8]);
+        info!("loaded oauth signing key with kid {}", kid);
+
+        Ok(Self {
+            issuer: issuer.trim_end_matches('/').to_owned(),
+            authorization_endpoint: format!("{}/oauth/authorize", frontend.trim_end_matches('/')),
+            encoding_key,
+            jwk: Jwk {
+                kty: "RSA".to_owned(),
+                use_: "sig".to_owned(),
+                alg: "RS256".to_owned(),
+                kid,
+                n: URL_SAFE_NO_PAD.encode(n),
+                e: URL_SAFE_NO_PAD.encode(e),
+            },
+        })
+    }
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+    pub fn authorization_endpoint(&self) -> &str {
+        &self.authorization_endpoint
+    }
+    pub fn jwk(&self) -> &Jwk {
+        &self.jwk
+    }
+    pub fn sign_id_token(&self, claims: &IdTokenClaims) -> Result<String, KeyError> {
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some(self.jwk.kid.clone());
+        Ok(jsonwebtoken::encode(&header, claims, &self.encoding_key)?)
+    }
+}
+
+/// Generates a random url safe token
+pub fn random_token() -> String {
+    let mut buf = [0u8; 32];
+    OsRng.fill_bytes(&mut buf);
+    URL_SAFE_NO_PAD.encode(buf)
+}
+
+/// Tokens, codes and client secrets are only ever stored as this hash
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Checks a PKCE `code_verifier` against the stored S256 `code_challenge`
+pub fn verify_pkce(verifier: &str, challenge: &str) -> bool {
+    if verifier.len() < 43 || verifier.len() > 128 {
+        return false;
+    }
+    let computed = URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+    computed.as_bytes().len() == challenge.as_bytes().len()
+        && computed
+            .bytes()
+            .zip(challenge.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+/// Filters the requested scopes down to the supported ones, sorted and each named once
+pub fn normalize_scope(scope: Option<&str>) -> Vec<String> {
+    let mut scopes = scope
+        .unwrap_or("openid")
+        .split_whitespace()
+        .filter(|s| SUPPORTED_SCOPES.contains(s))
+        .map(|s| s.to_owned())
+        .collect::<Vec<_>>();
+    scopes.sort_unstable();
+    scopes.dedup();
+    scopes
+}
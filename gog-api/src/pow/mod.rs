@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use gog_commons::data_structures::{PowChallenge, PowSolution};
+use gog_commons::pow;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use thiserror::Error;
+
+pub const CHALLENGE_TTL: i64 = 10 * 60;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum PowError {
+    #[error("a proof of work solution is required")]
+    Missing,
+    #[error("challenge signature is invalid")]
+    BadSignature,
+    #[error("challenge has expired")]
+    Expired,
+    #[error("solution does not meet the difficulty")]
+    Unsolved,
+    #[error("challenge was already used")]
+    Replayed,
+}
+
+/// Issues signed registration puzzles and remembers solved ones until they expire.
+/// The signing key lives only in memory, a restart invalidates outstanding challenges
+pub struct PowGuard {
+    key: [u8; 32],
+    difficulty: u8,
+    used: Mutex<HashMap<String, i64>>,
+}
+
+impl PowGuard {
+    /// Difficulty is in leading zero bits, 0 turns the check off
+    pub fn new(difficulty: u8) -> Self {
+        let mut key = [0u8; 32];
+        OsRng.fill_bytes(&mut key);
+        Self {
+            key,
+            difficulty,
+            used: Mutex::new(HashMap::new()),
+        }
+    }
+    pub fn is_enabled(&self) -> bool {
+        self.difficulty > 0
+    }
+    fn mac(&self, salt: &str, difficulty: u8, expires: i64) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("hmac accepts any key length");
+        mac.update(format!("{}:{}:{}", salt, difficulty, expires).as_bytes());
+        mac
+    }
+    pub fn issue(&self) -> PowChallenge {
+        let salt = crate::oauth::random_token();
+        let expires = chrono::Utc::now().timestamp() + CHALLENGE_TTL;
+        let signature = hex::encode(
+            self.mac(&salt, self.difficulty, expires)
+                .finalize()
+                .into_bytes(),
+        );
+        PowChallenge {
+            salt,
+            difficulty: self.difficulty,
+            expires,
+            signature,
+        }
+    }
+    /// Checks the solution and reserves its challenge for the returned ticket, the
+    /// challenge does not pass again while the ticket lives or once it was redeemed
+    pub fn verify(&self, solution: Option<&PowSolution>) -> Result<PowTicket<'_>, PowError> {
+        if !self.is_enabled() {
+            return Ok(PowTicket {
+                guard: self,
+                salt: None,
+            });
+        }
+        let Some(solution) = solution else {
+            return Err(PowError::Missing);
+        };
+        let challenge = &solution.challenge;
+        let signature = hex::decode(&challenge.signature).map_err(|_| PowError::BadSignature)?;
+        self.mac(&challenge.salt, challenge.difficulty, challenge.expires)
+            .verify_slice(&signature)
+            .map_err(|_| PowError::BadSignature)?;
+        let now = chrono::Utc::now().timestamp();
+        if challenge.expires < now {
+            return Err(PowError::Expired);
+        }
+        if !pow::meets_difficulty(&challenge.salt, solution.nonce, challenge.difficulty) {
+            return Err(PowError::Unsolved);
+        }
+
+        let mut used = self.used.lock().unwrap();
+        used.retain(|_, expires| *expires >= now);
+        if used
+            .insert(challenge.salt.clone(), challenge.expires)
+            .is_some()
+        {
+            return Err(PowError::Replayed);
+        }
+        Ok(PowTicket {
+            guard: self,
+            salt: Some(challenge.salt.clone()),
+        })
+    }
+}
+
+/// A solution that passed [`PowGuard::verify`]. Only [`PowTicket::redeem`] uses it up,
+/// dropping the ticket instead lets the same solution be sent again, e.g. with another login
+#[must_use]
+pub struct PowTicket<'a> {
+    guard: &'a PowGuard,
+    salt: Option<String>,
+}
+
+impl PowTicket<'_> {
+    /// Call once whatever the solution was for is done
+    pub fn redeem(mut self) {
+        self.salt = None;
+    }
+}
+
+impl Drop for PowTicket<'_> {
+    fn drop(&mut self) {
+        if let Some(salt) = self.salt.take() {
+            self.guard.used.lock().unwrap().remove(&salt);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solved(guard: &PowGuard) -> PowSolution {
+        let challenge = guard.issue();
+        let nonce = pow::solve_range(&challenge.salt, challenge.difficulty, 0, u64::MAX).unwrap();
+        PowSolution { challenge, nonce }
+    }
+
+    #[test]
+    fn only_a_redeemed_solution_is_used_up() {
+        let guard = PowGuard::new(4);
+        let solution = solved(&guard);
+        let ticket = guard.verify(Some(&solution)).unwrap();
+        assert_eq!(
+            guard.verify(Some(&solution)).err(),
+            Some(PowError::Replayed)
+        );
+        drop(ticket);
+        guard.verify(Some(&solution)).unwrap().redeem();
+        assert_eq!(
+            guard.verify(Some(&solution)).err(),
+            Some(PowError::Replayed)
+        );
+        assert_eq!(guard.verify(None).err(), Some(PowError::Missing));
+    }
+}
@@ -0,0 +1,279 @@
+use super::attachments;
+use super::entity::prelude::*;
+use super::permissions::{can, AuthUser, Authorized};
+use super::{DbConnection, ServiceResult};
+use crate::backup::BackupService;
+use crate::cache::ResourceCache;
+use crate::entity::{comments, login_data, oauth_tokens, user_data};
+use crate::errors::ServiceError;
+use crate::moderation::ModerationService;
+use crate::session::TokenSession;
+use crate::storage::BlobStore;
+use actix_web::{
+    web::{self, Data, Json, Path, Query},
+    HttpResponse,
+};
+use gog_commons::data_structures::{AdminUserData, RoleUpdate};
+use log::info;
+use sea_orm::{
+    sea_query::Expr, ActiveValue, ColumnTrait, EntityTrait, QueryFilter, QueryOrder, QuerySelect,
+};
+use serde::Deserialize;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+pub fn configure_service(cfg: &mut web::ServiceConfig) {
+    let scope = web::scope("/admin")
+        .service(admin_users_list)
+        .service(admin_users_set_role)
+        .service(admin_users_suspend)
+        .service(admin_users_unsuspend)
+        .service(admin_users_logout)
+        .service(admin_posts_delete)
+        .service(admin_comments_delete)
+        .service(admin_moderation_reload)
+        .service(admin_cache_stats)
+        .service(admin_backups_list)
+        .service(admin_backups_create);
+    cfg.service(scope);
+}
+
+/// Rows listed when the query asks for no other amount
+const PAGE: u64 = 50;
+/// Most rows listed at once, whatever the query asks for
+const PAGE_MAX: u64 = 200;
+
+/// How many rows of a moderation list to load when `requested` were asked for
+pub(super) fn page(requested: Option<u64>) -> u64 {
+    requested.unwrap_or(PAGE).min(PAGE_MAX)
+}
+
+#[derive(Deserialize)]
+struct UserListQuery {
+    limit: Option<u64>,
+    offset: Option<u64>,
+}
+
+#[actix_web::get("users")]
+async fn admin_users_list(
+    _auth: Authorized<can::ListUsers>,
+    query: Query<UserListQuery>,
+    db: Data<DbConnection>,
+) -> ServiceResult {
+    let users = LoginData::find()
+        .find_also_related(user_data::Entity)
+        .order_by_asc(login_data::Column::Login)
+        .offset(query.offset)
+        .limit(page(query.limit))
+        .all(&db.db_connection)
+        .await?
+        .into_iter()
+        .map(|(login, data)| AdminUserData {
+            role: login.role(),
+            login: login.login,
+            id: login.user_id,
+            suspended: login.suspended,
+            invited_by: login.invited_by,
+            created: data.and_then(|d| d.created),
+        })
+        .collect::<Vec<_>>();
+    Ok(HttpResponse::Ok().json(users))
+}
+
+async fn find_target(login: &str, db: &DbConnection) -> Result<login_data::Model, ServiceError> {
+    LoginData::find_by_id(login)
+        .one(&db.db_connection)
+        .await?
+        .ok_or(ServiceError::UserNotFound)
+}
+
+#[actix_web::post("users/{login}/role")]
+async fn admin_users_set_role(
+    auth: Authorized<can::ManageRoles>,
+    login: Path<String>,
+    update: Json<RoleUpdate>,
+    db: Data<DbConnection>,
+) -> ServiceResult {
+    let target = find_target(&login, &db).await?;
+    if target.login == auth.user.login {
+        return Ok(HttpResponse::BadRequest()
+            .reason("cannot change own role")
+            .finish());
+    }
+    let role = update.into_inner().role;
+    let mut model: login_data::ActiveModel = target.into();
+    model.role = ActiveValue::Set(role.as_str().to_owned());
+    LoginData::update(model).exec(&db.db_connection).await?;
+    info!(
+        "{} set role of {} to {}",
+        auth.user.login,
+        login,
+        role.as_str()
+    );
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Nobody gets to act on their peers or superiors, or on themselves
+fn ensure_outranks(
+    actor: &str,
+    actor_role: gog_commons::data_structures::Role,
+    target: &login_data::Model,
+) -> Result<(), ServiceError> {
+    if target.role() >= actor_role || target.login == actor {
+        return Err(ServiceError::Forbidden);
+    }
+    Ok(())
+}
+
+async fn set_suspended(
+    actor: &str,
+    actor_role: gog_commons::data_structures::Role,
+    login: &str,
+    suspended: bool,
+    db: &DbConnection,
+) -> Result<login_data::Model, ServiceError> {
+    let target = find_target(login, db).await?;
+    ensure_outranks(actor, actor_role, &target)?;
+    let mut model: login_data::ActiveModel = target.into();
+    model.suspended = ActiveValue::Set(suspended);
+    let target = LoginData::update(model).exec(&db.db_connection).await?;
+    info!("{} set suspended of {} to {}", actor, login, suspended);
+    Ok(target)
+}
+
+/// Suspends `login` on behalf of `actor`, ending all of their sessions and OAuth grants
+pub(super) async fn suspend_user(
+    actor: &AuthUser,
+    login: &str,
+    db: &DbConnection,
+    token_session: &Data<Mutex<dyn TokenSession>>,
+) -> Result<(), ServiceError> {
+    let target = set_suspended(&actor.login, actor.role, login, true, db).await?;
+    token_session
+        .lock()
+        .unwrap()
+        .remove_user_sessions(&target.login);
+    OAuthTokens::update_many()
+        .col_expr(oauth_tokens::Column::Revoked, Expr::value(true))
+        .filter(oauth_tokens::Column::UserId.eq(target.user_id))
+        .exec(&db.db_connection)
+        .await?;
+    Ok(())
+}
+
+#[actix_web::post("users/{login}/suspend")]
+async fn admin_users_suspend(
+    auth: Authorized<can::SuspendUsers>,
+    login: Path<String>,
+    db: Data<DbConnection>,
+    token_session: Data<Mutex<dyn TokenSession>>,
+) -> ServiceResult {
+    suspend_user(&auth.user, &login, &db, &token_session).await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[actix_web::post("users/{login}/unsuspend")]
+async fn admin_users_unsuspend(
+    auth: Authorized<can::SuspendUsers>,
+    login: Path<String>,
+    db: Data<DbConnection>,
+) -> ServiceResult {
+    set_suspended(&auth.user.login, auth.user.role, &login, false, &db).await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[actix_web::post("users/{login}/logout")]
+async fn admin_users_logout(
+    auth: Authorized<can::ForceLogout>,
+    login: Path<String>,
+    db: Data<DbConnection>,
+    token_session: Data<Mutex<dyn TokenSession>>,
+) -> ServiceResult {
+    let target = find_target(&login, &db).await?;
+    ensure_outranks(&auth.user.login, auth.user.role, &target)?;
+    let removed = token_session
+        .lock()
+        .unwrap()
+        .remove_user_sessions(&target.login);
+    info!(
+        "{} logged out {} ({} sessions)",
+        auth.user.login, target.login, removed
+    );
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "removed_sessions": removed })))
+}
+
+#[actix_web::delete("posts/{post_id}")]
+async fn admin_posts_delete(
+    auth: Authorized<can::DeleteAnyContent>,
+    post_id: Path<Uuid>,
+    db: Data<DbConnection>,
+    store: Data<dyn BlobStore>,
+) -> ServiceResult {
+    if !attachments::delete_post(post_id.into_inner(), &db.db_connection, store.get_ref()).await? {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    info!("{} deleted a post", auth.user.login);
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[actix_web::delete("comments/{comment_id}")]
+async fn admin_comments_delete(
+    auth: Authorized<can::DeleteAnyContent>,
+    comment_id: Path<Uuid>,
+    db: Data<DbConnection>,
+) -> ServiceResult {
+    let res = comments::Entity::delete_by_id(comment_id.into_inner())
+        .exec(&db.db_connection)
+        .await?;
+    if res.rows_affected == 0 {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    info!("{} deleted a comment", auth.user.login);
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[actix_web::post("moderation/reload")]
+async fn admin_moderation_reload(
+    auth: Authorized<can::ReloadModeration>,
+    moderation: Data<ModerationService>,
+) -> ServiceResult {
+    if let Err(e) = moderation.reload() {
+        // the previous pipeline stays active
+        return Ok(HttpResponse::UnprocessableEntity()
+            .reason("invalid moderation config")
+            .json(serde_json::json!({ "error": e.to_string() })));
+    }
+    info!("{} reloaded the moderation config", auth.user.login);
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[actix_web::get("cache")]
+async fn admin_cache_stats(
+    _auth: Authorized<can::ViewServerStats>,
+    cache: Data<ResourceCache>,
+) -> ServiceResult {
+    Ok(HttpResponse::Ok().json(cache.stats()))
+}
+
+#[actix_web::get("backups")]
+async fn admin_backups_list(
+    _auth: Authorized<can::ManageBackups>,
+    backups: Data<BackupService>,
+) -> ServiceResult {
+    Ok(HttpResponse::Ok().json(backups.list().await?))
+}
+
+/// Answers once the backup is written and verified, `409` while another one is being made
+/// or when one was made in the same second
+#[actix_web::post("backups")]
+async fn admin_backups_create(
+    auth: Authorized<can::ManageBackups>,
+    backups: Data<BackupService>,
+) -> ServiceResult {
+    let backup = backups.create().await?;
+    info!(
+        "{} backed up the database to {}",
+        auth.user.login, backup.name
+    );
+    Ok(HttpResponse::Created().json(backup))
+}
@@ -0,0 +1,298 @@
+use super::entity::prelude::*;
+use super::permissions::AuthUser;
+use super::resources::{self, CacheValidators};
+use super::{DbConnection, ServiceResult};
+use crate::config::Limits;
+use crate::entity::{post_attachments, posts};
+use crate::errors::ServiceError;
+use crate::images;
+use crate::storage::BlobStore;
+use actix_web::{
+    http::header::{self, CacheDirective},
+    web::{self, Bytes, Data, Path, Query},
+    FromRequest, HttpRequest, HttpResponse,
+};
+use gog_commons::data_structures::{AttachmentData, AttachmentRef, ValidationErrorResponse};
+use log::{debug, warn};
+use sea_orm::{
+    ActiveValue, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait,
+    PaginatorTrait, QueryFilter, QueryOrder,
+};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+use validator::{ValidationError, ValidationErrors};
+
+/// Uploads not used by a post yet, beyond this a user has to post or wait
+const PENDING_ATTACHMENTS_MAX: u64 = 16;
+/// Uploads never used by a post are dropped after this long
+const PENDING_ATTACHMENT_TTL_HOURS: i64 = 24;
+pub static ATTACHMENT_UNAVAILABLE: &str = "attachment unavailable";
+
+/// Whether posts have to describe every attached image
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AltTextPolicy {
+    pub required: bool,
+}
+
+fn attachments_error(code: &'static str, message: &'static str) -> HttpResponse {
+    let mut errors = ValidationErrors::new();
+    errors.add(
+        "attachments",
+        ValidationError::new(code).with_message(message.into()),
+    );
+    HttpResponse::BadRequest().json(ValidationErrorResponse {
+        reason: "Validation Failed".to_owned(),
+        errors,
+    })
+}
+
+/// Checks what a post wants to attach before anything is written
+pub(super) fn check_refs(
+    refs: &[AttachmentRef],
+    policy: AltTextPolicy,
+) -> Result<(), HttpResponse> {
+    let mut seen = HashSet::new();
+    if !refs.iter().all(|r| seen.insert(r.attachment_id)) {
+        return Err(attachments_error(
+            "duplicate_attachment",
+            "an image can only be attached once",
+        ));
+    }
+    let described = |r: &AttachmentRef| r.alt_text.as_deref().is_some_and(|a| !a.trim().is_empty());
+    if policy.required && !refs.iter().all(described) {
+        return Err(attachments_error(
+            "alt_text_required",
+            "every image needs a description",
+        ));
+    }
+    Ok(())
+}
+
+/// Hands the uploads over to `post_id` in the given order. Fails with [`ATTACHMENT_UNAVAILABLE`]
+/// when one of them does not exist, is not the user's or already belongs to a post
+pub(super) async fn attach<C: ConnectionTrait>(
+    refs: Vec<AttachmentRef>,
+    user_id: Uuid,
+    post_id: Uuid,
+    txn: &C,
+) -> Result<(), DbErr> {
+    for (position, r) in refs.into_iter().enumerate() {
+        let res = PostAttachments::update_many()
+            .set(post_attachments::ActiveModel {
+                post_id: ActiveValue::Set(Some(post_id)),
+                position: ActiveValue::Set(Some(position as i32)),
+                alt_text: ActiveValue::Set(
+                    r.alt_text
+                        .map(|a| a.trim().to_owned())
+                        .filter(|a| !a.is_empty()),
+                ),
+                ..Default::default()
+            })
+            .filter(post_attachments::Column::AttachmentId.eq(r.attachment_id))
+            .filter(post_attachments::Column::UserId.eq(user_id))
+            .filter(post_attachments::Column::PostId.is_null())
+            .exec(txn)
+            .await?;
+        if res.rows_affected != 1 {
+            return Err(DbErr::Custom(ATTACHMENT_UNAVAILABLE.to_owned()));
+        }
+    }
+    Ok(())
+}
+
+/// Attachments of each of the posts, in display order
+pub(super) async fn for_posts(
+    post_ids: impl IntoIterator<Item = Uuid>,
+    db: &DatabaseConnection,
+) -> Result<HashMap<Uuid, Vec<AttachmentData>>, DbErr> {
+    let post_ids = post_ids.into_iter().collect::<Vec<_>>();
+    let mut by_post: HashMap<Uuid, Vec<AttachmentData>> = HashMap::new();
+    if post_ids.is_empty() {
+        return Ok(by_post);
+    }
+    let rows = PostAttachments::find()
+        .filter(post_attachments::Column::PostId.is_in(post_ids))
+        .order_by_asc(post_attachments::Column::Position)
+        .all(db)
+        .await?;
+    for row in rows {
+        if let Some(post_id) = row.post_id {
+            by_post
+                .entry(post_id)
+                .or_default()
+                .push(row.into_attachment_data());
+        }
+    }
+    Ok(by_post)
+}
+
+/// Deletes a post and the images only it was using, returns whether the post existed
+pub(super) async fn delete_post(
+    post_id: Uuid,
+    db: &DatabaseConnection,
+    store: &dyn BlobStore,
+) -> Result<bool, ServiceError> {
+    let attached = PostAttachments::find()
+        .filter(post_attachments::Column::PostId.eq(post_id))
+        .all(db)
+        .await?;
+    let res = posts::Entity::delete_by_id(post_id).exec(db).await?;
+    release_all(attached, db, store).await;
+    Ok(res.rows_affected > 0)
+}
+
+/// Removes the blobs of attachment rows that are already gone from the database
+async fn release_all(
+    attachments: Vec<post_attachments::Model>,
+    db: &DatabaseConnection,
+    store: &dyn BlobStore,
+) {
+    for a in attachments {
+        for key in [a.blob_key, a.thumb_key] {
+            if let Err(e) = resources::release_blob(db, store, &key).await {
+                // an orphaned blob only wastes space
+                warn!("failed to remove attachment blob {}: {}", key, e);
+            }
+        }
+    }
+}
+
+/// Forgets uploads of `user_id` that never made it into a post
+async fn drop_stale_pending(
+    user_id: Uuid,
+    db: &DatabaseConnection,
+    store: &dyn BlobStore,
+) -> Result<(), DbErr> {
+    let cutoff = chrono::Utc::now() - chrono::Duration::hours(PENDING_ATTACHMENT_TTL_HOURS);
+    let stale = PostAttachments::find()
+        .filter(post_attachments::Column::UserId.eq(user_id))
+        .filter(post_attachments::Column::PostId.is_null())
+        .filter(post_attachments::Column::Uploaded.lt(cutoff))
+        .all(db)
+        .await?;
+    if stale.is_empty() {
+        return Ok(());
+    }
+    PostAttachments::delete_many()
+        .filter(post_attachments::Column::AttachmentId.is_in(stale.iter().map(|a| a.attachment_id)))
+        .exec(db)
+        .await?;
+    release_all(stale, db, store).await;
+    Ok(())
+}
+
+pub async fn attachments_upload(
+    user: AuthUser,
+    payload: Bytes,
+    db: Data<DbConnection>,
+    store: Data<dyn BlobStore>,
+    limits: Data<Limits>,
+) -> ServiceResult {
+    if payload.len() > limits.attachment_bytes {
+        return Ok(HttpResponse::BadRequest()
+            .reason("uploaded file exceeded allowed size")
+            .finish());
+    }
+    let db = &db.db_connection;
+    drop_stale_pending(user.user_id, db, store.get_ref()).await?;
+    let pending = PostAttachments::find()
+        .filter(post_attachments::Column::UserId.eq(user.user_id))
+        .filter(post_attachments::Column::PostId.is_null())
+        .count(db)
+        .await?;
+    if pending >= PENDING_ATTACHMENTS_MAX {
+        return Ok(HttpResponse::TooManyRequests()
+            .reason("too many images waiting to be posted")
+            .finish());
+    }
+
+    let processed = match web::block(move || images::process_attachment(&payload)).await {
+        Ok(Ok(processed)) => processed,
+        Ok(Err(e)) => {
+            debug!("rejected attachment upload: {}", e);
+            return Ok(HttpResponse::BadRequest()
+                .reason("uploaded file was not a valid png/jpeg/webp/gif image")
+                .finish());
+        }
+        Err(e) => {
+            return Err(ServiceError::ServerError {
+                source: Box::new(e),
+            })
+        }
+    };
+    let _writing = resources::blob_write_guard().await;
+    let blob_key = store
+        .put(&processed.image.data, processed.image.content_type)
+        .await?;
+    let thumb_key = store
+        .put(&processed.thumbnail.data, processed.thumbnail.content_type)
+        .await?;
+    let model = post_attachments::ActiveModel {
+        attachment_id: ActiveValue::Set(Uuid::new_v4()),
+        user_id: ActiveValue::Set(user.user_id),
+        post_id: ActiveValue::Set(None),
+        position: ActiveValue::Set(None),
+        alt_text: ActiveValue::Set(None),
+        content_type: ActiveValue::Set(processed.image.content_type.to_owned()),
+        width: ActiveValue::Set(processed.width as i32),
+        height: ActiveValue::Set(processed.height as i32),
+        blob_key: ActiveValue::Set(blob_key),
+        thumb_content_type: ActiveValue::Set(processed.thumbnail.content_type.to_owned()),
+        thumb_key: ActiveValue::Set(thumb_key),
+        uploaded: ActiveValue::Set(chrono::Utc::now()),
+    };
+    let attachment = PostAttachments::insert(model)
+        .exec_with_returning(db)
+        .await?;
+    Ok(HttpResponse::Created().json(attachment.into_attachment_data()))
+}
+
+#[derive(Deserialize)]
+struct AttachmentQuery {
+    #[serde(default)]
+    thumb: bool,
+}
+
+#[actix_web::get("attachments/{attachment_id}")]
+async fn attachments_get(
+    req: HttpRequest,
+    attachment_id: Path<Uuid>,
+    query: Query<AttachmentQuery>,
+    db: Data<DbConnection>,
+    store: Data<dyn BlobStore>,
+) -> ServiceResult {
+    let attachment = PostAttachments::find_by_id(attachment_id.into_inner())
+        .one(&db.db_connection)
+        .await?
+        .ok_or(ServiceError::NotFound)?;
+    let pending = attachment.post_id.is_none();
+    if pending {
+        // until it is posted, an upload is only there for whoever is writing the post
+        let uploader = AuthUser::extract(&req).await.ok();
+        if uploader.map(|u| u.user_id) != Some(attachment.user_id) {
+            return Err(ServiceError::NotFound);
+        }
+    }
+    let (key, content_type) = if query.thumb {
+        (attachment.thumb_key, attachment.thumb_content_type)
+    } else {
+        (attachment.blob_key, attachment.content_type)
+    };
+    let cache_control = header::CacheControl(vec![
+        if pending {
+            CacheDirective::Private
+        } else {
+            CacheDirective::Public
+        },
+        // an attachment never changes once uploaded
+        CacheDirective::MaxAge(resources::IMMUTABLE_MAX_AGE),
+        CacheDirective::Extension("immutable".to_owned(), None),
+    ]);
+    let validators = CacheValidators::new(&key, Some(attachment.uploaded));
+    if validators.is_fresh(&req) {
+        return Ok(validators.not_modified(cache_control));
+    }
+    let data = store.get(&key).await?.ok_or(ServiceError::NotFound)?;
+    Ok(validators.respond(&req, cache_control, &content_type, data))
+}
@@ -4,7 +4,7 @@ use actix_session::Session;
 use actix_web::{web, HttpResponse};
 use chrono::Utc;
 use gog_commons::data_structures;
-use gog_commons::data_structures::CommentCreationData;
+use gog_commons::data_structures::{CommentCreationData, CommentEditData, ValidationErrorResponse};
 use sea_orm::ColumnTrait;
 use sea_orm::EntityTrait;
 use sea_orm::QueryFilter;
@@ -13,14 +13,18 @@ use sea_orm::QuerySelect;
 use serde::Deserialize;
 use std::sync::Mutex;
 use uuid::Uuid;
-use validator::Validate;
 
+use crate::config::Limits;
 use crate::entity::comments;
 use crate::entity::login_data;
+use crate::errors::ServiceError;
+use crate::moderation::{ContentContext, ContentKind, ModerationService};
+use crate::service::permissions::AuthUser;
 use crate::service::{helpers, ServiceResult};
 pub fn configure_service(cfg: &mut web::ServiceConfig) {
     let scope = actix_web::web::scope("/comments")
         .service(comments_post)
+        .service(comments_edit)
         .service(comments_get);
     cfg.service(scope);
 }
@@ -30,15 +34,29 @@ pub async fn comments_post(
     db: web::Data<DbConnection>,
     session: Session,
     token_session: web::Data<Mutex<dyn TokenSession>>,
+    moderation: web::Data<ModerationService>,
+    limits: web::Data<Limits>,
 ) -> ServiceResult {
-    let login = helpers::validate_session(&token_session, &session)?;
+    let login = helpers::validate_session(&token_session, &session, &db).await?;
     let uid = helpers::get_user_id(&login, &db).await?;
     let comment = comment.into_inner();
-    if comment.validate().is_err() {
-        return Ok(HttpResponse::BadGateway().finish());
+    if let Err(errors) = limits.validate_content(ContentKind::Comment, &comment.content, &comment) {
+        return Ok(HttpResponse::BadRequest()
+            .reason("comment validation failed")
+            .json(ValidationErrorResponse {
+                reason: "comment validation failed".to_owned(),
+                errors,
+            }));
     }
+    let ctx = ContentContext {
+        kind: ContentKind::Comment,
+        user_id: uid,
+        editing: None,
+        db: &db.db_connection,
+    };
+    let content = moderation.pipeline().run(&comment.content, &ctx).await?;
     let comment = comments::ActiveModel {
-        content: sea_orm::ActiveValue::Set(comment.content),
+        content: sea_orm::ActiveValue::Set(content),
         comment_id: sea_orm::ActiveValue::Set(Uuid::new_v4()),
         post_id: sea_orm::ActiveValue::Set(comment.post_id),
         user_id: sea_orm::ActiveValue::Set(uid),
@@ -49,6 +67,46 @@ pub async fn comments_post(
         .await?;
     Ok(HttpResponse::Ok().finish())
 }
+#[actix_web::post("edit/{comment_id}")]
+pub async fn comments_edit(
+    user: AuthUser,
+    comment_id: web::Path<Uuid>,
+    edit: web::Json<CommentEditData>,
+    db: web::Data<DbConnection>,
+    moderation: web::Data<ModerationService>,
+    limits: web::Data<Limits>,
+) -> ServiceResult {
+    if let Err(errors) = limits.validate_content(ContentKind::Comment, &edit.content, &*edit) {
+        return Ok(HttpResponse::BadRequest()
+            .reason("comment validation failed")
+            .json(ValidationErrorResponse {
+                reason: "comment validation failed".to_owned(),
+                errors,
+            }));
+    }
+    let Some(comment) = comments::Entity::find_by_id(comment_id.into_inner())
+        .one(&db.db_connection)
+        .await?
+    else {
+        return Err(ServiceError::NotFound);
+    };
+    if comment.user_id != user.user_id {
+        return Err(ServiceError::Forbidden);
+    }
+    let ctx = ContentContext {
+        kind: ContentKind::Comment,
+        user_id: user.user_id,
+        editing: Some(comment.comment_id),
+        db: &db.db_connection,
+    };
+    let content = moderation.pipeline().run(&edit.content, &ctx).await?;
+    let mut model: comments::ActiveModel = comment.into();
+    model.content = sea_orm::ActiveValue::Set(content);
+    comments::Entity::update(model)
+        .exec(&db.db_connection)
+        .await?;
+    Ok(HttpResponse::Ok().finish())
+}
 #[derive(Deserialize)]
 pub struct CommentsGetQuery {
     cid: Option<Uuid>,
@@ -74,11 +132,14 @@ pub async fn comments_get(
             match com {
                 None => Ok(HttpResponse::NotFound().finish()),
                 Some((c, usr)) => {
+                    let (user_name, pfp_version) =
+                        usr.map_or((String::new(), None), |m| (m.login, m.pfp_version));
                     let ret = data_structures::CommentData {
                         comment_id: c.comment_id,
                         user_id: c.user_id,
                         post_id: c.post_id,
-                        user_name: usr.map_or(String::new(), |mdl| mdl.login),
+                        user_name,
+                        pfp_version,
                         posted: c.posted,
                         content: c.content,
                     };
@@ -97,11 +158,14 @@ pub async fn comments_get(
             let res = com
                 .into_iter()
                 .map(|(c, usr)| {
+                    let (user_name, pfp_version) =
+                        usr.map_or((String::new(), None), |m| (m.login, m.pfp_version));
                     let ret = data_structures::CommentData {
                         comment_id: c.comment_id,
                         user_id: c.user_id,
                         post_id: c.post_id,
-                        user_name: usr.map_or(String::new(), |mdl| mdl.login),
+                        user_name,
+                        pfp_version,
                         posted: c.posted,
                         content: c.content,
                     };
@@ -1,4 +1,7 @@
-use crate::{entity::login_data, errors::SessionValidationError};
+use crate::{
+    entity::{login_data, oauth_tokens, user_data},
+    errors::SessionValidationError,
+};
 
 use super::entity::prelude::*;
 use super::errors;
@@ -6,11 +9,19 @@ pub use super::objects::DbConnection;
 use super::SESSION_ID;
 use crate::session::TokenSession;
 use actix_session::Session;
-use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+use actix_web::{http::header, HttpRequest};
+use argon2::{
+    password_hash::{self, rand_core::OsRng, PasswordHasher, SaltString},
+    Argon2,
+};
+use gog_commons::data_structures::Role;
+use sea_orm::{ActiveValue, ColumnTrait, EntityTrait, QueryFilter};
 use std::{str::FromStr, sync::Mutex};
 use uuid::Uuid;
+use validator::{ValidationErrors, ValidationErrorsKind};
 
-pub fn validate_session(
+/// The login the request session belongs to, whatever became of the account since
+fn session_login(
     token_session: &actix_web::web::Data<Mutex<dyn TokenSession>>,
     session: &Session,
 ) -> Result<String, errors::SessionValidationError> {
@@ -28,9 +39,41 @@ pub fn validate_session(
     let Some(usr_login) = sess.get_user(&uuid) else {
         return Err(SessionValidationError::NoCookie);
     };
+    tracing::Span::current().record("user", usr_login.as_str());
     Ok(usr_login)
 }
 
+/// The account of the request session. Suspended accounts lose every session here, including
+/// ones from a login that got in before the suspension removed the others
+pub async fn session_account(
+    token_session: &actix_web::web::Data<Mutex<dyn TokenSession>>,
+    session: &Session,
+    db: &DbConnection,
+) -> Result<login_data::Model, errors::ServiceError> {
+    let login = session_login(token_session, session)?;
+    let Some(model) = LoginData::find_by_id(&login).one(&db.db_connection).await? else {
+        return Err(errors::ServiceError::UserNotFound);
+    };
+    if model.suspended {
+        token_session
+            .lock()
+            .unwrap()
+            .remove_user_sessions(&model.login);
+        return Err(errors::ServiceError::Suspended);
+    }
+    tracing::Span::current().record("user_id", tracing::field::display(model.user_id));
+    Ok(model)
+}
+
+/// The login of the request session, as long as the account is not suspended
+pub async fn validate_session(
+    token_session: &actix_web::web::Data<Mutex<dyn TokenSession>>,
+    session: &Session,
+    db: &DbConnection,
+) -> Result<String, errors::ServiceError> {
+    Ok(session_account(token_session, session, db).await?.login)
+}
+
 pub async fn get_user_id(login: &str, db: &DbConnection) -> Result<Uuid, errors::UserIdError> {
     let usr = LoginData::find()
         .filter(login_data::Column::Login.eq(login))
@@ -41,3 +84,84 @@ pub async fn get_user_id(login: &str, db: &DbConnection) -> Result<Uuid, errors:
         None => Err(errors::UserIdError::NoUser),
     }
 }
+
+/// A fresh salt and the argon2 hash of `password` with it
+pub fn hash_password(password: &str) -> Result<(SaltString, String), password_hash::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)?
+        .to_string();
+    Ok((salt, hash))
+}
+
+/// The rows a new account is made of, both still have to be inserted
+pub fn new_user(
+    login: &str,
+    salt: &SaltString,
+    hash: String,
+    role: Role,
+    invited_by: Option<Uuid>,
+) -> (login_data::ActiveModel, user_data::ActiveModel) {
+    let user_id = Uuid::new_v4();
+    let login = login_data::ActiveModel {
+        login: ActiveValue::Set(login.to_owned()),
+        salt: ActiveValue::Set(salt.as_str().to_owned()),
+        hash: ActiveValue::Set(hash),
+        user_id: ActiveValue::Set(user_id),
+        role: ActiveValue::Set(role.as_str().to_owned()),
+        suspended: ActiveValue::Set(false),
+        invited_by: ActiveValue::Set(invited_by),
+        pfp_version: ActiveValue::Set(None),
+    };
+    let data = user_data::ActiveModel {
+        user_id: ActiveValue::Set(user_id),
+        description: ActiveValue::Set(Some("".to_owned())),
+        created: ActiveValue::Set(Some(chrono::Utc::now())),
+        ..Default::default()
+    };
+    (login, data)
+}
+
+/// Field names and error codes of a failed validation, for logs. Never the values, rules like
+/// `length` keep the rejected value in their params
+pub fn validation_summary(errors: &ValidationErrors) -> String {
+    let mut fields: Vec<String> = errors
+        .errors()
+        .iter()
+        .map(|(field, kind)| match kind {
+            ValidationErrorsKind::Field(errors) => {
+                let codes: Vec<&str> = errors.iter().map(|e| e.code.as_ref()).collect();
+                format!("{} ({})", field, codes.join(", "))
+            }
+            _ => field.to_string(),
+        })
+        .collect();
+    fields.sort();
+    fields.join(", ")
+}
+
+/// Extracts the token of an `Authorization: Bearer` header
+pub fn bearer_token(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
+/// Looks up a valid OAuth2 access token presented as a bearer token
+pub async fn validate_bearer(
+    req: &HttpRequest,
+    db: &DbConnection,
+) -> Result<oauth_tokens::Model, errors::OAuthError> {
+    let Some(token) = bearer_token(req) else {
+        return Err(errors::OAuthError::InvalidToken);
+    };
+    match OAuthTokens::find_by_id(crate::oauth::hash_token(token))
+        .one(&db.db_connection)
+        .await?
+    {
+        Some(t) if t.kind == oauth_tokens::KIND_ACCESS && t.is_valid() => Ok(t),
+        _ => Err(errors::OAuthError::InvalidToken),
+    }
+}
@@ -0,0 +1,200 @@
+use super::entity::prelude::*;
+use super::permissions::{AuthUser, Permission};
+use super::{DbConnection, ServiceResult};
+use crate::entity::{invites, login_data};
+use crate::errors::ServiceError;
+use actix_web::{
+    web::{self, Data, Json, Path, Query},
+    HttpResponse,
+};
+use gog_commons::data_structures::{InviteCreationData, RegistrationMode, ValidationErrorResponse};
+use log::{error, info};
+use sea_orm::{
+    sea_query::Expr, ActiveValue, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, PaginatorTrait,
+    QueryFilter, QueryOrder,
+};
+use serde::Deserialize;
+use validator::{Validate, ValidationError, ValidationErrors};
+
+/// Limits for users without [`Permission::ManageInvites`]
+const USER_INVITE_MAX_USES: i32 = 1;
+const USER_INVITE_TTL_HOURS: i64 = 7 * 24;
+const USER_ACTIVE_INVITES: u64 = 5;
+
+const INVITE_CODE_LENGTH: usize = 16;
+
+pub fn configure_service(cfg: &mut web::ServiceConfig) {
+    let scope = web::scope("/invites")
+        .service(invites_create)
+        .service(invites_list)
+        .service(invites_revoke);
+    cfg.service(scope);
+}
+
+fn invite_code_error(message: &'static str) -> HttpResponse {
+    let mut errors = ValidationErrors::new();
+    errors.add(
+        "invite_code",
+        ValidationError::new("invite_code").with_message(message.into()),
+    );
+    HttpResponse::BadRequest().json(ValidationErrorResponse {
+        reason: "Validation Failed".to_owned(),
+        errors,
+    })
+}
+
+/// Decides whether a registration may go ahead under `mode`,
+/// on success returns the invite it is going to use up
+pub(super) async fn registration_invite(
+    mode: RegistrationMode,
+    code: Option<&str>,
+    db: &DbConnection,
+) -> Result<Option<invites::Model>, HttpResponse> {
+    let code = match (mode, code) {
+        (RegistrationMode::Closed, _) => {
+            return Err(HttpResponse::Forbidden()
+                .reason("registration is closed")
+                .finish())
+        }
+        (RegistrationMode::InviteOnly, None) => {
+            return Err(invite_code_error("an invite code is required"))
+        }
+        (RegistrationMode::Open, None) => return Ok(None),
+        (_, Some(code)) => code.trim(),
+    };
+    match Invites::find_by_id(code).one(&db.db_connection).await {
+        Ok(Some(invite)) if invite.is_usable() => Ok(Some(invite)),
+        Ok(_) => Err(invite_code_error("invite code is invalid or expired")),
+        Err(e) => {
+            error!("invite lookup error: {}", e);
+            Err(HttpResponse::InternalServerError().finish())
+        }
+    }
+}
+
+/// Takes one use of the invite, false when it ran out in the meantime
+pub(super) async fn use_invite<C: ConnectionTrait>(code: &str, db: &C) -> Result<bool, DbErr> {
+    let res = Invites::update_many()
+        .col_expr(
+            invites::Column::Uses,
+            Expr::col(invites::Column::Uses).add(1),
+        )
+        .filter(invites::Column::Code.eq(code))
+        .filter(Expr::col(invites::Column::Uses).lt(Expr::col(invites::Column::MaxUses)))
+        .exec(db)
+        .await?;
+    Ok(res.rows_affected == 1)
+}
+
+fn generate_code() -> String {
+    let mut code = crate::oauth::random_token();
+    code.truncate(INVITE_CODE_LENGTH);
+    code
+}
+
+#[actix_web::post("")]
+async fn invites_create(
+    user: AuthUser,
+    data: Json<InviteCreationData>,
+    db: Data<DbConnection>,
+) -> ServiceResult {
+    let data = data.into_inner();
+    if let Err(errors) = data.validate() {
+        return Ok(HttpResponse::BadRequest()
+            .reason("invite validation failed")
+            .json(ValidationErrorResponse {
+                reason: "invite validation failed".to_owned(),
+                errors,
+            }));
+    }
+    let now = chrono::Utc::now();
+    let (max_uses, expires) = if user.require(Permission::ManageInvites).is_ok() {
+        (
+            data.max_uses.unwrap_or(1),
+            data.expires_in_hours
+                .map(|h| now + chrono::Duration::hours(h)),
+        )
+    } else {
+        let active = Invites::find()
+            .filter(invites::Column::CreatedBy.eq(user.user_id))
+            .filter(Expr::col(invites::Column::Uses).lt(Expr::col(invites::Column::MaxUses)))
+            .filter(invites::Column::Expires.gt(now))
+            .count(&db.db_connection)
+            .await?;
+        if active >= USER_ACTIVE_INVITES {
+            return Ok(HttpResponse::TooManyRequests()
+                .reason("too many active invites")
+                .finish());
+        }
+        let hours = data
+            .expires_in_hours
+            .unwrap_or(USER_INVITE_TTL_HOURS)
+            .min(USER_INVITE_TTL_HOURS);
+        (
+            data.max_uses.unwrap_or(1).min(USER_INVITE_MAX_USES),
+            Some(now + chrono::Duration::hours(hours)),
+        )
+    };
+
+    let model = invites::ActiveModel {
+        code: ActiveValue::Set(generate_code()),
+        created_by: ActiveValue::Set(user.user_id),
+        created: ActiveValue::Set(now),
+        expires: ActiveValue::Set(expires),
+        max_uses: ActiveValue::Set(max_uses),
+        uses: ActiveValue::Set(0),
+    };
+    let invite = Invites::insert(model)
+        .exec_with_returning(&db.db_connection)
+        .await?;
+    info!("{} minted an invite for {} uses", user.login, max_uses);
+    Ok(HttpResponse::Created().json(invite.into_invite_data(user.login)))
+}
+
+#[derive(Deserialize)]
+struct InviteListQuery {
+    /// Everyone's invites instead of just the caller's, needs [`Permission::ManageInvites`]
+    #[serde(default)]
+    all: bool,
+}
+
+#[actix_web::get("")]
+async fn invites_list(
+    user: AuthUser,
+    query: Query<InviteListQuery>,
+    db: Data<DbConnection>,
+) -> ServiceResult {
+    let mut select = Invites::find().find_also_related(login_data::Entity);
+    if query.all {
+        user.require(Permission::ManageInvites)?;
+    } else {
+        select = select.filter(invites::Column::CreatedBy.eq(user.user_id));
+    }
+    let invites = select
+        .order_by_desc(invites::Column::Created)
+        .all(&db.db_connection)
+        .await?
+        .into_iter()
+        .map(|(i, creator)| i.into_invite_data(creator.map_or(String::new(), |l| l.login)))
+        .collect::<Vec<_>>();
+    Ok(HttpResponse::Ok().json(invites))
+}
+
+#[actix_web::delete("{code}")]
+async fn invites_revoke(
+    user: AuthUser,
+    code: Path<String>,
+    db: Data<DbConnection>,
+) -> ServiceResult {
+    let invite = Invites::find_by_id(code.into_inner())
+        .one(&db.db_connection)
+        .await?
+        .ok_or(ServiceError::NotFound)?;
+    if invite.created_by != user.user_id {
+        user.require(Permission::ManageInvites)?;
+    }
+    Invites::delete_by_id(invite.code)
+        .exec(&db.db_connection)
+        .await?;
+    Ok(HttpResponse::Ok().finish())
+}
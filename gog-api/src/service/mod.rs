@@ -1,58 +1,85 @@
+pub mod admin;
+pub mod attachments;
 pub mod comments;
 mod helpers;
+pub(crate) use helpers::{
+    bearer_token, hash_password, new_user, validate_bearer, validation_summary,
+};
+pub mod invites;
+pub mod oauth;
 mod objects;
+pub mod permissions;
 pub mod posts;
+pub mod reports;
 pub mod resources;
 use super::entity;
 use super::entity::prelude::*;
 use super::errors;
 use super::session::TokenSession;
-use crate::{cache::ResourceCache, entity::login_data, errors::ServiceError};
+use crate::{
+    cache::ResourceCache,
+    config::{InstanceConfig, Limits, ResourceConfig},
+    entity::login_data,
+    errors::ServiceError,
+    pow::PowGuard,
+    storage::BlobStore,
+};
 use actix_session::Session;
 use actix_web::{
     self,
-    http::header::{self, CacheDirective, ContentType},
+    http::header::{self, CacheDirective},
     web::{self},
-    HttpResponse, Responder,
+    HttpRequest, HttpResponse, Responder,
 };
 use argon2::{
-    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    password_hash::{PasswordHash, PasswordVerifier},
     Argon2,
 };
 use gog_commons as commons;
 use gog_commons::data_structures::UserCreationData;
-use gog_commons::data_structures::UserDataResponse;
 use gog_commons::data_structures::UserLogin;
 use log::{debug, error, info, log, Level};
 pub use objects::DbConnection;
 use objects::UserProfileQuery;
 use objects::UserUpdateDataExt;
-use sea_orm::{
-    ActiveModelTrait, ActiveValue, ColumnTrait, DbErr, EntityTrait, QueryFilter, TransactionTrait,
-};
+use permissions::AuthUser;
+use sea_orm::{ActiveModelTrait, ColumnTrait, DbErr, EntityTrait, QueryFilter, TransactionTrait};
 use std::{error::Error, str::FromStr, sync::Mutex};
 use uuid::Uuid;
 use validator::Validate;
 type ServiceResult = Result<HttpResponse, ServiceError>;
 
-static SESSION_ID: &str = "id";
-pub fn configure_service(cfg: &mut web::ServiceConfig) {
+pub(crate) static SESSION_ID: &str = "id";
+static INVITE_USED_UP: &str = "invite used up";
+pub fn configure_service(cfg: &mut web::ServiceConfig, limits: &Limits) {
     use actix_web::guard;
     cfg.service(hello_world);
+    cfg.service(instance_info);
     let user_scope = web::scope("/user")
         .service(user_create)
+        .service(user_challenge)
         .service(user_exists)
         .service(user_login_token)
         .service(user_data)
         .service(user_update)
         .service(user_logout)
+        .service(user_csrf_token)
         .service(
             web::resource("/upload_pfp")
-                .guard(guard::Header("content-type", "image/jpg"))
+                .guard(guard::fn_guard(resources::image_content_type))
                 .guard(guard::Post())
+                .app_data(web::PayloadConfig::new(limits.pfp_bytes))
                 .route(web::post().to(resources::user_upload_pfp)),
         )
+        .service(
+            web::resource("/upload_banner")
+                .guard(guard::fn_guard(resources::image_content_type))
+                .guard(guard::Post())
+                .app_data(web::PayloadConfig::new(limits.banner_bytes))
+                .route(web::post().to(resources::user_upload_banner)),
+        )
         .service(user_get_pfp)
+        .service(user_get_banner)
         // .service(user_profile_name)
         // .service(user_profile_id)
         .service(user_profile);
@@ -61,6 +88,7 @@ pub fn configure_service(cfg: &mut web::ServiceConfig) {
 
 #[actix_web::get("profile")]
 async fn user_profile(
+    viewer: Option<AuthUser>,
     query: web::Query<UserProfileQuery>,
     db: web::Data<DbConnection>,
 ) -> Result<HttpResponse, ServiceError> {
@@ -87,13 +115,8 @@ async fn user_profile(
 
     match data {
         Some(model) => {
-            let resp = UserDataResponse {
-                created: model.created,
-                description: model.description.unwrap_or_default(),
-                login: login_data.login,
-                gender: model.gender,
-                id: model.user_id,
-            };
+            let owner = viewer.is_some_and(|v| v.user_id == model.user_id);
+            let resp = model.into_user_data_response(login_data, owner);
             let json = serde_json::to_string(&resp).or_else(|e| {
                 error!("user_profile_id serialization error {:?}", e);
                 Err(ServiceError::ServerError {
@@ -192,6 +215,37 @@ async fn hello_world() -> impl Responder {
     HttpResponse::ImATeapot()
 }
 
+/// Public, the frontend reads it once at startup and takes its limits from it
+#[actix_web::get("/instance")]
+async fn instance_info(
+    instance: web::Data<InstanceConfig>,
+    registration: web::Data<commons::data_structures::RegistrationMode>,
+    limits: web::Data<Limits>,
+) -> impl Responder {
+    use commons::data_structures::{InstanceInfo, InstanceLimits};
+    HttpResponse::Ok()
+        .insert_header(header::CacheControl(vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(300),
+        ]))
+        .json(InstanceInfo {
+            name: instance.name.clone(),
+            description: instance.description.clone(),
+            registration_mode: **registration,
+            limits: InstanceLimits {
+                post_length: limits.post_length,
+                comment_length: limits.comment_length,
+                alt_text_length: commons::data_structures::MAX_ALT_TEXT_LENGTH,
+                post_attachments: commons::data_structures::MAX_POST_ATTACHMENTS,
+                require_alt_text: limits.require_alt_text,
+                pfp_bytes: limits.pfp_bytes,
+                banner_bytes: limits.banner_bytes,
+                attachment_bytes: limits.attachment_bytes,
+                image_content_types: crate::images::accepted_content_types(),
+            },
+        })
+}
+
 #[actix_web::post("/logout")]
 async fn user_logout(
     token_session: web::Data<Mutex<dyn TokenSession>>,
@@ -203,6 +257,7 @@ async fn user_logout(
             .finish();
     };
     session.remove(SESSION_ID);
+    crate::csrf::forget(&session);
 
     let mut lock = token_session.lock();
     let token_session = lock.as_mut().unwrap();
@@ -212,6 +267,16 @@ async fn user_logout(
     HttpResponse::Ok().reason("removed session").finish()
 }
 
+/// The CSRF token of the session, for a frontend that was reloaded since the login.
+/// Also answers sessions whose login expired, so they can still send the next one
+#[actix_web::get("/csrf")]
+async fn user_csrf_token(session: Session) -> ServiceResult {
+    let token = crate::csrf::get_or_issue(&session)?;
+    Ok(HttpResponse::Ok()
+        .insert_header(header::CacheControl(vec![CacheDirective::NoStore]))
+        .json(commons::data_structures::CsrfTokenResponse { token }))
+}
+
 #[actix_web::post("/update")]
 async fn user_update(
     db: web::Data<DbConnection>,
@@ -225,12 +290,15 @@ async fn user_update(
             reason: "Validation Failed".to_owned(),
             errors: e,
         };
+        debug!(
+            "invalid profile update: {}",
+            validation_summary(&resp.errors)
+        );
         let json = serde_json::to_string(&resp).unwrap();
-        debug!("{}", json);
         return Ok(HttpResponse::BadRequest().body(json));
     }
 
-    let user = helpers::validate_session(&token_session, &session)?;
+    let user = helpers::validate_session(&token_session, &session, &db).await?;
     let user_id = helpers::get_user_id(&user, &db).await?;
 
     let db = &db.db_connection;
@@ -256,14 +324,24 @@ async fn user_update(
     }
 }
 
+/// A proof of work puzzle that has to be solved for `/create`
+#[actix_web::get("/challenge")]
+async fn user_challenge(pow_guard: web::Data<PowGuard>) -> impl Responder {
+    HttpResponse::Ok()
+        .insert_header(header::CacheControl(vec![CacheDirective::NoStore]))
+        .json(pow_guard.issue())
+}
+
 #[actix_web::post("/create")]
 async fn user_create(
     creation_data: web::Json<UserCreationData>,
     app_data: web::Data<DbConnection>,
+    registration: web::Data<commons::data_structures::RegistrationMode>,
+    pow_guard: web::Data<PowGuard>,
 ) -> impl Responder {
     log!(Level::Info, "user data: {:?}", creation_data.0);
     if let Err(e) = creation_data.validate() {
-        error!("Validation errors\n {:?}", e.errors());
+        info!("invalid registration: {}", validation_summary(&e));
         let resp = commons::data_structures::ValidationErrorResponse {
             reason: "Validation Failed".to_owned(),
             errors: e,
@@ -282,6 +360,34 @@ async fn user_create(
         // };
     }
     let creation = &creation_data.0;
+    // a taken login or a bad invite code does not use up the solved puzzle
+    let ticket = match pow_guard.verify(creation.pow.as_ref()) {
+        Ok(ticket) => ticket,
+        Err(e) => {
+            debug!("registration challenge failed: {}", e);
+            let mut errors = validator::ValidationErrors::new();
+            errors.add(
+                "pow",
+                validator::ValidationError::new("pow").with_message(e.to_string().into()),
+            );
+            return HttpResponse::BadRequest().json(
+                commons::data_structures::ValidationErrorResponse {
+                    reason: "Validation Failed".to_owned(),
+                    errors,
+                },
+            );
+        }
+    };
+    let invite = match invites::registration_invite(
+        *registration.get_ref(),
+        creation.invite_code.as_deref(),
+        &app_data,
+    )
+    .await
+    {
+        Ok(invite) => invite,
+        Err(response) => return response,
+    };
     let db = &app_data.db_connection;
 
     use entity::login_data;
@@ -297,40 +403,26 @@ async fn user_create(
                 .finish()
         }
         Ok(None) => {
-            let salt = SaltString::generate(&mut OsRng);
-
-            let argon2 = Argon2::default();
+            let (salt, password_hash) = hash_password(&creation.password).unwrap();
 
-            let password_hash = argon2
-                .hash_password(creation.password.as_bytes(), &salt)
-                .unwrap()
-                .to_string();
+            log!(Level::Debug, "hashed the password of {}", creation.login);
 
-            log!(
-                Level::Debug,
-                "login: {} , salt: {:?} , hash: {:?}",
-                creation.login,
-                salt,
-                password_hash
+            let (active, data) = new_user(
+                &creation.login,
+                &salt,
+                password_hash,
+                commons::data_structures::Role::User,
+                invite.as_ref().map(|i| i.created_by),
             );
 
-            let active = login_data::ActiveModel {
-                login: sea_orm::ActiveValue::Set(creation.login.clone()),
-                salt: sea_orm::ActiveValue::Set(salt.as_str().to_owned()),
-                hash: sea_orm::ActiveValue::Set(password_hash),
-                user_id: sea_orm::ActiveValue::Set(uuid::Uuid::new_v4()),
-            };
-
-            let data = entity::user_data::ActiveModel {
-                user_id: active.user_id.clone(),
-                description: sea_orm::ActiveValue::Set(Some("".to_owned())),
-                created: ActiveValue::Set(Some(chrono::Utc::now())),
-                ..Default::default()
-            };
-
             let res = db
                 .transaction::<_, (), DbErr>(|txn| {
                     Box::pin(async move {
+                        if let Some(invite) = invite {
+                            if !invites::use_invite(&invite.code, txn).await? {
+                                return Err(DbErr::Custom(INVITE_USED_UP.to_owned()));
+                            }
+                        }
                         active.insert(txn).await?;
                         data.insert(txn).await?;
                         Ok(())
@@ -339,7 +431,17 @@ async fn user_create(
                 .await;
 
             match res {
-                Ok(_) => HttpResponse::Created().finish(),
+                Ok(_) => {
+                    ticket.redeem();
+                    HttpResponse::Created().finish()
+                }
+                Err(sea_orm::TransactionError::Transaction(DbErr::Custom(msg)))
+                    if msg == INVITE_USED_UP =>
+                {
+                    HttpResponse::Conflict()
+                        .reason("invite code was used up")
+                        .finish()
+                }
                 Err(t_err) => {
                     error!("Could not create new user: {}", t_err.to_string());
                     HttpResponse::InternalServerError()
@@ -437,19 +539,22 @@ async fn user_login_token(
         .verify_password(login_data.password.as_bytes(), &parsed_hash)
         .is_ok()
     {
+        if model.suspended {
+            return Err(ServiceError::Suspended);
+        }
         let mut lock = token_session.lock();
         let guard = lock.as_mut().unwrap();
 
         let token = guard.add_user(&model.login);
         session.insert(SESSION_ID, token.to_string()).unwrap();
-        log!(Level::Debug, "token: {}", token.to_string());
-
         if let Some(t) = guard.get_user(&token) {
             log!(Level::Debug, "user session exitsts: {}", t);
         }
+        let csrf_token = crate::csrf::issue(&session)?;
 
         Ok(HttpResponse::Accepted()
             .reason("password accepted")
+            .insert_header((crate::csrf::HEADER, csrf_token))
             .finish())
     } else {
         session.remove(SESSION_ID);
@@ -466,7 +571,7 @@ async fn user_data(
     use entity::login_data;
     log!(Level::Debug, "user data");
 
-    let usr_login = helpers::validate_session(&token_session, &session)?;
+    let usr_login = helpers::validate_session(&token_session, &session, &data).await?;
 
     let Ok(Some(usr)) = LoginData::find()
         .filter(login_data::Column::Login.eq(&usr_login))
@@ -486,13 +591,7 @@ async fn user_data(
 
     match data {
         Ok(Some(data)) => {
-            let data = UserDataResponse {
-                login: usr.login,
-                id: usr.user_id,
-                description: data.description.unwrap_or_default(),
-                gender: data.gender,
-                created: data.created,
-            };
+            let data = data.into_user_data_response(usr, true);
 
             if let Ok(json) = serde_json::to_string(&data) {
                 Ok(HttpResponse::Ok()
@@ -515,44 +614,136 @@ async fn user_data(
     }
 }
 
-static DEFAULT_PFP_PATH: &str = "data/default_pfp.jpeg";
+#[derive(serde::Deserialize)]
+struct PfpQuery {
+    size: Option<u32>,
+    /// The `pfp_version` of the user, makes the response cacheable for good
+    v: Option<String>,
+}
+
+/// Rows point into the blob store once `migrate-blobs` ran, older ones still carry their bytes
+async fn load_blob(
+    store: &dyn BlobStore,
+    key: Option<String>,
+    data: Option<Vec<u8>>,
+) -> Result<Option<Vec<u8>>, ServiceError> {
+    match key {
+        Some(key) => Ok(store.get(&key).await?.or(data)),
+        None => Ok(data),
+    }
+}
 
 #[actix_web::get("/get_pfp/{login}")]
 async fn user_get_pfp(
+    req: HttpRequest,
     login: web::Path<String>,
+    query: web::Query<PfpQuery>,
     db: web::Data<DbConnection>,
-    cache: web::Data<Mutex<ResourceCache>>,
+    store: web::Data<dyn BlobStore>,
+    cache: web::Data<ResourceCache>,
+    resource_config: web::Data<ResourceConfig>,
 ) -> Result<HttpResponse, ServiceError> {
-    let id = if let Ok(uuid) = uuid::Uuid::from_str(&login) {
-        uuid
+    let user = if let Ok(uuid) = uuid::Uuid::from_str(&login) {
+        LoginData::find()
+            .filter(login_data::Column::UserId.eq(uuid))
+            .one(&db.db_connection)
+            .await?
     } else {
-        helpers::get_user_id(&login, &db).await?
+        LoginData::find_by_id(login.as_str())
+            .one(&db.db_connection)
+            .await?
     };
-    match UserPfp::find_by_id(id).one(&db.db_connection).await? {
-        Some(entity::user_pfp::Model {
-            user_id: _,
-            data: Some(d),
-        }) => Ok(HttpResponse::Found()
-            .content_type(ContentType::jpeg())
-            .append_header(header::CacheControl(vec![
-                CacheDirective::MaxAge(0),
-                CacheDirective::MustRevalidate,
-                CacheDirective::NoStore,
-            ]))
-            .body(d)),
-        _ => {
-            let mut lock = cache.lock();
-            let cache = lock.as_mut().unwrap();
-            let pfp = cache.get_or_load(DEFAULT_PFP_PATH).await?;
-            debug!("pfp: {}", pfp.len());
-            Ok(HttpResponse::Found()
-                .content_type(ContentType::jpeg())
-                .append_header(header::CacheControl(vec![
-                    CacheDirective::MaxAge(0),
-                    CacheDirective::MustRevalidate,
-                    CacheDirective::NoStore,
-                ]))
-                .body(pfp))
+    let user = user.ok_or(errors::UserIdError::NoUser)?;
+    let versioned = query.v.is_some() && query.v == user.pfp_version;
+    let cache_control = if versioned {
+        header::CacheControl(vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(resources::IMMUTABLE_MAX_AGE),
+            CacheDirective::Extension("immutable".to_owned(), None),
+        ])
+    } else {
+        // cached copies are fine as long as they are checked against the validators first
+        header::CacheControl(vec![CacheDirective::NoCache])
+    };
+
+    let size = crate::images::pfp_size_for(query.size);
+    let variant = UserPfpVariants::find_by_id((user.user_id, size as i32))
+        .one(&db.db_connection)
+        .await?;
+    if let Some(variant) = variant {
+        let validators = variant
+            .content_hash
+            .as_deref()
+            .map(|hash| resources::CacheValidators::new(hash, variant.uploaded));
+        if let Some(validators) = &validators {
+            if validators.is_fresh(&req) {
+                return Ok(validators.not_modified(cache_control));
+            }
+        }
+        if let Some(data) = load_blob(store.get_ref(), variant.blob_key, variant.data).await? {
+            let validators = validators.unwrap_or_else(|| {
+                resources::CacheValidators::new(&crate::storage::content_key(&data), None)
+            });
+            return Ok(validators.respond(&req, cache_control, &variant.content_type, data));
+        }
+    }
+    // pictures uploaded before resizing existed are stored as the original jpeg
+    let legacy = match UserPfp::find_by_id(user.user_id)
+        .one(&db.db_connection)
+        .await?
+    {
+        Some(entity::user_pfp::Model { blob_key, data, .. }) => {
+            load_blob(store.get_ref(), blob_key, data).await?
         }
+        None => None,
+    };
+    let pfp = match legacy {
+        Some(d) => web::Bytes::from(d),
+        None => cache.get_or_load(&resource_config.default_pfp).await?,
+    };
+    let validators = resources::CacheValidators::new(&crate::storage::content_key(&pfp), None);
+    Ok(validators.respond(&req, cache_control, "image/jpeg", pfp))
+}
+
+#[derive(serde::Deserialize)]
+struct BannerQuery {
+    /// The `banner_version` of the user, makes the response cacheable for good
+    v: Option<String>,
+}
+
+#[actix_web::get("/get_banner/{login}")]
+async fn user_get_banner(
+    req: HttpRequest,
+    login: web::Path<String>,
+    query: web::Query<BannerQuery>,
+    db: web::Data<DbConnection>,
+    store: web::Data<dyn BlobStore>,
+) -> Result<HttpResponse, ServiceError> {
+    let user = LoginData::find_by_id(login.as_str())
+        .one(&db.db_connection)
+        .await?
+        .ok_or(errors::UserIdError::NoUser)?;
+    let data = UserData::find_by_id(user.user_id)
+        .one(&db.db_connection)
+        .await?
+        .ok_or(ServiceError::UserNotFound)?;
+    let version = data.banner_version();
+    let (Some(key), Some(content_type)) = (data.banner_key, data.banner_content_type) else {
+        return Err(ServiceError::NotFound);
+    };
+    let cache_control = if query.v.is_some() && query.v == version {
+        header::CacheControl(vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(resources::IMMUTABLE_MAX_AGE),
+            CacheDirective::Extension("immutable".to_owned(), None),
+        ])
+    } else {
+        header::CacheControl(vec![CacheDirective::NoCache])
+    };
+    let validators = resources::CacheValidators::new(&key, data.banner_uploaded);
+    if validators.is_fresh(&req) {
+        return Ok(validators.not_modified(cache_control));
     }
+    let banner = store.get(&key).await?.ok_or(ServiceError::NotFound)?;
+    Ok(validators.respond(&req, cache_control, &content_type, banner))
 }
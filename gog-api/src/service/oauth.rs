@@ -0,0 +1,768 @@
+use super::entity::prelude::*;
+use super::{helpers, DbConnection};
+use crate::entity::{login_data, oauth_clients, oauth_codes, oauth_tokens};
+use crate::errors::OAuthError;
+use crate::oauth::{self, IdTokenClaims, OAuthProvider};
+use crate::session::TokenSession;
+use actix_session::Session;
+use actix_web::{
+    http::header::{self, CacheDirective},
+    web::{self, Data, Form, Json, Query},
+    HttpRequest, HttpResponse, ResponseError,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use gog_commons::data_structures::{
+    OAuthAuthorizeQuery, OAuthClientCredentials, OAuthClientInfo, OAuthClientRegistration,
+    OAuthConsent, OAuthConsentResponse,
+};
+use log::{debug, info};
+use sea_orm::{
+    sea_query::Expr, ActiveValue, ColumnTrait, EntityTrait, QueryFilter, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use uuid::Uuid;
+use validator::Validate;
+
+pub fn configure_service(cfg: &mut web::ServiceConfig) {
+    let scope = web::scope("/oauth")
+        .service(oauth_clients_register)
+        .service(oauth_clients_list)
+        .service(oauth_authorize_info)
+        .service(oauth_authorize_consent)
+        .service(oauth_token)
+        .service(oauth_userinfo)
+        .service(oauth_revoke)
+        .service(oauth_jwks);
+    cfg.service(scope);
+    cfg.service(openid_configuration);
+}
+
+#[actix_web::post("clients")]
+async fn oauth_clients_register(
+    registration: Json<OAuthClientRegistration>,
+    db: Data<DbConnection>,
+    token_session: Data<Mutex<dyn TokenSession>>,
+    session: Session,
+) -> super::ServiceResult {
+    let registration = registration.into_inner();
+    if let Err(errors) = registration.validate() {
+        return Ok(HttpResponse::BadRequest()
+            .reason("client registration validation failed")
+            .json(errors));
+    }
+    if registration
+        .redirect_uris
+        .iter()
+        .any(|uri| !is_valid_redirect_uri(uri))
+    {
+        return Ok(HttpResponse::BadRequest()
+            .reason("invalid redirect uri")
+            .finish());
+    }
+
+    let login = helpers::validate_session(&token_session, &session, &db).await?;
+    let owner_id = helpers::get_user_id(&login, &db).await?;
+
+    let secret = registration.confidential.then(oauth::random_token);
+    let model = oauth_clients::ActiveModel {
+        client_id: ActiveValue::Set(Uuid::new_v4()),
+        owner_id: ActiveValue::Set(owner_id),
+        name: ActiveValue::Set(registration.name.clone()),
+        secret_hash: ActiveValue::Set(secret.as_deref().map(oauth::hash_token)),
+        redirect_uris: ActiveValue::Set(registration.redirect_uris.join("\n")),
+        created: ActiveValue::Set(chrono::Utc::now()),
+    };
+    let client = OAuthClients::insert(model)
+        .exec_with_returning(&db.db_connection)
+        .await?;
+    info!("registered oauth client {} for {}", client.client_id, login);
+
+    Ok(HttpResponse::Created().json(OAuthClientCredentials {
+        client_id: client.client_id,
+        name: client.name,
+        redirect_uris: registration.redirect_uris,
+        client_secret: secret,
+    }))
+}
+
+#[actix_web::get("clients")]
+async fn oauth_clients_list(
+    db: Data<DbConnection>,
+    token_session: Data<Mutex<dyn TokenSession>>,
+    session: Session,
+) -> super::ServiceResult {
+    let login = helpers::validate_session(&token_session, &session, &db).await?;
+    let owner_id = helpers::get_user_id(&login, &db).await?;
+    let clients = OAuthClients::find()
+        .filter(oauth_clients::Column::OwnerId.eq(owner_id))
+        .all(&db.db_connection)
+        .await?
+        .into_iter()
+        .map(|c| OAuthClientCredentials {
+            client_id: c.client_id,
+            redirect_uris: c.redirect_uris.lines().map(|l| l.to_owned()).collect(),
+            name: c.name,
+            client_secret: None,
+        })
+        .collect::<Vec<_>>();
+    Ok(HttpResponse::Ok().json(clients))
+}
+
+fn is_valid_redirect_uri(uri: &str) -> bool {
+    match url::Url::parse(uri) {
+        Ok(url) => url.fragment().is_none() && !url.cannot_be_a_base(),
+        Err(_) => false,
+    }
+}
+
+/// Checks an authorization request against the registered client
+async fn validate_authorize_request(
+    query: &OAuthAuthorizeQuery,
+    db: &DbConnection,
+) -> Result<oauth_clients::Model, OAuthError> {
+    if query.response_type != "code" {
+        return Err(OAuthError::InvalidRequest(
+            "only the code response type is supported",
+        ));
+    }
+    if query.code_challenge_method.as_deref() != Some("S256") {
+        return Err(OAuthError::InvalidRequest("PKCE with S256 is required"));
+    }
+    if query.code_challenge.len() != 43 {
+        return Err(OAuthError::InvalidRequest("malformed code_challenge"));
+    }
+    let Some(client) = OAuthClients::find_by_id(query.client_id)
+        .one(&db.db_connection)
+        .await?
+    else {
+        return Err(OAuthError::InvalidClient);
+    };
+    if !client.allows_redirect(&query.redirect_uri) {
+        return Err(OAuthError::InvalidRequest("redirect_uri is not registered"));
+    }
+    Ok(client)
+}
+
+#[actix_web::get("authorize")]
+async fn oauth_authorize_info(
+    query: Query<OAuthAuthorizeQuery>,
+    db: Data<DbConnection>,
+) -> Result<HttpResponse, OAuthError> {
+    let query = query.into_inner();
+    let client = validate_authorize_request(&query, &db).await?;
+    Ok(HttpResponse::Ok().json(OAuthClientInfo {
+        client_id: client.client_id,
+        name: client.name,
+        scopes: oauth::normalize_scope(query.scope.as_deref()),
+    }))
+}
+
+#[actix_web::post("authorize")]
+async fn oauth_authorize_consent(
+    consent: Json<OAuthConsent>,
+    db: Data<DbConnection>,
+    token_session: Data<Mutex<dyn TokenSession>>,
+    session: Session,
+) -> super::ServiceResult {
+    let login = helpers::validate_session(&token_session, &session, &db).await?;
+    let user_id = helpers::get_user_id(&login, &db).await?;
+    let OAuthConsent { request, approve } = consent.into_inner();
+
+    if let Err(e) = validate_authorize_request(&request, &db).await {
+        return Ok(e.error_response());
+    }
+    let Ok(mut redirect) = url::Url::parse(&request.redirect_uri) else {
+        return Ok(HttpResponse::BadRequest().finish());
+    };
+
+    if approve {
+        let code = oauth::random_token();
+        let model = oauth_codes::ActiveModel {
+            code_hash: ActiveValue::Set(oauth::hash_token(&code)),
+            client_id: ActiveValue::Set(request.client_id),
+            user_id: ActiveValue::Set(user_id),
+            redirect_uri: ActiveValue::Set(request.redirect_uri.clone()),
+            scope: ActiveValue::Set(oauth::normalize_scope(request.scope.as_deref()).join(" ")),
+            code_challenge: ActiveValue::Set(request.code_challenge.clone()),
+            nonce: ActiveValue::Set(request.nonce.clone()),
+            expires: ActiveValue::Set(
+                chrono::Utc::now() + chrono::Duration::seconds(oauth::AUTHORIZATION_CODE_TTL),
+            ),
+        };
+        OAuthCodes::insert(model).exec(&db.db_connection).await?;
+        redirect.query_pairs_mut().append_pair("code", &code);
+    } else {
+        redirect
+            .query_pairs_mut()
+            .append_pair("error", "access_denied");
+    }
+    if let Some(state) = &request.state {
+        redirect.query_pairs_mut().append_pair("state", state);
+    }
+    debug!(
+        "oauth consent of {} for client {}: {}",
+        login, request.client_id, approve
+    );
+    Ok(HttpResponse::Ok().json(OAuthConsentResponse {
+        redirect: redirect.to_string(),
+    }))
+}
+
+#[derive(Deserialize)]
+struct TokenRequest {
+    grant_type: String,
+    code: Option<String>,
+    redirect_uri: Option<String>,
+    code_verifier: Option<String>,
+    refresh_token: Option<String>,
+    client_id: Option<Uuid>,
+    client_secret: Option<String>,
+}
+
+#[derive(Serialize)]
+struct TokenResponse {
+    access_token: String,
+    token_type: &'static str,
+    expires_in: i64,
+    refresh_token: String,
+    scope: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id_token: Option<String>,
+}
+
+/// Authenticates a client with either `client_secret_basic` or `client_secret_post`.
+/// Public clients only have to name themselves, they are bound by PKCE instead.
+async fn authenticate_client(
+    req: &HttpRequest,
+    client_id: Option<Uuid>,
+    client_secret: Option<&str>,
+    db: &DbConnection,
+) -> Result<oauth_clients::Model, OAuthError> {
+    let basic = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Basic "))
+        .and_then(|b| STANDARD.decode(b).ok())
+        .and_then(|b| String::from_utf8(b).ok())
+        .and_then(|s| {
+            let (id, secret) = s.split_once(':')?;
+            Some((id.parse::<Uuid>().ok()?, secret.to_owned()))
+        });
+    let (client_id, secret) = match (basic, client_id) {
+        (Some((id, secret)), _) => (id, Some(secret)),
+        (None, Some(id)) => (id, client_secret.map(|s| s.to_owned())),
+        (None, None) => return Err(OAuthError::InvalidClient),
+    };
+    let Some(client) = OAuthClients::find_by_id(client_id)
+        .one(&db.db_connection)
+        .await?
+    else {
+        return Err(OAuthError::InvalidClient);
+    };
+    match (&client.secret_hash, secret) {
+        (None, _) => Ok(client),
+        (Some(hash), Some(secret)) if oauth::hash_token(&secret).eq(hash) => Ok(client),
+        _ => Err(OAuthError::InvalidClient),
+    }
+}
+
+#[actix_web::post("token")]
+async fn oauth_token(
+    req: HttpRequest,
+    form: Form<TokenRequest>,
+    db: Data<DbConnection>,
+    provider: Data<OAuthProvider>,
+) -> Result<HttpResponse, OAuthError> {
+    let form = form.into_inner();
+    let client =
+        authenticate_client(&req, form.client_id, form.client_secret.as_deref(), &db).await?;
+
+    let response = match form.grant_type.as_str() {
+        "authorization_code" => {
+            let (Some(code), Some(verifier), Some(redirect_uri)) =
+                (form.code, form.code_verifier, form.redirect_uri)
+            else {
+                return Err(OAuthError::InvalidRequest(
+                    "code, code_verifier and redirect_uri are required",
+                ));
+            };
+            let hash = oauth::hash_token(&code);
+            let Some(grant) = OAuthCodes::find_by_id(hash.clone())
+                .one(&db.db_connection)
+                .await?
+            else {
+                return Err(OAuthError::InvalidGrant("unknown authorization code"));
+            };
+            // codes are single use, whoever deletes it first gets to redeem it
+            let deleted = OAuthCodes::delete_by_id(hash)
+                .exec(&db.db_connection)
+                .await?;
+            if deleted.rows_affected == 0 {
+                return Err(OAuthError::InvalidGrant("authorization code already used"));
+            }
+            if grant.client_id != client.client_id || grant.redirect_uri != redirect_uri {
+                return Err(OAuthError::InvalidGrant("authorization code mismatch"));
+            }
+            if chrono::Utc::now() >= grant.expires {
+                return Err(OAuthError::InvalidGrant("authorization code expired"));
+            }
+            if !oauth::verify_pkce(&verifier, &grant.code_challenge) {
+                return Err(OAuthError::InvalidGrant("code_verifier does not match"));
+            }
+            issue_tokens(
+                &db,
+                &provider,
+                &client,
+                grant.user_id,
+                &grant.scope,
+                grant.nonce,
+                None,
+            )
+            .await?
+        }
+        "refresh_token" => {
+            let Some(refresh) = form.refresh_token else {
+                return Err(OAuthError::InvalidRequest("refresh_token is required"));
+            };
+            let Some(token) = OAuthTokens::find_by_id(oauth::hash_token(&refresh))
+                .one(&db.db_connection)
+                .await?
+            else {
+                return Err(OAuthError::InvalidGrant("unknown refresh token"));
+            };
+            if token.kind != oauth_tokens::KIND_REFRESH
+                || token.client_id != client.client_id
+                || !token.is_valid()
+            {
+                return Err(OAuthError::InvalidGrant("refresh token is not valid"));
+            }
+            // refresh tokens are rotated on every use
+            issue_tokens(
+                &db,
+                &provider,
+                &client,
+                token.user_id,
+                &token.scope,
+                None,
+                Some(token.token_hash),
+            )
+            .await?
+        }
+        _ => return Err(OAuthError::UnsupportedGrantType),
+    };
+
+    Ok(HttpResponse::Ok()
+        .append_header(header::CacheControl(vec![CacheDirective::NoStore]))
+        .json(response))
+}
+
+async fn issue_tokens(
+    db: &DbConnection,
+    provider: &OAuthProvider,
+    client: &oauth_clients::Model,
+    user_id: Uuid,
+    scope: &str,
+    nonce: Option<String>,
+    rotated: Option<String>,
+) -> Result<TokenResponse, OAuthError> {
+    let Some(user) = LoginData::find()
+        .filter(login_data::Column::UserId.eq(user_id))
+        .one(&db.db_connection)
+        .await?
+    else {
+        return Err(OAuthError::InvalidGrant("user no longer exists"));
+    };
+    let now = chrono::Utc::now();
+    let access_token = oauth::random_token();
+    let refresh_token = oauth::random_token();
+
+    let token_model = |token: &str, kind: &str, ttl: i64| oauth_tokens::ActiveModel {
+        token_hash: ActiveValue::Set(oauth::hash_token(token)),
+        kind: ActiveValue::Set(kind.to_owned()),
+        client_id: ActiveValue::Set(client.client_id),
+        user_id: ActiveValue::Set(user_id),
+        scope: ActiveValue::Set(scope.to_owned()),
+        expires: ActiveValue::Set(now + chrono::Duration::seconds(ttl)),
+        revoked: ActiveValue::Set(false),
+    };
+    let access = token_model(
+        &access_token,
+        oauth_tokens::KIND_ACCESS,
+        oauth::ACCESS_TOKEN_TTL,
+    );
+    let refresh = token_model(
+        &refresh_token,
+        oauth_tokens::KIND_REFRESH,
+        oauth::REFRESH_TOKEN_TTL,
+    );
+    db.db_connection
+        .transaction::<_, (), OAuthError>(|txn| {
+            Box::pin(async move {
+                // `rotated` is revoked by whoever gets to it first, a replay of it mints nothing
+                if let Some(hash) = rotated {
+                    let revoked = OAuthTokens::update_many()
+                        .col_expr(oauth_tokens::Column::Revoked, Expr::value(true))
+                        .filter(oauth_tokens::Column::TokenHash.eq(hash))
+                        .filter(oauth_tokens::Column::Revoked.eq(false))
+                        .exec(txn)
+                        .await?;
+                    if revoked.rows_affected != 1 {
+                        return Err(OAuthError::InvalidGrant("refresh token already used"));
+                    }
+                }
+                OAuthTokens::insert(access).exec(txn).await?;
+                OAuthTokens::insert(refresh).exec(txn).await?;
+                Ok(())
+            })
+        })
+        .await
+        .map_err(|e| match e {
+            sea_orm::TransactionError::Connection(e) => OAuthError::from(e),
+            sea_orm::TransactionError::Transaction(e) => e,
+        })?;
+
+    let id_token = if scope.split_whitespace().any(|s| s == "openid") {
+        Some(provider.sign_id_token(&IdTokenClaims {
+            iss: provider.issuer().to_owned(),
+            sub: user_id.to_string(),
+            aud: client.client_id.to_string(),
+            exp: (now + chrono::Duration::seconds(oauth::ACCESS_TOKEN_TTL)).timestamp(),
+            iat: now.timestamp(),
+            nonce,
+            preferred_username: user.login,
+        })?)
+    } else {
+        None
+    };
+
+    Ok(TokenResponse {
+        access_token,
+        token_type: "Bearer",
+        expires_in: oauth::ACCESS_TOKEN_TTL,
+        refresh_token,
+        scope: scope.to_owned(),
+        id_token,
+    })
+}
+
+#[derive(Serialize)]
+struct UserInfoResponse {
+    sub: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    preferred_username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    profile: Option<String>,
+}
+
+#[actix_web::get("userinfo")]
+async fn oauth_userinfo(
+    req: HttpRequest,
+    db: Data<DbConnection>,
+    provider: Data<OAuthProvider>,
+) -> Result<HttpResponse, OAuthError> {
+    let token = helpers::validate_bearer(&req, &db).await?;
+    let Some(user) = LoginData::find()
+        .filter(login_data::Column::UserId.eq(token.user_id))
+        .one(&db.db_connection)
+        .await?
+    else {
+        return Err(OAuthError::InvalidToken);
+    };
+    let profile = token.scope.split_whitespace().any(|s| s == "profile");
+    Ok(HttpResponse::Ok().json(UserInfoResponse {
+        sub: user.user_id.to_string(),
+        preferred_username: profile.then(|| user.login.clone()),
+        profile: profile
+            .then(|| format!("{}/user/profile?username={}", provider.issuer(), user.login)),
+    }))
+}
+
+#[derive(Deserialize)]
+struct RevokeRequest {
+    token: String,
+    client_id: Option<Uuid>,
+    client_secret: Option<String>,
+}
+
+#[actix_web::post("revoke")]
+async fn oauth_revoke(
+    req: HttpRequest,
+    form: Form<RevokeRequest>,
+    db: Data<DbConnection>,
+) -> Result<HttpResponse, OAuthError> {
+    let form = form.into_inner();
+    let client =
+        authenticate_client(&req, form.client_id, form.client_secret.as_deref(), &db).await?;
+    let token = OAuthTokens::find_by_id(oauth::hash_token(&form.token))
+        .one(&db.db_connection)
+        .await?;
+    // unknown tokens are not an error (RFC 7009 section 2.2)
+    let Some(token) = token.filter(|t| t.client_id == client.client_id) else {
+        return Ok(HttpResponse::Ok().finish());
+    };
+    if token.kind == oauth_tokens::KIND_REFRESH {
+        // revoking a refresh token ends the whole grant
+        OAuthTokens::update_many()
+            .col_expr(
+                oauth_tokens::Column::Revoked,
+                sea_orm::sea_query::Expr::value(true),
+            )
+            .filter(oauth_tokens::Column::ClientId.eq(token.client_id))
+            .filter(oauth_tokens::Column::UserId.eq(token.user_id))
+            .exec(&db.db_connection)
+            .await?;
+    } else {
+        let mut model: oauth_tokens::ActiveModel = token.into();
+        model.revoked = ActiveValue::Set(true);
+        OAuthTokens::update(model).exec(&db.db_connection).await?;
+    }
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[actix_web::get("jwks")]
+async fn oauth_jwks(provider: Data<OAuthProvider>) -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({ "keys": [provider.jwk()] }))
+}
+
+#[actix_web::get("/.well-known/openid-configuration")]
+async fn openid_configuration(provider: Data<OAuthProvider>) -> HttpResponse {
+    let issuer = provider.issuer();
+    HttpResponse::Ok().json(serde_json::json!({
+        "issuer": issuer,
+        "authorization_endpoint": provider.authorization_endpoint(),
+        "token_endpoint": format!("{}/oauth/token", issuer),
+        "userinfo_endpoint": format!("{}/oauth/userinfo", issuer),
+        "revocation_endpoint": format!("{}/oauth/revoke", issuer),
+        "jwks_uri": format!("{}/oauth/jwks", issuer),
+        "response_types_supported": ["code"],
+        "grant_types_supported": ["authorization_code", "refresh_token"],
+        "subject_types_supported": ["public"],
+        "id_token_signing_alg_values_supported": ["RS256"],
+        "scopes_supported": oauth::SUPPORTED_SCOPES,
+        "token_endpoint_auth_methods_supported":
+            ["client_secret_basic", "client_secret_post", "none"],
+        "code_challenge_methods_supported": ["S256"],
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migrator::{schema::scratch_database, Migrator};
+    use crate::service::{hash_password, new_user, SESSION_ID};
+    use crate::session::DefaultTokenSession;
+    use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+    use actix_web::{
+        cookie::{Cookie, Key},
+        dev::ServiceResponse,
+        http::StatusCode,
+        test::{self, TestRequest},
+        App,
+    };
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use sea_orm::ActiveModelTrait;
+    use sea_orm_migration::MigratorTrait;
+    use serde_json::Value;
+    use sha2::{Digest, Sha256};
+    use std::sync::Arc;
+
+    const LOGIN: &str = "alice";
+    const CALLBACK: &str = "http://127.0.0.1:9000/cb";
+
+    /// Signs the test user in the way `/user/login` does
+    async fn sign_in(
+        session: Session,
+        token_session: Data<Mutex<dyn TokenSession>>,
+    ) -> HttpResponse {
+        let token = token_session.lock().unwrap().add_user(LOGIN);
+        session.insert(SESSION_ID, token.to_string()).unwrap();
+        HttpResponse::Ok().finish()
+    }
+
+    async fn json(resp: ServiceResponse) -> Value {
+        test::read_body_json(resp).await
+    }
+
+    fn token_request(params: &[(&str, &str)]) -> TestRequest {
+        TestRequest::post().uri("/oauth/token").set_form(params)
+    }
+
+    /// The signed in user approving a request for `challenge`
+    fn consent(cookie: &Cookie<'static>, client_id: &str, challenge: &str) -> TestRequest {
+        TestRequest::post()
+            .uri("/oauth/authorize")
+            .cookie(cookie.clone())
+            .set_json(serde_json::json!({
+                "approve": true,
+                "request": {
+                    "response_type": "code",
+                    "client_id": client_id,
+                    "redirect_uri": CALLBACK,
+                    "scope": "profile openid",
+                    "state": "xyz",
+                    "code_challenge": challenge,
+                    "code_challenge_method": "S256",
+                },
+            }))
+    }
+
+    /// The code the consent response redirects to the client with
+    async fn code_of(resp: ServiceResponse) -> String {
+        assert_eq!(resp.status(), StatusCode::OK);
+        let redirect = url::Url::parse(json(resp).await["redirect"].as_str().unwrap()).unwrap();
+        assert!(redirect.as_str().starts_with(CALLBACK));
+        let query: std::collections::HashMap<_, _> = redirect.query_pairs().into_owned().collect();
+        assert_eq!(query["state"], "xyz");
+        query["code"].clone()
+    }
+
+    #[actix_web::test]
+    async fn code_token_userinfo_refresh_and_revoke() {
+        let db = scratch_database().await.unwrap();
+        Migrator::up(&db, None).await.unwrap();
+        let (salt, hash) = hash_password("password").unwrap();
+        let (login, data) = new_user(
+            LOGIN,
+            &salt,
+            hash,
+            gog_commons::data_structures::Role::User,
+            None,
+        );
+        login.insert(&db).await.unwrap();
+        data.insert(&db).await.unwrap();
+
+        let key_path = std::env::temp_dir().join(format!("gog-oauth-{}.pem", Uuid::new_v4()));
+        let provider = OAuthProvider::load_or_generate(
+            "http://127.0.0.1:8081",
+            "http://127.0.0.1:8082",
+            key_path.to_str().unwrap(),
+        )
+        .unwrap();
+        let _ = std::fs::remove_file(&key_path);
+        let token_session: Arc<Mutex<dyn TokenSession>> =
+            Arc::new(Mutex::new(DefaultTokenSession::new()));
+        let app = test::init_service(
+            App::new()
+                .configure(configure_service)
+                .route("/login", web::post().to(sign_in))
+                .app_data(Data::new(DbConnection::new(db)))
+                .app_data(Data::from(token_session))
+                .app_data(Data::new(provider))
+                .wrap(SessionMiddleware::new(
+                    CookieSessionStore::default(),
+                    Key::generate(),
+                )),
+        )
+        .await;
+
+        let resp = test::call_service(&app, TestRequest::post().uri("/login").to_request()).await;
+        let cookie = resp.response().cookies().next().unwrap().into_owned();
+        let req = TestRequest::post()
+            .uri("/oauth/clients")
+            .cookie(cookie.clone())
+            .set_json(serde_json::json!({
+                "name": "dummy",
+                "redirect_uris": [CALLBACK],
+                "confidential": false,
+            }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let client = json(resp).await;
+        let client_id = client["client_id"].as_str().unwrap().to_owned();
+
+        let verifier = oauth::random_token();
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+        let exchange = |code: &str, verifier: &str| {
+            token_request(&[
+                ("grant_type", "authorization_code"),
+                ("client_id", &client_id),
+                ("code", code),
+                ("code_verifier", verifier),
+                ("redirect_uri", CALLBACK),
+            ])
+            .to_request()
+        };
+
+        // PKCE: a verifier that does not hash to the challenge gets nothing, and burns the code
+        let req = consent(&cookie, &client_id, &challenge).to_request();
+        let code = code_of(test::call_service(&app, req).await).await;
+        let resp = test::call_service(&app, exchange(&code, &oauth::random_token())).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(json(resp).await["error"], "invalid_grant");
+        let resp = test::call_service(&app, exchange(&code, &verifier)).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let req = consent(&cookie, &client_id, &challenge).to_request();
+        let code = code_of(test::call_service(&app, req).await).await;
+        let resp = test::call_service(&app, exchange(&code, &verifier)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let tokens = json(resp).await;
+        assert_eq!(tokens["token_type"], "Bearer");
+        assert_eq!(tokens["scope"], "openid profile");
+        assert!(tokens["id_token"].is_string());
+        let resp = test::call_service(&app, exchange(&code, &verifier)).await;
+        assert_eq!(
+            resp.status(),
+            StatusCode::BAD_REQUEST,
+            "codes are single use"
+        );
+
+        let userinfo = |access: &str| {
+            TestRequest::get()
+                .uri("/oauth/userinfo")
+                .insert_header((header::AUTHORIZATION, format!("Bearer {}", access)))
+                .to_request()
+        };
+        let resp =
+            test::call_service(&app, userinfo(tokens["access_token"].as_str().unwrap())).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(json(resp).await["preferred_username"], LOGIN);
+
+        let refresh = |token: &str| {
+            token_request(&[
+                ("grant_type", "refresh_token"),
+                ("client_id", &client_id),
+                ("refresh_token", token),
+            ])
+            .to_request()
+        };
+        let first_refresh = tokens["refresh_token"].as_str().unwrap();
+        let resp = test::call_service(&app, refresh(first_refresh)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let rotated = json(resp).await;
+        assert_ne!(rotated["refresh_token"], tokens["refresh_token"]);
+        let resp = test::call_service(&app, refresh(first_refresh)).await;
+        assert_eq!(
+            resp.status(),
+            StatusCode::BAD_REQUEST,
+            "refresh tokens are single use"
+        );
+
+        let access = rotated["access_token"].as_str().unwrap();
+        let resp = test::call_service(&app, userinfo(access)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let revoke = TestRequest::post()
+            .uri("/oauth/revoke")
+            .set_form([
+                ("token", rotated["refresh_token"].as_str().unwrap()),
+                ("client_id", &client_id),
+            ])
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, revoke).await.status(),
+            StatusCode::OK
+        );
+        let resp = test::call_service(&app, userinfo(access)).await;
+        assert_eq!(
+            resp.status(),
+            StatusCode::UNAUTHORIZED,
+            "revoking ends the grant"
+        );
+        let resp =
+            test::call_service(&app, refresh(rotated["refresh_token"].as_str().unwrap())).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+}
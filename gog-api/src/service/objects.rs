@@ -22,15 +22,36 @@ impl UserUpdateDataExt for gog_commons::data_structures::UserUpdateData {
         if let Some(desc) = self.description {
             model.description = sea_orm::ActiveValue::Set(Some(desc));
         }
-        if let Some(gender) = self.gender {
-            model.gender = sea_orm::ActiveValue::Set(Some(gender));
+        let cleared = |v: String| {
+            let v = v.trim().to_owned();
+            sea_orm::ActiveValue::Set((!v.is_empty()).then_some(v))
+        };
+        if let Some(pronouns) = self.pronouns {
+            model.pronouns = cleared(pronouns);
+        }
+        if let Some(display_name) = self.display_name {
+            model.display_name = cleared(display_name);
+        }
+        if let Some(location) = self.location {
+            model.location = cleared(location);
+        }
+        if let Some(website) = self.website {
+            model.website = cleared(website);
+        }
+        if let Some(fields) = self.fields {
+            let json = serde_json::to_string(&fields).expect("profile fields serialize");
+            model.profile_fields = sea_orm::ActiveValue::Set(Some(json));
+        }
+        if let Some(visibility) = self.visibility {
+            let json = serde_json::to_string(&visibility).expect("visibility serializes");
+            model.visibility = sea_orm::ActiveValue::Set(Some(json));
         }
     }
 }
 
 #[derive(Clone)]
 pub struct DbConnection {
-    pub(super) db_connection: sea_orm::DatabaseConnection,
+    pub(crate) db_connection: sea_orm::DatabaseConnection,
 }
 impl DbConnection {
     pub fn new(db_connection: sea_orm::prelude::DatabaseConnection) -> Self {
@@ -0,0 +1,137 @@
+use super::{helpers, DbConnection};
+use crate::errors::ServiceError;
+use crate::session::TokenSession;
+use actix_session::SessionExt;
+use actix_web::{dev::Payload, web::Data, FromRequest, HttpRequest};
+use gog_commons::data_structures::Role;
+use std::{future::Future, marker::PhantomData, pin::Pin, sync::Mutex};
+use uuid::Uuid;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Permission {
+    ListUsers,
+    ManageRoles,
+    SuspendUsers,
+    ForceLogout,
+    DeleteAnyContent,
+    ModerateReports,
+    ReloadModeration,
+    ManageInvites,
+    ViewServerStats,
+    ManageBackups,
+}
+
+pub trait RoleExt {
+    fn has_permission(&self, permission: Permission) -> bool;
+}
+
+impl RoleExt for Role {
+    fn has_permission(&self, permission: Permission) -> bool {
+        match self {
+            Role::Admin => true,
+            Role::Moderator => !matches!(
+                permission,
+                Permission::ManageRoles | Permission::ManageInvites | Permission::ManageBackups
+            ),
+            Role::User => false,
+        }
+    }
+}
+
+/// The user owning the request session, suspended accounts never get extracted
+#[derive(Clone, Debug)]
+pub struct AuthUser {
+    pub login: String,
+    pub user_id: Uuid,
+    pub role: Role,
+}
+
+impl AuthUser {
+    pub fn require(&self, permission: Permission) -> Result<(), ServiceError> {
+        if self.role.has_permission(permission) {
+            Ok(())
+        } else {
+            Err(ServiceError::Forbidden)
+        }
+    }
+}
+
+impl FromRequest for AuthUser {
+    type Error = ServiceError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let session = req.get_session();
+        let token_session = req
+            .app_data::<Data<Mutex<dyn TokenSession>>>()
+            .expect("token session is not configured")
+            .clone();
+        let db = req
+            .app_data::<Data<DbConnection>>()
+            .expect("database is not configured")
+            .clone();
+        Box::pin(async move {
+            let model = helpers::session_account(&token_session, &session, &db).await?;
+            Ok(AuthUser {
+                role: model.role(),
+                login: model.login,
+                user_id: model.user_id,
+            })
+        })
+    }
+}
+
+pub trait PermissionMarker {
+    const PERMISSION: Permission;
+}
+
+/// Extracts an [`AuthUser`] holding the permission `P`, see [`can`]
+pub struct Authorized<P: PermissionMarker> {
+    pub user: AuthUser,
+    _permission: PhantomData<P>,
+}
+
+impl<P: PermissionMarker + 'static> FromRequest for Authorized<P> {
+    type Error = ServiceError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let user = AuthUser::from_request(req, payload);
+        Box::pin(async move {
+            let user = user.await?;
+            user.require(P::PERMISSION)?;
+            Ok(Authorized {
+                user,
+                _permission: PhantomData,
+            })
+        })
+    }
+}
+
+/// Marker types for [`Authorized`], e.g. `Authorized<can::ListUsers>`
+pub mod can {
+    use super::{Permission, PermissionMarker};
+
+    macro_rules! permission_markers {
+        ($($name:ident),*) => {
+            $(
+                pub struct $name;
+                impl PermissionMarker for $name {
+                    const PERMISSION: Permission = Permission::$name;
+                }
+            )*
+        };
+    }
+
+    permission_markers!(
+        ListUsers,
+        ManageRoles,
+        SuspendUsers,
+        ForceLogout,
+        DeleteAnyContent,
+        ModerateReports,
+        ReloadModeration,
+        ViewServerStats,
+        ManageBackups
+    );
+}
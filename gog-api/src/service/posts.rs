@@ -3,27 +3,40 @@ use actix_web::{
     web::{self, Data, Json, Query},
     Either, HttpResponse,
 };
-use gog_commons::data_structures::PostData;
+use gog_commons::data_structures::{AttachmentData, PostData};
 use sea_orm::{
-    ActiveModelBehavior, ActiveValue, ColumnTrait, EntityTrait, QueryFilter, QueryOrder,
-    QuerySelect, Related,
+    ActiveModelBehavior, ActiveValue, ColumnTrait, DbErr, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect, Related, TransactionError, TransactionTrait,
 };
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
-use validator::Validate;
 
 use crate::{
+    config::Limits,
     entity::{comments, login_data, posts},
     errors::ServiceError,
+    moderation::{ContentContext, ContentKind, ModerationService},
     session::TokenSession,
 };
 
-use super::{helpers, DbConnection, ServiceResult};
-use gog_commons::data_structures::PostCreationData;
+use super::attachments::{self, AltTextPolicy};
+use super::permissions::AuthUser;
+use super::{helpers, resources, DbConnection, ServiceResult};
+use gog_commons::data_structures::{PostCreationData, ValidationErrorResponse};
 use std::sync::Mutex;
-pub fn configure_service(cfg: &mut web::ServiceConfig) {
+pub fn configure_service(cfg: &mut web::ServiceConfig, limits: &Limits) {
+    use actix_web::guard;
     let posts_scope = web::scope("/posts")
+        .service(
+            web::resource("/attachments")
+                .guard(guard::fn_guard(resources::image_content_type))
+                .guard(guard::Post())
+                .app_data(web::PayloadConfig::new(limits.attachment_bytes))
+                .route(web::post().to(attachments::attachments_upload)),
+        )
+        .service(attachments::attachments_get)
         .service(posts_create)
+        .service(posts_edit)
         .service(posts_newest)
         .service(posts_user)
         .service(posts_filter)
@@ -37,31 +50,112 @@ async fn posts_create(
     session: Session,
     token_session: Data<Mutex<dyn TokenSession>>,
     db: Data<DbConnection>,
+    moderation: Data<ModerationService>,
+    alt_text: Data<AltTextPolicy>,
+    limits: Data<Limits>,
 ) -> super::ServiceResult {
-    if let Err(errors) = post_data.validate() {
+    if let Err(errors) = limits.validate_content(ContentKind::Post, &post_data.content, &*post_data)
+    {
         return Ok(HttpResponse::BadRequest()
             .reason("post creation data validation failed")
-            .json(errors));
+            .json(ValidationErrorResponse {
+                reason: "post creation data validation failed".to_owned(),
+                errors,
+            }));
     };
+    if let Err(res) = attachments::check_refs(&post_data.attachments, **alt_text) {
+        return Ok(res);
+    }
 
-    let login = helpers::validate_session(&token_session, &session)?;
+    let login = helpers::validate_session(&token_session, &session, &db).await?;
     let id = helpers::get_user_id(&login, &db).await?;
 
+    let ctx = ContentContext {
+        kind: ContentKind::Post,
+        user_id: id,
+        editing: None,
+        db: &db.db_connection,
+    };
+    let content = moderation.pipeline().run(&post_data.content, &ctx).await?;
+
+    let post_id = Uuid::new_v4();
     let model = posts::ActiveModel {
-        post_id: ActiveValue::Set(Uuid::new_v4()),
+        post_id: ActiveValue::Set(post_id),
         user_id: ActiveValue::Set(id),
         posted: ActiveValue::Set(chrono::Utc::now()),
-        content: ActiveValue::Set(post_data.content.clone()),
+        content: ActiveValue::Set(content),
+    };
+    let refs = post_data.into_inner().attachments;
+
+    let res = db
+        .db_connection
+        .transaction::<_, (), DbErr>(|txn| {
+            Box::pin(async move {
+                posts::Entity::insert(model).exec(txn).await?;
+                attachments::attach(refs, id, post_id, txn).await
+            })
+        })
+        .await;
+    match res {
+        Ok(()) => Ok(HttpResponse::Created().finish()),
+        Err(TransactionError::Transaction(DbErr::Custom(msg)))
+            if msg == attachments::ATTACHMENT_UNAVAILABLE =>
+        {
+            Ok(HttpResponse::BadRequest()
+                .reason("attached image does not exist or is already used")
+                .finish())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[actix_web::post("edit/{post_id}")]
+async fn posts_edit(
+    user: AuthUser,
+    post_id: web::Path<Uuid>,
+    post_data: Json<PostCreationData>,
+    db: Data<DbConnection>,
+    moderation: Data<ModerationService>,
+    limits: Data<Limits>,
+) -> super::ServiceResult {
+    if let Err(errors) = limits.validate_content(ContentKind::Post, &post_data.content, &*post_data)
+    {
+        return Ok(HttpResponse::BadRequest()
+            .reason("post edit data validation failed")
+            .json(ValidationErrorResponse {
+                reason: "post edit data validation failed".to_owned(),
+                errors,
+            }));
+    };
+    let Some(post) = posts::Entity::find_by_id(post_id.into_inner())
+        .one(&db.db_connection)
+        .await?
+    else {
+        return Err(ServiceError::NotFound);
     };
+    if post.user_id != user.user_id {
+        return Err(ServiceError::Forbidden);
+    }
+
+    let ctx = ContentContext {
+        kind: ContentKind::Post,
+        user_id: user.user_id,
+        editing: Some(post.post_id),
+        db: &db.db_connection,
+    };
+    let content = moderation.pipeline().run(&post_data.content, &ctx).await?;
 
-    posts::Entity::insert(model).exec(&db.db_connection).await?;
-    Ok(HttpResponse::Created().finish())
+    let mut model: posts::ActiveModel = post.into();
+    model.content = ActiveValue::Set(content);
+    posts::Entity::update(model).exec(&db.db_connection).await?;
+    Ok(HttpResponse::Ok().finish())
 }
 
 #[actix_web::post("filter")]
 async fn posts_filter(
     db: Data<DbConnection>,
     filter: Json<gog_commons::data_structures::PostsFilter>,
+    limits: Data<Limits>,
 ) -> super::ServiceResult {
     let filter = filter.into_inner();
     let mut posts = super::entity::posts::Entity::find().find_also_related(login_data::Entity);
@@ -71,72 +165,70 @@ async fn posts_filter(
     } else if let Some(uid) = filter.user_id {
         posts = posts.filter(login_data::Column::UserId.eq(uid));
     }
-    posts = posts.limit(filter.limit.unwrap_or(20));
     let posts = posts
         .order_by_desc(posts::Column::Posted)
-        .limit(filter.limit)
+        .limit(limits.posts_page(filter.limit))
         .all(&db.db_connection)
         .await?;
 
     // let body = serde_json::to_string(&posts)
     //     .or_else(|e| Err(super::ServiceError::ServerError{source:Box::new(e)}))?;
 
-    Ok(HttpResponse::Found().json(
-        posts
-            .into_iter()
-            .map(|e| PostResponse {
-                login: match e.1 {
-                    Some(m) => m.login,
-                    None => String::new(),
-                },
-                content: e.0.content,
-                post_id: e.0.post_id,
-                user_id: e.0.post_id,
-                posted: e.0.posted,
-            })
-            .collect::<Vec<_>>(),
-    ))
+    Ok(HttpResponse::Found().json(post_responses(posts, &db).await?))
 }
 
 #[actix_web::get("newest/{amount}")]
 async fn posts_newest(
     amount: actix_web::web::Path<u64>,
     db: Data<DbConnection>,
+    limits: Data<Limits>,
 ) -> super::ServiceResult {
     let posts = super::entity::posts::Entity::find()
         .find_also_related(login_data::Entity)
         .order_by_desc(posts::Column::Posted)
-        .limit(Some(amount.into_inner()))
+        .limit(limits.posts_page(Some(amount.into_inner())))
         .all(&db.db_connection)
         .await?;
 
     // let body = serde_json::to_string(&posts)
     //     .or_else(|e| Err(super::ServiceError::ServerError{source:Box::new(e)}))?;
 
-    Ok(HttpResponse::Found().json(
-        posts
-            .into_iter()
-            .map(|e| PostResponse {
-                login: match e.1 {
-                    Some(m) => m.login,
-                    None => String::new(),
-                },
-                content: e.0.content,
-                post_id: e.0.post_id,
-                user_id: e.0.post_id,
-                posted: e.0.posted,
-            })
-            .collect::<Vec<_>>(),
-    ))
+    Ok(HttpResponse::Found().json(post_responses(posts, &db).await?))
 }
 
 #[derive(Serialize)]
 struct PostResponse {
     login: String,
+    pfp_version: Option<String>,
     user_id: Uuid,
     post_id: Uuid,
     posted: chrono::DateTime<chrono::Utc>,
     content: String,
+    attachments: Vec<AttachmentData>,
+}
+
+async fn post_responses(
+    posts: Vec<(posts::Model, Option<login_data::Model>)>,
+    db: &DbConnection,
+) -> Result<Vec<PostResponse>, DbErr> {
+    let mut attached =
+        attachments::for_posts(posts.iter().map(|(p, _)| p.post_id), &db.db_connection).await?;
+    Ok(posts
+        .into_iter()
+        .map(|e| {
+            let (login, pfp_version) =
+                e.1.map_or((String::new(), None), |m| (m.login, m.pfp_version));
+            PostResponse {
+                login,
+                pfp_version,
+                attachments: attached.remove(&e.0.post_id).unwrap_or_default(),
+                content: e.0.content,
+                post_id: e.0.post_id,
+                user_id: e.0.user_id,
+                posted: e.0.posted,
+            }
+        })
+        .collect())
 }
 
 #[derive(serde::Deserialize)]
@@ -155,6 +247,7 @@ struct PostIdQuery {
 async fn posts_user(
     query: Either<Query<PostLoginQuery>, Query<PostIdQuery>>,
     db: Data<DbConnection>,
+    limits: Data<Limits>,
 ) -> super::ServiceResult {
     let (id, amount) = match query {
         Either::Right(idq) => (idq.user_id, idq.amount),
@@ -168,24 +261,31 @@ async fn posts_user(
     };
 
     let posts = super::entity::posts::Entity::find()
+        .find_also_related(login_data::Entity)
         .filter(posts::Column::UserId.eq(id))
         .order_by_desc(posts::Column::Posted)
-        .limit(Some(amount))
+        .limit(limits.posts_page(Some(amount)))
         .all(&db.db_connection)
         .await?;
 
-    Ok(HttpResponse::Found().json(posts))
+    Ok(HttpResponse::Found().json(post_responses(posts, &db).await?))
 }
 
 #[actix_web::get("id/{post_id}")]
 async fn posts_id(post_id: web::Path<Uuid>, db: Data<DbConnection>) -> super::ServiceResult {
-    let post = posts::Entity::find_by_id(post_id.into_inner())
+    let post_id = post_id.into_inner();
+    let mut attached = attachments::for_posts([post_id], &db.db_connection).await?;
+    let post = posts::Entity::find_by_id(post_id)
         .find_also_related(login_data::Entity)
         .one(&db.db_connection)
         .await?
         .map_or(None, |(p, l)| {
+            let (login, pfp_version) =
+                l.map_or((String::new(), None), |m| (m.login, m.pfp_version));
             Some(PostData {
-                login: l.map_or("".to_owned(), |m| m.login),
+                login,
+                pfp_version,
+                attachments: attached.remove(&p.post_id).unwrap_or_default(),
                 post_id: p.post_id.to_string(),
                 user_id: p.user_id.to_string(),
                 posted: p.posted,
@@ -0,0 +1,268 @@
+use super::entity::prelude::*;
+use super::permissions::{can, AuthUser, Authorized};
+use super::{admin, attachments, DbConnection, ServiceResult};
+use crate::entity::{comments, login_data, posts, reports};
+use crate::errors::ServiceError;
+use crate::session::TokenSession;
+use crate::storage::BlobStore;
+use actix_web::{
+    web::{self, Data, Json, Path, Query},
+    HttpResponse,
+};
+use gog_commons::data_structures::{
+    ReportAction, ReportCreationData, ReportResolution, ReportStatus, ReportTargetKind,
+};
+use log::info;
+use sea_orm::{
+    sea_query::Expr, ActiveValue, ColumnTrait, EntityTrait, QueryFilter, QueryOrder, QuerySelect,
+};
+use serde::Deserialize;
+use std::sync::Mutex;
+use uuid::Uuid;
+use validator::Validate;
+
+pub fn configure_service(cfg: &mut web::ServiceConfig) {
+    let scope = web::scope("/reports")
+        .service(reports_create)
+        .service(reports_queue)
+        .service(reports_claim)
+        .service(reports_resolve);
+    cfg.service(scope);
+}
+
+/// Returns the id of the user responsible for the reported target, None if it does not exist
+async fn target_author(
+    kind: ReportTargetKind,
+    target_id: Uuid,
+    db: &DbConnection,
+) -> Result<Option<Uuid>, ServiceError> {
+    let db = &db.db_connection;
+    Ok(match kind {
+        ReportTargetKind::Post => posts::Entity::find_by_id(target_id)
+            .one(db)
+            .await?
+            .map(|p| p.user_id),
+        ReportTargetKind::Comment => comments::Entity::find_by_id(target_id)
+            .one(db)
+            .await?
+            .map(|c| c.user_id),
+        ReportTargetKind::User => LoginData::find()
+            .filter(login_data::Column::UserId.eq(target_id))
+            .one(db)
+            .await?
+            .map(|l| l.user_id),
+    })
+}
+
+#[actix_web::post("")]
+async fn reports_create(
+    user: AuthUser,
+    report: Json<ReportCreationData>,
+    db: Data<DbConnection>,
+) -> ServiceResult {
+    let report = report.into_inner();
+    if let Err(errors) = report.validate() {
+        return Ok(HttpResponse::BadRequest()
+            .reason("report validation failed")
+            .json(errors));
+    }
+    if target_author(report.target_kind, report.target_id, &db)
+        .await?
+        .is_none()
+    {
+        return Ok(HttpResponse::NotFound()
+            .reason("reported content does not exist")
+            .finish());
+    }
+    let pending = Reports::find()
+        .filter(reports::Column::ReporterId.eq(user.user_id))
+        .filter(reports::Column::TargetId.eq(report.target_id))
+        .filter(reports::Column::Status.ne(ReportStatus::Resolved.as_str()))
+        .one(&db.db_connection)
+        .await?;
+    if pending.is_some() {
+        return Ok(HttpResponse::Conflict().reason("already reported").finish());
+    }
+
+    let report_id = Uuid::new_v4();
+    let model = reports::ActiveModel {
+        report_id: ActiveValue::Set(report_id),
+        reporter_id: ActiveValue::Set(user.user_id),
+        target_kind: ActiveValue::Set(report.target_kind.as_str().to_owned()),
+        target_id: ActiveValue::Set(report.target_id),
+        category: ActiveValue::Set(report.category.as_str().to_owned()),
+        reason: ActiveValue::Set(report.reason),
+        created: ActiveValue::Set(chrono::Utc::now()),
+        status: ActiveValue::Set(ReportStatus::Open.as_str().to_owned()),
+        claimed_by: ActiveValue::Set(None),
+        resolved_by: ActiveValue::Set(None),
+        action: ActiveValue::Set(None),
+        note: ActiveValue::Set(None),
+        resolved: ActiveValue::Set(None),
+    };
+    Reports::insert(model).exec(&db.db_connection).await?;
+    Ok(HttpResponse::Created().json(serde_json::json!({ "report_id": report_id })))
+}
+
+#[derive(Deserialize)]
+struct QueueQuery {
+    status: Option<ReportStatus>,
+    limit: Option<u64>,
+}
+
+#[actix_web::get("queue")]
+async fn reports_queue(
+    _auth: Authorized<can::ModerateReports>,
+    query: Query<QueueQuery>,
+    db: Data<DbConnection>,
+) -> ServiceResult {
+    let mut select = Reports::find().find_also_related(login_data::Entity);
+    select = match query.status {
+        Some(status) => select.filter(reports::Column::Status.eq(status.as_str())),
+        None => select.filter(reports::Column::Status.ne(ReportStatus::Resolved.as_str())),
+    };
+    let queue = select
+        .order_by_asc(reports::Column::Created)
+        .limit(admin::page(query.limit))
+        .all(&db.db_connection)
+        .await?
+        .into_iter()
+        .map(|(r, reporter)| r.into_report_data(reporter.map_or(String::new(), |l| l.login)))
+        .collect::<Vec<_>>();
+    Ok(HttpResponse::Ok().json(queue))
+}
+
+async fn find_report(report_id: Uuid, db: &DbConnection) -> Result<reports::Model, ServiceError> {
+    Reports::find_by_id(report_id)
+        .one(&db.db_connection)
+        .await?
+        .ok_or(ServiceError::NotFound)
+}
+
+#[actix_web::post("{report_id}/claim")]
+async fn reports_claim(
+    auth: Authorized<can::ModerateReports>,
+    report_id: Path<Uuid>,
+    db: Data<DbConnection>,
+) -> ServiceResult {
+    let report_id = report_id.into_inner();
+    // one statement, so that of two moderators claiming at once only one gets the report
+    let claimed = Reports::update_many()
+        .col_expr(
+            reports::Column::Status,
+            Expr::value(ReportStatus::Claimed.as_str()),
+        )
+        .col_expr(
+            reports::Column::ClaimedBy,
+            Expr::value(Some(auth.user.user_id)),
+        )
+        .filter(reports::Column::ReportId.eq(report_id))
+        .filter(reports::Column::Status.eq(ReportStatus::Open.as_str()))
+        .exec(&db.db_connection)
+        .await?;
+    if claimed.rows_affected == 0 {
+        find_report(report_id, &db).await?;
+        return Ok(HttpResponse::Conflict()
+            .reason("report is not open")
+            .finish());
+    }
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[actix_web::post("{report_id}/resolve")]
+async fn reports_resolve(
+    auth: Authorized<can::ModerateReports>,
+    report_id: Path<Uuid>,
+    resolution: Json<ReportResolution>,
+    db: Data<DbConnection>,
+    store: Data<dyn BlobStore>,
+    token_session: Data<Mutex<dyn TokenSession>>,
+) -> ServiceResult {
+    let resolution = resolution.into_inner();
+    if let Err(errors) = resolution.validate() {
+        return Ok(HttpResponse::BadRequest()
+            .reason("resolution validation failed")
+            .json(errors));
+    }
+    let report = find_report(report_id.into_inner(), &db).await?;
+    match (report.status(), report.claimed_by) {
+        (ReportStatus::Resolved, _) => {
+            return Ok(HttpResponse::Conflict()
+                .reason("report already resolved")
+                .finish())
+        }
+        (ReportStatus::Claimed, Some(by)) if by != auth.user.user_id => {
+            return Ok(HttpResponse::Conflict()
+                .reason("report claimed by another moderator")
+                .finish())
+        }
+        _ => (),
+    }
+
+    let kind = report.target_kind();
+    match resolution.action {
+        ReportAction::Dismiss => (),
+        ReportAction::DeleteContent => {
+            let db = &db.db_connection;
+            match kind {
+                ReportTargetKind::Post => {
+                    attachments::delete_post(report.target_id, db, store.get_ref()).await?;
+                }
+                ReportTargetKind::Comment => {
+                    comments::Entity::delete_by_id(report.target_id)
+                        .exec(db)
+                        .await?;
+                }
+                ReportTargetKind::User => {
+                    return Ok(HttpResponse::BadRequest()
+                        .reason("users cannot be deleted as content")
+                        .finish())
+                }
+            }
+        }
+        ReportAction::SuspendUser => {
+            let Some(author) = target_author(kind, report.target_id, &db).await? else {
+                return Ok(HttpResponse::NotFound()
+                    .reason("reported content no longer exists")
+                    .finish());
+            };
+            let Some(author) = LoginData::find()
+                .filter(login_data::Column::UserId.eq(author))
+                .one(&db.db_connection)
+                .await?
+            else {
+                return Err(ServiceError::UserNotFound);
+            };
+            admin::suspend_user(&auth.user, &author.login, &db, &token_session).await?;
+        }
+    }
+
+    // acting on the content settles every other report about it as well
+    let mut resolve = Reports::update_many()
+        .col_expr(
+            reports::Column::Status,
+            Expr::value(ReportStatus::Resolved.as_str()),
+        )
+        .col_expr(reports::Column::ResolvedBy, Expr::value(auth.user.user_id))
+        .col_expr(
+            reports::Column::Action,
+            Expr::value(resolution.action.as_str()),
+        )
+        .col_expr(reports::Column::Note, Expr::value(resolution.note))
+        .col_expr(reports::Column::Resolved, Expr::value(chrono::Utc::now()));
+    resolve = if resolution.action == ReportAction::Dismiss {
+        resolve.filter(reports::Column::ReportId.eq(report.report_id))
+    } else {
+        resolve
+            .filter(reports::Column::TargetId.eq(report.target_id))
+            .filter(reports::Column::Status.ne(ReportStatus::Resolved.as_str()))
+    };
+    resolve.exec(&db.db_connection).await?;
+    info!(
+        "{} resolved report {} with {}",
+        auth.user.login,
+        report.report_id,
+        resolution.action.as_str()
+    );
+    Ok(HttpResponse::Ok().finish())
+}
@@ -2,61 +2,361 @@ use super::entity::prelude::*;
 use super::helpers;
 use super::DbConnection;
 use crate::session::TokenSession;
-use crate::{entity::user_pfp, errors::ServiceError};
+use crate::storage::{self, BlobStore, StorageError};
+use crate::{
+    config::Limits,
+    entity::{login_data, post_attachments, user_data, user_pfp, user_pfp_variants},
+    errors::ServiceError,
+    images,
+};
 use actix_session::Session;
 use actix_web::{
     self,
+    guard::GuardContext,
+    http::header::{self, EntityTag, HttpDate},
     web::{self, Bytes},
-    HttpResponse,
+    HttpMessage, HttpRequest, HttpResponse,
+};
+use gog_commons::data_structures::{BannerUploadResponse, PfpUploadResponse};
+use log::{debug, warn};
+use sea_orm::{
+    sea_query::Expr, ActiveValue, ColumnTrait, Condition, DatabaseConnection, DbErr, EntityTrait,
+    PaginatorTrait, QueryFilter, QuerySelect, TransactionTrait,
 };
-use infer;
-use sea_orm::{ActiveModelTrait, ActiveValue, EntityTrait, IntoActiveModel};
 use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use tokio::sync::{RwLock, RwLockReadGuard};
+use uuid::Uuid;
+
+/// Lets any `image/*` upload through to the handler, which checks the actual bytes
+pub fn image_content_type(ctx: &GuardContext) -> bool {
+    ctx.head()
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map_or(false, |v| v.starts_with("image/"))
+}
+
+/// `max-age` of responses whose url changes along with their content, so they can be marked
+/// `immutable`. A year is the longest caches are expected to keep anything (RFC 9111)
+pub const IMMUTABLE_MAX_AGE: u32 = 365 * 24 * 60 * 60;
+
+/// `ETag` and `Last-Modified` of a picture, for answering conditional requests
+pub struct CacheValidators {
+    etag: EntityTag,
+    last_modified: Option<SystemTime>,
+}
+
+impl CacheValidators {
+    pub fn new(content_hash: &str, modified: Option<chrono::DateTime<chrono::Utc>>) -> Self {
+        Self {
+            etag: EntityTag::new_strong(content_hash.to_owned()),
+            // http dates have no fractions of a second
+            last_modified: modified
+                .map(|m| SystemTime::UNIX_EPOCH + Duration::from_secs(m.timestamp().max(0) as u64)),
+        }
+    }
+    /// Whether the client already has this picture, `If-None-Match` wins over `If-Modified-Since`
+    pub fn is_fresh(&self, req: &HttpRequest) -> bool {
+        if let Some(matches) = req.get_header::<header::IfNoneMatch>() {
+            return match matches {
+                header::IfNoneMatch::Any => true,
+                header::IfNoneMatch::Items(tags) => tags.iter().any(|t| t.weak_eq(&self.etag)),
+            };
+        }
+        match (
+            req.get_header::<header::IfModifiedSince>(),
+            self.last_modified,
+        ) {
+            (Some(since), Some(modified)) => SystemTime::from(since.0) >= modified,
+            _ => false,
+        }
+    }
+    fn headers(
+        &self,
+        res: &mut actix_web::HttpResponseBuilder,
+        cache_control: header::CacheControl,
+    ) {
+        res.insert_header(header::ETag(self.etag.clone()))
+            .insert_header(cache_control);
+        if let Some(modified) = self.last_modified {
+            res.insert_header(header::LastModified(HttpDate::from(modified)));
+        }
+    }
+    pub fn not_modified(&self, cache_control: header::CacheControl) -> HttpResponse {
+        let mut res = HttpResponse::NotModified();
+        self.headers(&mut res, cache_control);
+        res.finish()
+    }
+    pub fn respond(
+        &self,
+        req: &HttpRequest,
+        cache_control: header::CacheControl,
+        content_type: &str,
+        data: impl Into<Bytes>,
+    ) -> HttpResponse {
+        if self.is_fresh(req) {
+            return self.not_modified(cache_control);
+        }
+        let mut res = HttpResponse::Ok();
+        self.headers(&mut res, cache_control);
+        res.insert_header((header::CONTENT_TYPE, content_type.to_owned()))
+            .body(data.into())
+    }
+}
 
-static PFP_BYTES_MAX: usize = 25_000;
 pub async fn user_upload_pfp(
     payload: Bytes,
     db: web::Data<DbConnection>,
+    store: web::Data<dyn BlobStore>,
     token_session: web::Data<Mutex<dyn TokenSession>>,
     session: Session,
+    limits: web::Data<Limits>,
 ) -> Result<HttpResponse, ServiceError> {
-    if payload.len() > PFP_BYTES_MAX {
+    if payload.len() > limits.pfp_bytes {
         return Ok(HttpResponse::BadRequest()
             .reason("uploaded file exceeded allowed size")
             .finish());
     }
 
-    let Some(file_type) = infer::get(&payload) else {
-        return Ok(HttpResponse::BadRequest()
-            .reason("unknown file type")
-            .finish());
+    let login = helpers::validate_session(&token_session, &session, &db).await?;
+    let id = helpers::get_user_id(&login, &db).await?;
+
+    let variants = match web::block(move || images::process_pfp(&payload)).await {
+        Ok(Ok(variants)) => variants,
+        Ok(Err(e)) => {
+            debug!("rejected pfp upload: {}", e);
+            return Ok(HttpResponse::BadRequest()
+                .reason("uploaded file was not a valid png/jpeg/webp/gif image")
+                .finish());
+        }
+        Err(e) => {
+            return Err(ServiceError::ServerError {
+                source: Box::new(e),
+            })
+        }
     };
 
-    let mime = file_type.mime_type();
-    if mime != "image/jpg" && mime != "image/jpeg" {
+    let pfp_version = replace_pfp(&db.db_connection, store.get_ref(), id, variants).await?;
+
+    Ok(HttpResponse::Ok().json(PfpUploadResponse { pfp_version }))
+}
+
+pub async fn user_upload_banner(
+    payload: Bytes,
+    db: web::Data<DbConnection>,
+    store: web::Data<dyn BlobStore>,
+    token_session: web::Data<Mutex<dyn TokenSession>>,
+    session: Session,
+    limits: web::Data<Limits>,
+) -> Result<HttpResponse, ServiceError> {
+    if payload.len() > limits.banner_bytes {
         return Ok(HttpResponse::BadRequest()
-            .reason("uploaded file was not a valid jpg/jpeg file")
+            .reason("uploaded file exceeded allowed size")
             .finish());
     }
 
-    let login = helpers::validate_session(&token_session, &session)?;
+    let login = helpers::validate_session(&token_session, &session, &db).await?;
     let id = helpers::get_user_id(&login, &db).await?;
 
-    let db = &db.db_connection;
-    let mut model = match UserPfp::find_by_id(id).one(db).await? {
-        Some(m) => m.into_active_model(),
-        None => {
-            let m = user_pfp::ActiveModel {
-                user_id: ActiveValue::Set(id),
-                data: ActiveValue::Set(None),
-            };
-            let res = UserPfp::insert(m.clone()).exec(db).await?;
-            m
+    let banner = match web::block(move || images::process_banner(&payload)).await {
+        Ok(Ok(banner)) => banner,
+        Ok(Err(e)) => {
+            debug!("rejected banner upload: {}", e);
+            return Ok(HttpResponse::BadRequest()
+                .reason("uploaded file was not a valid png/jpeg/webp/gif image")
+                .finish());
+        }
+        Err(e) => {
+            return Err(ServiceError::ServerError {
+                source: Box::new(e),
+            })
         }
     };
 
-    model.data = ActiveValue::Set(Some(payload.to_vec()));
-    UserPfp::update(model).exec(db).await?;
+    let banner_version = replace_banner(&db.db_connection, store.get_ref(), id, banner).await?;
+
+    Ok(HttpResponse::Ok().json(BannerUploadResponse { banner_version }))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReplacePfpError {
+    #[error("Database error: {source}")]
+    DatabaseError {
+        #[from]
+        source: DbErr,
+    },
+    #[error("Storage error: {source}")]
+    StorageError {
+        #[from]
+        source: StorageError,
+    },
+}
+
+impl From<sea_orm::TransactionError<DbErr>> for ReplacePfpError {
+    fn from(e: sea_orm::TransactionError<DbErr>) -> Self {
+        match e {
+            sea_orm::TransactionError::Connection(e)
+            | sea_orm::TransactionError::Transaction(e) => e.into(),
+        }
+    }
+}
+
+/// Held shared from writing a blob until a row refers to it, and exclusively by [`release_blob`]
+/// from counting the rows until the delete. Without it, a blob that an upload has just written
+/// again could be deleted before the upload's row exists. It only orders work within this process,
+/// which is why the commands releasing blobs refuse to run next to a server, see
+/// [`crate::maintenance::server_running`]
+static BLOB_REFS: RwLock<()> = RwLock::const_new(());
+
+/// Take this before `BlobStore::put` and hold it until the row for the blob is written.
+/// Drop it before calling [`release_blob`], which would wait for it forever
+pub async fn blob_write_guard() -> RwLockReadGuard<'static, ()> {
+    BLOB_REFS.read().await
+}
+
+/// Length of the content hash prefix used as `login_data.pfp_version`
+const PFP_VERSION_LENGTH: usize = 16;
 
-    Ok(HttpResponse::Ok().finish())
+/// Swaps the stored profile picture of `id` for freshly processed variants and returns
+/// the new picture version.
+/// The new blobs are written before the rows point at them, the old ones are removed afterwards
+pub async fn replace_pfp(
+    db: &DatabaseConnection,
+    store: &dyn BlobStore,
+    id: Uuid,
+    variants: Vec<images::ImageVariant>,
+) -> Result<String, ReplacePfpError> {
+    let uploaded = chrono::Utc::now();
+    let writing = blob_write_guard().await;
+    let mut rows = Vec::with_capacity(variants.len());
+    let mut version = (0, String::new());
+    for v in variants {
+        let hash = storage::content_key(&v.data);
+        if v.size >= version.0 {
+            version = (v.size, hash[..PFP_VERSION_LENGTH].to_owned());
+        }
+        let key = store.put(&v.data, v.content_type).await?;
+        rows.push(user_pfp_variants::ActiveModel {
+            user_id: ActiveValue::Set(id),
+            size: ActiveValue::Set(v.size as i32),
+            content_type: ActiveValue::Set(v.content_type.to_owned()),
+            blob_key: ActiveValue::Set(Some(key)),
+            data: ActiveValue::Set(None),
+            content_hash: ActiveValue::Set(Some(hash)),
+            uploaded: ActiveValue::Set(Some(uploaded)),
+        });
+    }
+    let version = version.1;
+    let mut old_keys: Vec<Option<String>> = UserPfpVariants::find()
+        .select_only()
+        .column(user_pfp_variants::Column::BlobKey)
+        .filter(user_pfp_variants::Column::UserId.eq(id))
+        .into_tuple()
+        .all(db)
+        .await?;
+    old_keys.extend(
+        UserPfp::find_by_id(id)
+            .select_only()
+            .column(user_pfp::Column::BlobKey)
+            .into_tuple::<Option<String>>()
+            .one(db)
+            .await?,
+    );
+    let pfp_version = version.clone();
+    db.transaction::<_, (), DbErr>(|txn| {
+        Box::pin(async move {
+            UserPfpVariants::delete_many()
+                .filter(user_pfp_variants::Column::UserId.eq(id))
+                .exec(txn)
+                .await?;
+            UserPfpVariants::insert_many(rows).exec(txn).await?;
+            // the original upload from before resizing existed is not needed anymore
+            UserPfp::delete_by_id(id).exec(txn).await?;
+            LoginData::update_many()
+                .col_expr(login_data::Column::PfpVersion, Expr::value(pfp_version))
+                .filter(login_data::Column::UserId.eq(id))
+                .exec(txn)
+                .await?;
+            Ok(())
+        })
+    })
+    .await?;
+    drop(writing);
+    for key in old_keys.into_iter().flatten() {
+        if let Err(e) = release_blob(db, store, &key).await {
+            // an orphaned blob only wastes space, the upload itself went through
+            warn!("failed to remove old blob {}: {}", key, e);
+        }
+    }
+    Ok(version)
+}
+
+/// Stores a processed banner for `id` and returns its version, the previous banner is removed
+pub async fn replace_banner(
+    db: &DatabaseConnection,
+    store: &dyn BlobStore,
+    id: Uuid,
+    banner: images::ImageVariant,
+) -> Result<String, ReplacePfpError> {
+    let writing = blob_write_guard().await;
+    let key = store.put(&banner.data, banner.content_type).await?;
+    let old_key: Option<String> = UserData::find_by_id(id)
+        .select_only()
+        .column(user_data::Column::BannerKey)
+        .into_tuple::<Option<String>>()
+        .one(db)
+        .await?
+        .flatten();
+    UserData::update_many()
+        .set(user_data::ActiveModel {
+            banner_key: ActiveValue::Set(Some(key.clone())),
+            banner_content_type: ActiveValue::Set(Some(banner.content_type.to_owned())),
+            banner_uploaded: ActiveValue::Set(Some(chrono::Utc::now())),
+            ..Default::default()
+        })
+        .filter(user_data::Column::UserId.eq(id))
+        .exec(db)
+        .await?;
+    drop(writing);
+    if let Some(old_key) = old_key.filter(|k| *k != key) {
+        if let Err(e) = release_blob(db, store, &old_key).await {
+            warn!("failed to remove old blob {}: {}", old_key, e);
+        }
+    }
+    Ok(key[..PFP_VERSION_LENGTH].to_owned())
+}
+
+/// Deletes the blob unless a row still refers to it, keys are shared between equal images
+/// of profile pictures, banners and post attachments alike
+pub async fn release_blob(
+    db: &DatabaseConnection,
+    store: &dyn BlobStore,
+    key: &str,
+) -> Result<(), ReplacePfpError> {
+    let _releasing = BLOB_REFS.write().await;
+    let users = UserPfpVariants::find()
+        .filter(user_pfp_variants::Column::BlobKey.eq(key))
+        .count(db)
+        .await?
+        + UserPfp::find()
+            .filter(user_pfp::Column::BlobKey.eq(key))
+            .count(db)
+            .await?
+        + PostAttachments::find()
+            .filter(
+                Condition::any()
+                    .add(post_attachments::Column::BlobKey.eq(key))
+                    .add(post_attachments::Column::ThumbKey.eq(key)),
+            )
+            .count(db)
+            .await?
+        + UserData::find()
+            .filter(user_data::Column::BannerKey.eq(key))
+            .count(db)
+            .await?;
+    if users == 0 {
+        store.delete(key).await?;
+    }
+    Ok(())
 }
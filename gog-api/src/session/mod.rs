@@ -6,6 +6,9 @@ use std::sync::Arc;
 use std::sync::Mutex;
 use std::time::Duration;
 use uuid::Uuid;
+
+use crate::jobs::JobError;
+
 type SessionMap = HashMap<Uuid, UserSessionData>;
 
 #[derive(Clone, Debug)]
@@ -18,60 +21,46 @@ pub trait TokenSession: Send + Sync {
     fn add_user(&mut self, user: &str) -> Uuid;
     fn get_user(&mut self, id: &Uuid) -> Option<String>;
     fn remove_user(&mut self, user: &Uuid) -> ();
+    /// Ends every session of `login`, returns how many were removed
+    fn remove_user_sessions(&mut self, login: &str) -> usize;
+    /// Sessions that have not expired yet, whether or not the cleaner got to the others
+    fn active_sessions(&self) -> usize;
+    /// Drops the sessions that have expired, returns how many there were
+    fn remove_expired(&mut self) -> usize;
 }
 
 pub struct DefaultTokenSession {
     active_users: Arc<Mutex<SessionMap>>,
-    cleaner: Option<tokio::task::JoinHandle<()>>,
+    ttl: Duration,
+    extension: Duration,
 }
 
-async fn cleaner_task(users_sess: std::sync::Weak<Mutex<SessionMap>>, interval: u64) {
-    loop {
-        tokio::time::sleep(Duration::from_secs(interval)).await;
-        let Some(users) = users_sess.upgrade() else {
-            break;
-        };
-        let mut lock = users.lock();
-        let guard = lock.as_mut().unwrap();
-        let now = chrono::Utc::now();
-        let to_rm = guard
-            .iter()
-            .filter_map(|(id, usd)| {
-                if now.ge(&usd.expire) {
-                    Some(id.to_owned())
-                } else {
-                    None
-                }
-            })
-            .collect::<Vec<_>>();
-
-        for id in to_rm.into_iter() {
-            guard.remove(&id);
-        }
-        drop(lock);
+/// Drops expired sessions, run by the job supervisor every `session.cleaning_interval`
+pub async fn cleaner_task(sessions: Arc<Mutex<dyn TokenSession>>) -> Result<(), JobError> {
+    let removed = sessions
+        .lock()
+        .map_err(|e| JobError::Failed(e.to_string()))?
+        .remove_expired();
+    if removed > 0 {
+        debug!("removed {} expired session(s)", removed);
     }
+    Ok(())
 }
 
 impl DefaultTokenSession {
-    ///
-    /// `cleaning_interval` determines if a cleaner task should be spawned
-    /// and how big the time interval between each cleaning is.
-    ///
-    pub fn new(cleaning_interval: Option<u64>) -> Self {
-        let users = Arc::new(Mutex::new(SessionMap::new()));
-        let mut clean: Option<tokio::task::JoinHandle<()>> = None;
-        if let Some(interval) = cleaning_interval {
-            let users_for_cleanup = users.clone();
-            clean = Some(tokio::spawn(async move {
-                let weak = Arc::downgrade(&users_for_cleanup);
-                cleaner_task(weak, interval).await
-            }));
-        }
+    pub fn new() -> Self {
         Self {
-            active_users: users.clone(),
-            cleaner: clean,
+            active_users: Arc::new(Mutex::new(SessionMap::new())),
+            ttl: Duration::from_secs(600),
+            extension: Duration::from_secs(60 * 5),
         }
     }
+    /// Sessions live for `ttl` after login, every request adds `extension`
+    pub fn with_expiry(mut self, ttl: Duration, extension: Duration) -> Self {
+        self.ttl = ttl;
+        self.extension = extension;
+        self
+    }
 }
 
 impl TokenSession for DefaultTokenSession {
@@ -87,7 +76,7 @@ impl TokenSession for DefaultTokenSession {
 
         let session_data = UserSessionData {
             user_login: user.to_owned(),
-            expire: chrono::Utc::now() + Duration::from_secs(600),
+            expire: chrono::Utc::now() + self.ttl,
         };
 
         active_users.insert(uuid, session_data);
@@ -98,8 +87,9 @@ impl TokenSession for DefaultTokenSession {
         let mut lock = self.active_users.lock();
         let active_users = lock.as_mut().expect("mutext poisoned");
         if active_users.contains_key(id) {
+            let extension = self.extension;
             active_users.entry(*id).and_modify(|e| {
-                e.expire += Duration::from_secs(60 * 5);
+                e.expire += extension;
             });
             Some(active_users.get(id).unwrap().user_login.to_owned())
         } else {
@@ -112,4 +102,28 @@ impl TokenSession for DefaultTokenSession {
         active_users.remove(user);
         debug!("has_user {}", active_users.contains_key(user));
     }
+    fn remove_user_sessions(&mut self, login: &str) -> usize {
+        let mut lock = self.active_users.lock();
+        let active_users = lock.as_mut().expect("mutext poisoned");
+        let before = active_users.len();
+        active_users.retain(|_, data| data.user_login != login);
+        before - active_users.len()
+    }
+    fn active_sessions(&self) -> usize {
+        let lock = self.active_users.lock();
+        let active_users = lock.as_ref().expect("mutext poisoned");
+        let now = chrono::Utc::now();
+        active_users
+            .values()
+            .filter(|data| data.expire > now)
+            .count()
+    }
+    fn remove_expired(&mut self) -> usize {
+        let mut lock = self.active_users.lock();
+        let active_users = lock.as_mut().expect("mutext poisoned");
+        let before = active_users.len();
+        let now = chrono::Utc::now();
+        active_users.retain(|_, data| data.expire > now);
+        before - active_users.len()
+    }
 }
@@ -0,0 +1,97 @@
+mod local;
+mod s3;
+
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+pub use local::LocalBlobStore;
+pub use s3::{S3BlobStore, S3Config};
+
+#[derive(Debug, Error)]
+pub enum StorageError {
+    #[error("Io error: {source}")]
+    IoError {
+        #[from]
+        source: std::io::Error,
+    },
+    #[error("S3 error: {message}")]
+    S3Error { message: String },
+    #[error("Invalid blob key `{key}`")]
+    InvalidKey { key: String },
+}
+
+/// Where image bytes live, the database only keeps the keys handed out by [`BlobStore::put`].
+/// Stores are content addressed: equal data gets the same key, so a key may be shared
+/// between rows and must only be deleted once nothing refers to it anymore
+#[async_trait]
+pub trait BlobStore: Send + Sync {
+    async fn put(&self, data: &[u8], content_type: &str) -> Result<String, StorageError>;
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError>;
+    async fn delete(&self, key: &str) -> Result<(), StorageError>;
+}
+
+/// Hex sha256 of the content, used as its key
+pub fn content_key(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+/// Keys come from the database but end up in paths, anything else than a content key is refused
+fn validate_key(key: &str) -> Result<(), StorageError> {
+    if key.len() == 64 && key.bytes().all(|b| b.is_ascii_hexdigit()) {
+        Ok(())
+    } else {
+        Err(StorageError::InvalidKey {
+            key: key.to_owned(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gog_commons::vars;
+
+    /// What every store has to do, whatever keeps the bytes
+    async fn exercise(store: &dyn BlobStore) {
+        let data = uuid::Uuid::new_v4().to_string().into_bytes();
+        let key = store.put(&data, "text/plain").await.unwrap();
+        assert_eq!(key, content_key(&data));
+        assert_eq!(store.put(&data, "text/plain").await.unwrap(), key);
+        assert_eq!(store.get(&key).await.unwrap(), Some(data));
+        store.delete(&key).await.unwrap();
+        assert_eq!(store.get(&key).await.unwrap(), None);
+        store.delete(&key).await.unwrap();
+        assert!(matches!(
+            store.get("../../etc/passwd").await,
+            Err(StorageError::InvalidKey { .. })
+        ));
+    }
+
+    #[actix_web::test]
+    async fn local_store() {
+        let root = std::env::temp_dir().join(format!("gog-blobs-{}", uuid::Uuid::new_v4()));
+        exercise(&LocalBlobStore::new(&root)).await;
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    /// Runs against a live S3 compatible service, e.g. the MinIO of the README, when
+    /// `MAGOG_TEST_S3_ENDPOINT` is set. The bucket and credentials come from the usual variables
+    #[actix_web::test]
+    async fn s3_store() {
+        let Ok(endpoint) = std::env::var("MAGOG_TEST_S3_ENDPOINT") else {
+            eprintln!("MAGOG_TEST_S3_ENDPOINT is not set, skipping the S3 store test");
+            return;
+        };
+        let var = |name: &str| std::env::var(name).expect(name);
+        exercise(&S3BlobStore::new(S3Config {
+            bucket: var(vars::S3_BUCKET_ENV),
+            endpoint: Some(endpoint),
+            region: std::env::var(vars::S3_REGION_ENV)
+                .unwrap_or_else(|_| vars::defaults::S3_REGION.to_owned()),
+            access_key: var(vars::S3_ACCESS_KEY_ENV),
+            secret_key: var(vars::S3_SECRET_KEY_ENV),
+        }))
+        .await;
+    }
+}
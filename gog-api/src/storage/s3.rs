@@ -0,0 +1,99 @@
+use async_trait::async_trait;
+use aws_sdk_s3::{
+    config::{BehaviorVersion, Credentials, Region},
+    error::DisplayErrorContext,
+    primitives::ByteStream,
+    Client,
+};
+
+use super::{content_key, validate_key, BlobStore, StorageError};
+
+pub struct S3Config {
+    pub bucket: String,
+    /// Set for S3 compatible services such as MinIO, e.g. `http://localhost:9000`
+    pub endpoint: Option<String>,
+    pub region: String,
+    pub access_key: String,
+    pub secret_key: String,
+}
+
+/// Blobs as objects in an S3 compatible bucket, keyed by their content key
+pub struct S3BlobStore {
+    client: Client,
+    bucket: String,
+}
+
+fn s3_error(e: impl std::error::Error) -> StorageError {
+    StorageError::S3Error {
+        message: DisplayErrorContext(e).to_string(),
+    }
+}
+
+impl S3BlobStore {
+    pub fn new(config: S3Config) -> Self {
+        let credentials = Credentials::new(
+            config.access_key,
+            config.secret_key,
+            None,
+            None,
+            "gog-magog",
+        );
+        let mut builder = aws_sdk_s3::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new(config.region))
+            .credentials_provider(credentials);
+        if let Some(endpoint) = config.endpoint {
+            // MinIO and friends do not do virtual hosted buckets
+            builder = builder.endpoint_url(endpoint).force_path_style(true);
+        }
+        Self {
+            client: Client::from_conf(builder.build()),
+            bucket: config.bucket,
+        }
+    }
+}
+
+#[async_trait]
+impl BlobStore for S3BlobStore {
+    async fn put(&self, data: &[u8], content_type: &str) -> Result<String, StorageError> {
+        let key = content_key(data);
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(&key)
+            .content_type(content_type)
+            .body(ByteStream::from(data.to_vec()))
+            .send()
+            .await
+            .map_err(s3_error)?;
+        Ok(key)
+    }
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        validate_key(key)?;
+        let object = match self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+        {
+            Ok(object) => object,
+            Err(e) if e.as_service_error().is_some_and(|e| e.is_no_such_key()) => return Ok(None),
+            Err(e) => return Err(s3_error(e)),
+        };
+        let data = object.body.collect().await.map_err(s3_error)?;
+        Ok(Some(data.into_bytes().to_vec()))
+    }
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        validate_key(key)?;
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(s3_error)?;
+        Ok(())
+    }
+}
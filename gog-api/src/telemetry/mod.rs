@@ -0,0 +1,195 @@
+//! Logs and traces. Every request runs in a span carrying its id, route and user, and whatever is
+//! logged while handling it carries them along, the `log` macros used across the server included.
+//! Lines go to stderr as text or JSON, spans can be sent to an OTLP collector as well.
+//! Both pass [`redact`] on the way out
+
+mod redact;
+
+use std::time::Instant;
+
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    error::InternalError,
+    http::header::{HeaderName, HeaderValue},
+    middleware::Next,
+    Error,
+};
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+    trace::{BatchSpanProcessor, SdkTracerProvider},
+    Resource,
+};
+use thiserror::Error;
+use tracing::{field, Instrument};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+
+use crate::config::LoggingConfig;
+
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+/// Longer ids sent by clients are replaced, like ones with characters other than these
+const REQUEST_ID_MAX: usize = 128;
+
+#[derive(Debug, Error)]
+pub enum TelemetryError {
+    #[error("invalid log filter: {source}")]
+    FilterError {
+        #[from]
+        source: tracing_subscriber::filter::ParseError,
+    },
+    #[error("OTLP exporter error: {source}")]
+    ExporterError {
+        #[from]
+        source: opentelemetry_otlp::ExporterBuildError,
+    },
+    #[error("logging is set up already: {source}")]
+    InitError {
+        #[from]
+        source: tracing_subscriber::util::TryInitError,
+    },
+}
+
+/// Sends the traces that are still buffered when dropped
+pub struct Telemetry {
+    provider: Option<SdkTracerProvider>,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        if let Some(provider) = self.provider.take() {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("failed to send the remaining traces: {}", e);
+            }
+        }
+    }
+}
+
+/// Sets up logging for the whole process, `RUST_LOG` takes precedence over the configured filter
+pub fn init(config: &LoggingConfig) -> Result<Telemetry, TelemetryError> {
+    let filter = match std::env::var(EnvFilter::DEFAULT_ENV) {
+        Ok(filter) if !filter.trim().is_empty() => EnvFilter::try_new(filter)?,
+        _ => EnvFilter::try_new(&config.filter)?,
+    };
+    let json = config.format == "json";
+    // colors would put escape codes between names and values, where redaction could miss them
+    let output = tracing_subscriber::fmt::layer()
+        .with_ansi(false)
+        .with_writer(redact::RedactingWriter::new(json));
+    let output = if json {
+        output
+            .json()
+            .with_current_span(true)
+            .with_span_list(false)
+            .boxed()
+    } else {
+        output.boxed()
+    };
+    let provider = match &config.otlp_endpoint {
+        Some(endpoint) => {
+            let exporter = opentelemetry_otlp::SpanExporter::builder()
+                .with_http()
+                .with_endpoint(endpoint)
+                .build()?;
+            Some(
+                SdkTracerProvider::builder()
+                    .with_span_processor(redact::RedactingProcessor(
+                        BatchSpanProcessor::builder(exporter).build(),
+                    ))
+                    .with_resource(
+                        Resource::builder()
+                            .with_service_name(config.service_name.clone())
+                            .build(),
+                    )
+                    .build(),
+            )
+        }
+        None => None,
+    };
+    let traces = provider.as_ref().map(|provider| {
+        tracing_opentelemetry::layer().with_tracer(provider.tracer(config.service_name.clone()))
+    });
+    tracing_subscriber::registry()
+        .with(output)
+        .with(traces)
+        .with(filter)
+        .try_init()?;
+    if let Some(endpoint) = &config.otlp_endpoint {
+        tracing::info!("sending traces to {}", endpoint);
+    }
+    Ok(Telemetry { provider })
+}
+
+/// Ids from clients are kept to connect their logs with ours, as long as they cannot
+/// smuggle anything into a log line
+fn valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= REQUEST_ID_MAX
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b':'))
+}
+
+/// Runs the request in a span with its id, taken from [`REQUEST_ID_HEADER`] or made up,
+/// and sends the id back in the same header. `user` and `user_id` are filled in by the
+/// extractors that find them, `status` once the response is ready
+pub async fn trace(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let started = Instant::now();
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|id| id.to_str().ok())
+        .filter(|id| valid_request_id(id))
+        .map_or_else(|| uuid::Uuid::new_v4().to_string(), str::to_owned);
+    let method = req.method().clone();
+    let route = req.match_pattern();
+    let route = route.as_deref().unwrap_or(crate::metrics::UNMATCHED);
+    let span = tracing::info_span!(
+        "request",
+        otel.name = %format!("{} {}", method, route),
+        request_id = %request_id,
+        method = %method,
+        route = route,
+        path = req.path(),
+        user = field::Empty,
+        user_id = field::Empty,
+        status = field::Empty,
+    );
+    let path = req.path().to_owned();
+    let res = next.call(req).instrument(span.clone()).await;
+    let status = match &res {
+        Ok(res) => res.status(),
+        Err(e) => e.as_response_error().status_code(),
+    };
+    span.record("status", status.as_u16());
+    span.in_scope(|| {
+        tracing::info!(
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            "{} {} {}",
+            method,
+            path,
+            status.as_u16()
+        )
+    });
+    let id = HeaderValue::from_str(&request_id).ok();
+    let header = HeaderName::from_static("x-request-id");
+    match res {
+        Ok(mut res) => {
+            if let Some(id) = id {
+                res.headers_mut().insert(header, id);
+            }
+            Ok(res.map_into_boxed_body())
+        }
+        // errors become responses further out, so the id goes along with one made here
+        Err(e) => {
+            let mut response = e.error_response();
+            if let Some(id) = id {
+                response.headers_mut().insert(header, id);
+            }
+            Err(InternalError::from_response(e, response).into())
+        }
+    }
+}
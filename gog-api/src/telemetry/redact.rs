@@ -0,0 +1,397 @@
+//! Keeps secrets out of logs and traces, whatever the code asked to log. Fields and attributes
+//! named like a secret lose their value, and so does text that looks like `password: ...`,
+//! a bearer token or a password hash. This works on the finished output, so it also covers
+//! `{:?}` of types that print more than they should
+
+use std::borrow::Cow;
+use std::io::{Stderr, Write};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use opentelemetry::{Context, KeyValue, Value};
+use opentelemetry_sdk::{
+    error::OTelSdkResult,
+    trace::{Span, SpanData, SpanProcessor},
+    Resource,
+};
+use regex::{Captures, Regex};
+use tracing_subscriber::fmt::MakeWriter;
+
+pub const REDACTED: &str = "<redacted>";
+/// Parts of field names that hold secrets, compared in lowercase
+const SENSITIVE: [&str; 8] = [
+    "password",
+    "passwd",
+    "secret",
+    "salt",
+    "hash",
+    "token",
+    "authorization",
+    "cookie",
+];
+
+pub fn is_sensitive(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    SENSITIVE.iter().any(|s| key.contains(s))
+}
+
+fn patterns() -> &'static [Regex; 3] {
+    static PATTERNS: OnceLock<[Regex; 3]> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        let keys = SENSITIVE.join("|");
+        [
+            // PHC strings like `$argon2id$v=19$m=...`
+            Regex::new(r"\$argon2(?:id|i|d)\$\S+").unwrap(),
+            Regex::new(r"(?i)\b(bearer)\s+\S+").unwrap(),
+            // the `key: ` and `key=` before a value, see `value_end` for where the value ends
+            Regex::new(&format!(r#"(?i)\b\w*(?:{})\w*"?\s*[:=]\s*"#, keys)).unwrap(),
+        ]
+    })
+}
+
+/// Where the string opened by the quote at `start` is closed, or the end of `text`
+fn quoted_end(text: &[u8], start: usize) -> usize {
+    let mut i = start + 1;
+    while i < text.len() {
+        match text[i] {
+            b'\\' => i += 1,
+            b'"' => return i + 1,
+            _ => (),
+        }
+        i += 1;
+    }
+    text.len()
+}
+
+/// Where the bracket at `start` is closed, or the end of `text`
+fn bracket_end(text: &[u8], start: usize) -> usize {
+    let mut depth = 0usize;
+    let mut i = start;
+    while i < text.len() {
+        match text[i] {
+            b'"' => {
+                i = quoted_end(text, i);
+                continue;
+            }
+            b'(' | b'[' | b'{' => depth += 1,
+            b')' | b']' | b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+            _ => (),
+        }
+        i += 1;
+    }
+    text.len()
+}
+
+/// Where the value starting at `start` ends. A quoted string, or anything in brackets with
+/// whatever they nest, like `Some("...")` or `Field([...])` in `{:?}` output. Else the value
+/// goes up to the next whitespace
+fn value_end(text: &str, start: usize) -> usize {
+    let text = text.as_bytes();
+    if text.get(start) == Some(&b'"') {
+        return quoted_end(text, start);
+    }
+    let mut i = start;
+    while i < text.len() && (text[i].is_ascii_alphanumeric() || text[i] == b'_') {
+        i += 1;
+    }
+    if matches!(text.get(i), Some(b'(' | b'[' | b'{')) {
+        return bracket_end(text, i);
+    }
+    while i < text.len() && !text[i].is_ascii_whitespace() {
+        i += 1;
+    }
+    i
+}
+
+/// `text` with every secret it appears to contain replaced
+pub fn scrub(text: &str) -> Cow<'_, str> {
+    let [hash, bearer, pair] = patterns();
+    let mut text = Cow::Borrowed(text);
+    if hash.is_match(&text) {
+        text = Cow::Owned(hash.replace_all(&text, REDACTED).into_owned());
+    }
+    if bearer.is_match(&text) {
+        let replaced = bearer.replace_all(&text, |c: &Captures| format!("{} {}", &c[1], REDACTED));
+        text = Cow::Owned(replaced.into_owned());
+    }
+    if pair.is_match(&text) {
+        let mut replaced = String::with_capacity(text.len());
+        let mut last = 0;
+        for key in pair.find_iter(&text) {
+            let end = value_end(&text, key.end());
+            // keys inside a value that is gone already, or without a value
+            if key.start() < last || end == key.end() {
+                continue;
+            }
+            replaced.push_str(&text[last..key.end()]);
+            replaced.push_str(REDACTED);
+            last = end;
+        }
+        replaced.push_str(&text[last..]);
+        text = Cow::Owned(replaced);
+    }
+    text
+}
+
+/// Walks a JSON log line, so that replacing a value never breaks its quoting
+fn scrub_json(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::String(text) => {
+            if let Cow::Owned(scrubbed) = scrub(text) {
+                *text = scrubbed;
+            }
+        }
+        serde_json::Value::Array(values) => values.iter_mut().for_each(scrub_json),
+        serde_json::Value::Object(fields) => {
+            for (key, value) in fields.iter_mut() {
+                if is_sensitive(key) {
+                    *value = REDACTED.into();
+                } else {
+                    scrub_json(value);
+                }
+            }
+        }
+        _ => (),
+    }
+}
+
+fn scrub_line(line: &str, json: bool) -> String {
+    if json {
+        if let Ok(mut value) = serde_json::from_str::<serde_json::Value>(line) {
+            scrub_json(&mut value);
+            return value.to_string();
+        }
+    }
+    scrub(line).into_owned()
+}
+
+/// Hands out a [`RedactedLines`] for every event the formatter writes
+pub struct RedactingWriter {
+    json: bool,
+}
+
+impl RedactingWriter {
+    pub fn new(json: bool) -> Self {
+        Self { json }
+    }
+}
+
+impl<'a> MakeWriter<'a> for RedactingWriter {
+    type Writer = RedactedLines;
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactedLines {
+            json: self.json,
+            buffer: Vec::new(),
+            out: std::io::stderr(),
+        }
+    }
+}
+
+/// Collects what is written for one event and writes it to `out`, scrubbed, once dropped
+pub struct RedactedLines<W: Write = Stderr> {
+    json: bool,
+    buffer: Vec<u8>,
+    out: W,
+}
+
+impl<W: Write> Write for RedactedLines<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<W: Write> Drop for RedactedLines<W> {
+    fn drop(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        let text = String::from_utf8_lossy(&self.buffer);
+        let mut out = String::with_capacity(text.len());
+        for line in text.lines() {
+            out.push_str(&scrub_line(line, self.json));
+            out.push('\n');
+        }
+        let _ = self.out.write_all(out.as_bytes());
+    }
+}
+
+fn scrub_attributes(attributes: &mut [KeyValue]) {
+    for attribute in attributes {
+        if is_sensitive(attribute.key.as_str()) {
+            attribute.value = Value::from(REDACTED);
+        } else if let Value::String(text) = &attribute.value {
+            if let Cow::Owned(scrubbed) = scrub(text.as_str()) {
+                attribute.value = Value::from(scrubbed);
+            }
+        }
+    }
+}
+
+/// Scrubs finished spans before `P` exports them
+#[derive(Debug)]
+pub struct RedactingProcessor<P>(pub P);
+
+impl<P: SpanProcessor> SpanProcessor for RedactingProcessor<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        self.0.on_start(span, cx)
+    }
+    fn on_end(&self, mut span: SpanData) {
+        if let Cow::Owned(name) = scrub(&span.name) {
+            span.name = name.into();
+        }
+        scrub_attributes(&mut span.attributes);
+        for event in span.events.events.iter_mut() {
+            if let Cow::Owned(name) = scrub(&event.name) {
+                event.name = name.into();
+            }
+            scrub_attributes(&mut event.attributes);
+        }
+        self.0.on_end(span)
+    }
+    fn force_flush(&self) -> OTelSdkResult {
+        self.0.force_flush()
+    }
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.0.shutdown_with_timeout(timeout)
+    }
+    fn set_resource(&mut self, resource: &Resource) {
+        self.0.set_resource(resource)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use validator::Validate;
+
+    #[test]
+    fn scrubs_key_value_text() {
+        assert_eq!(
+            scrub("login=alice password=hunter2 token: abc cookie=\"id=1; x=2\" next"),
+            format!(
+                "login=alice password={0} token: {0} cookie={0} next",
+                REDACTED
+            )
+        );
+        assert_eq!(
+            scrub("sent Bearer abc.def"),
+            format!("sent Bearer {}", REDACTED)
+        );
+        assert!(!scrub("Authorization: Bearer abc.def").contains("abc.def"));
+        assert_eq!(
+            scrub("stored $argon2id$v=19$m=19456,t=2,p=1$c2FsdA$aGFzaA end"),
+            format!("stored {} end", REDACTED)
+        );
+        assert!(matches!(scrub("nothing to hide here"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn scrubs_everything_nested_under_a_key() {
+        assert_eq!(
+            scrub(
+                r#"Some { password: Some("two words"), secret: [1, (2, "3)")], login: "alice" }"#
+            ),
+            format!(
+                r#"Some {{ password: {0}, secret: {0}, login: "alice" }}"#,
+                REDACTED
+            )
+        );
+        assert_eq!(
+            scrub(r#"{"token": {"inner": "a b", "more": {"c": 1}}} after"#),
+            format!(r#"{{"token": {}}} after"#, REDACTED)
+        );
+    }
+
+    #[test]
+    fn scrubs_validation_errors_of_a_registration() {
+        let plaintext = "hünter2 mit leerzeichen";
+        let errors = gog_commons::data_structures::UserCreationData {
+            login: "alice".to_owned(),
+            password: plaintext.to_owned(),
+            invite_code: None,
+            pow: None,
+        }
+        .validate()
+        .unwrap_err();
+        let logged = format!("Validation errors\n {:?}", errors.errors());
+        assert!(logged.contains(plaintext), "the params keep the value");
+        let scrubbed = scrub(&logged);
+        assert!(!scrubbed.contains("hünter2"), "{}", scrubbed);
+        assert!(!scrubbed.contains("leerzeichen"), "{}", scrubbed);
+        let line = json!({ "fields": { "message": logged } }).to_string();
+        assert!(!scrub_line(&line, true).contains("leerzeichen"));
+        assert!(!crate::service::validation_summary(&errors).contains("hünter2"));
+    }
+
+    #[test]
+    fn scrubs_json_keys_at_any_depth() {
+        let mut value = json!({
+            "message": "signed in",
+            "password": "hunter2",
+            "fields": {
+                "login": "alice",
+                "access_token": "abc",
+                "request": { "Cookie": ["id=1"], "path": "/api/login" },
+            },
+            "spans": [{ "note": "password=hunter2" }],
+            "token_session": { "id": "abc", "expires": 1 },
+        });
+        scrub_json(&mut value);
+        assert_eq!(
+            value,
+            json!({
+                "message": "signed in",
+                "password": REDACTED,
+                "fields": {
+                    "login": "alice",
+                    "access_token": REDACTED,
+                    "request": { "Cookie": REDACTED, "path": "/api/login" },
+                },
+                "spans": [{ "note": format!("password={}", REDACTED) }],
+                "token_session": REDACTED,
+            })
+        );
+    }
+
+    fn written(json: bool, parts: &[&str]) -> String {
+        let mut out = Vec::new();
+        {
+            let mut lines = RedactedLines {
+                json,
+                buffer: Vec::new(),
+                out: &mut out,
+            };
+            for part in parts {
+                lines.write_all(part.as_bytes()).unwrap();
+            }
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn scrubs_lines_split_across_writes() {
+        assert_eq!(
+            written(false, &["INFO login password=hun", "ter2 done\n"]),
+            format!("INFO login password={} done\n", REDACTED)
+        );
+        let line = written(
+            true,
+            &[r#"{"fields":{"tok"#, r#"en":"abc","login":"alice"}}"#],
+        );
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(
+            value,
+            json!({ "fields": { "token": REDACTED, "login": "alice" } })
+        );
+    }
+}
@@ -1,4 +1,5 @@
 pub mod vars {
+    pub const CONFIG_PATH_ENV: &str = "MAGOG_CONFIG";
     pub const BACKEND_ADDRESS_ENV: &str = "MAGOG_BACKEND_ADDR";
     pub const BACKEND_PORT_ENV: &str = "MAGOG_BACKEND_PORT";
     pub const BACKEND_DATABASE_URL_ENV: &str = "MAGOG_BACKEND_DB_URL";
@@ -7,16 +8,75 @@ pub mod vars {
     pub const FRONTENT_ADDRESS_ENV: &str = "MAGOG_FRONTEND_ADDR";
     pub const FRONTEND_PORT_ENV: &str = "MAGOG_FRONTEND_PORT";
 
+    pub const OAUTH_ISSUER_ENV: &str = "MAGOG_OAUTH_ISSUER";
+    pub const OAUTH_KEY_PATH_ENV: &str = "MAGOG_OAUTH_KEY_PATH";
+
+    pub const BOOTSTRAP_ADMIN_ENV: &str = "MAGOG_BOOTSTRAP_ADMIN";
+
+    pub const MODERATION_CONFIG_ENV: &str = "MAGOG_MODERATION_CONFIG";
+
+    pub const REGISTRATION_MODE_ENV: &str = "MAGOG_REGISTRATION_MODE";
+    pub const POW_DIFFICULTY_ENV: &str = "MAGOG_POW_DIFFICULTY";
+
+    pub const BLOB_STORE_ENV: &str = "MAGOG_BLOB_STORE";
+    pub const BLOB_DIR_ENV: &str = "MAGOG_BLOB_DIR";
+    pub const S3_BUCKET_ENV: &str = "MAGOG_S3_BUCKET";
+    pub const S3_ENDPOINT_ENV: &str = "MAGOG_S3_ENDPOINT";
+    pub const S3_REGION_ENV: &str = "MAGOG_S3_REGION";
+    pub const S3_ACCESS_KEY_ENV: &str = "MAGOG_S3_ACCESS_KEY";
+    pub const S3_SECRET_KEY_ENV: &str = "MAGOG_S3_SECRET_KEY";
+
+    pub const REQUIRE_ALT_TEXT_ENV: &str = "MAGOG_REQUIRE_ALT_TEXT";
+
+    pub const FRONTEND_DIR_ENV: &str = "MAGOG_FRONTEND_DIR";
+    pub const API_PREFIX_ENV: &str = "MAGOG_API_PREFIX";
+
+    pub const RESOURCE_CACHE_MAX_BYTES_ENV: &str = "MAGOG_RESOURCE_CACHE_MAX_BYTES";
+    pub const RESOURCE_CACHE_TTL_ENV: &str = "MAGOG_RESOURCE_CACHE_TTL";
+
+    pub const CORS_ORIGINS_ENV: &str = "MAGOG_CORS_ORIGINS";
+    pub const CORS_ORIGIN_PATTERNS_ENV: &str = "MAGOG_CORS_ORIGIN_PATTERNS";
+    pub const CORS_METHODS_ENV: &str = "MAGOG_CORS_METHODS";
+    pub const CORS_HEADERS_ENV: &str = "MAGOG_CORS_HEADERS";
+    pub const CORS_CREDENTIALS_ENV: &str = "MAGOG_CORS_CREDENTIALS";
+    pub const CORS_MAX_AGE_ENV: &str = "MAGOG_CORS_MAX_AGE";
+
+    pub const LOG_FORMAT_ENV: &str = "MAGOG_LOG_FORMAT";
+    pub const OTLP_ENDPOINT_ENV: &str = "MAGOG_OTLP_ENDPOINT";
+    pub const SHUTDOWN_TIMEOUT_ENV: &str = "MAGOG_SHUTDOWN_TIMEOUT";
+
     pub mod defaults {
         pub const BACKEND_ADDRESS: &str = "127.0.0.1";
         pub const BACKEND_PORT: u16 = 8081;
 
         pub const FRONTEND_ADDRESS: &str = "127.0.0.1";
         pub const FRONTEND_PORT: u16 = 8082;
+
+        pub const OAUTH_KEY_PATH: &str = "data/oauth_key.pem";
+
+        pub const MODERATION_CONFIG: &str = "data/moderation.toml";
+
+        /// Leading zero bits, around a million hashes on average
+        pub const POW_DIFFICULTY: u8 = 20;
+
+        pub const BLOB_DIR: &str = "data/blobs";
+        pub const S3_REGION: &str = "us-east-1";
+
+        pub const API_PREFIX: &str = "/api";
+
+        pub const RESOURCE_CACHE_MAX_BYTES: usize = 16 * 1024 * 1024;
+
+        pub const INSTANCE_NAME: &str = "gog-magog";
+        pub const POST_LENGTH: usize = 300;
+        pub const COMMENT_LENGTH: usize = 300;
+        pub const PFP_BYTES: usize = 5 * 1024 * 1024;
+        pub const BANNER_BYTES: usize = 8 * 1024 * 1024;
+        pub const ATTACHMENT_BYTES: usize = 8 * 1024 * 1024;
     }
 }
 #[cfg(feature = "backend")]
 pub mod validation;
+pub mod pow;
 
 pub mod data_structures {
     use serde;
@@ -27,38 +87,186 @@ pub mod data_structures {
     #[cfg_attr(feature = "backend", derive(Validate))]
     pub struct CommentCreationData {
         #[cfg_attr(feature = "backend",
-            validate(length(min = 1, max = 300, message = "comment content of disallowed size")))]
+            validate(length(min = 1, message = "comment content of disallowed size")))]
         pub content: String,
         pub post_id: Uuid,
     }
+    #[derive(Clone, serde::Serialize, serde::Deserialize, Debug)]
+    #[cfg_attr(feature = "backend", derive(Validate))]
+    pub struct CommentEditData {
+        #[cfg_attr(feature = "backend",
+            validate(length(min = 1, message = "comment content of disallowed size")))]
+        pub content: String,
+    }
     #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
     pub struct PostsFilter {
         pub username: Option<String>,
         pub user_id: Option<Uuid>,
         pub limit: Option<u64>,
     }
+    pub const MAX_POST_ATTACHMENTS: usize = 4;
+    /// Kept in step with the validation of [`AttachmentRef::alt_text`]
+    pub const MAX_ALT_TEXT_LENGTH: usize = 1000;
     #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
     #[cfg_attr(feature = "backend", derive(Validate))]
     pub struct PostCreationData {
         #[cfg_attr(feature = "backend",
-            validate(length(min = 1, max = 300, message = "post content of disallowed size")))]
+            validate(length(min = 1, message = "post content of disallowed size")))]
         pub content: String,
+        /// Uploaded through `POST /posts/attachments`, shown in this order.
+        /// Only used when creating a post, edits keep the attachments
+        #[serde(default)]
+        #[cfg_attr(feature = "backend",
+            validate(length(max = 4, message = "a post can have at most 4 images"), nested))]
+        pub attachments: Vec<AttachmentRef>,
+    }
+    #[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+    #[cfg_attr(feature = "backend", derive(Validate))]
+    pub struct AttachmentRef {
+        pub attachment_id: Uuid,
+        #[cfg_attr(feature = "backend",
+            validate(length(max = 1000, message = "alt text is too long")))]
+        pub alt_text: Option<String>,
+    }
+    /// An image of a post, served at `posts/attachments/{attachment_id}`,
+    /// add `?thumb=true` for the thumbnail
+    #[derive(Clone, serde::Deserialize, serde::Serialize, Debug, PartialEq)]
+    pub struct AttachmentData {
+        pub attachment_id: Uuid,
+        pub alt_text: Option<String>,
+        pub width: u32,
+        pub height: u32,
     }
     #[derive(serde::Serialize, serde::Deserialize)]
     pub struct UserDataResponse {
         pub login: String,
         pub id: uuid::Uuid,
         pub description: String,
-        pub gender: Option<String>,
+        pub pronouns: Option<String>,
         pub created: Option<chrono::DateTime<chrono::Utc>>,
+        pub role: Role,
+        /// Changes with every uploaded picture, passed as `?v=` to get a url that can be cached for good
+        pub pfp_version: Option<String>,
+        pub display_name: Option<String>,
+        pub location: Option<String>,
+        pub website: Option<String>,
+        pub fields: Vec<ProfileField>,
+        /// Set when the user has a banner, used like `pfp_version`
+        pub banner_version: Option<String>,
+        /// Only sent to the user themselves, fields others may not see are left out for them
+        pub visibility: Option<ProfileVisibility>,
+    }
+    /// Who gets to see a profile field. There are no followers yet,
+    /// until then `Followers` means the same as `OnlyMe`
+    #[derive(Clone, Copy, serde::Serialize, serde::Deserialize, Debug, Default, PartialEq, Eq)]
+    #[serde(rename_all = "snake_case")]
+    pub enum Visibility {
+        #[default]
+        Public,
+        Followers,
+        OnlyMe,
+    }
+    #[derive(Clone, Copy, serde::Serialize, serde::Deserialize, Debug, Default, PartialEq, Eq)]
+    #[serde(default)]
+    pub struct ProfileVisibility {
+        pub pronouns: Visibility,
+        pub location: Visibility,
+        pub website: Visibility,
+        pub fields: Visibility,
+    }
+    /// Offered as suggestions, any other text up to 40 characters is fine too
+    pub const PRONOUN_PRESETS: [&str; 7] = [
+        "she/her",
+        "he/him",
+        "they/them",
+        "she/they",
+        "he/they",
+        "any pronouns",
+        "ask me",
+    ];
+    /// Returned by `POST /user/upload_pfp`
+    #[derive(Clone, serde::Serialize, serde::Deserialize, Debug)]
+    pub struct PfpUploadResponse {
+        pub pfp_version: String,
+    }
+    /// Returned by `GET /user/csrf`, state changing requests send it back in `X-CSRF-Token`
+    #[derive(Clone, serde::Serialize, serde::Deserialize, Debug)]
+    pub struct CsrfTokenResponse {
+        pub token: String,
+    }
+    /// Returned by `POST /user/upload_banner`
+    #[derive(Clone, serde::Serialize, serde::Deserialize, Debug)]
+    pub struct BannerUploadResponse {
+        pub banner_version: String,
     }
     #[derive(Clone, serde::Deserialize, serde::Serialize, Debug, Default)]
     pub struct UserData {
         pub login: String,
         pub id: String,
         pub description: String,
-        pub gender: String,
-        pub created: Option<chrono::DateTime<chrono::Utc>>
+        #[serde(default)]
+        pub pronouns: Option<String>,
+        pub created: Option<chrono::DateTime<chrono::Utc>>,
+        #[serde(default)]
+        pub role: Role,
+        /// Part of the profile picture url, see `UserDataResponse`
+        #[serde(default)]
+        pub pfp_version: Option<String>,
+        #[serde(default)]
+        pub display_name: Option<String>,
+        #[serde(default)]
+        pub location: Option<String>,
+        #[serde(default)]
+        pub website: Option<String>,
+        #[serde(default)]
+        pub fields: Vec<ProfileField>,
+        #[serde(default)]
+        pub banner_version: Option<String>,
+        #[serde(default)]
+        pub visibility: Option<ProfileVisibility>,
+    }
+    /// Roles are ordered, every role can do everything the roles below it can
+    #[derive(Clone, Copy, serde::Serialize, serde::Deserialize, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+    #[serde(rename_all = "lowercase")]
+    pub enum Role {
+        #[default]
+        User,
+        Moderator,
+        Admin,
+    }
+    impl Role {
+        pub fn as_str(&self) -> &'static str {
+            match self {
+                Role::User => "user",
+                Role::Moderator => "moderator",
+                Role::Admin => "admin",
+            }
+        }
+    }
+    impl std::str::FromStr for Role {
+        type Err = String;
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "user" => Ok(Role::User),
+                "moderator" => Ok(Role::Moderator),
+                "admin" => Ok(Role::Admin),
+                _ => Err(format!("unknown role `{}`", s)),
+            }
+        }
+    }
+    #[derive(Clone, serde::Deserialize, serde::Serialize, Debug)]
+    pub struct AdminUserData {
+        pub login: String,
+        pub id: Uuid,
+        pub role: Role,
+        pub suspended: bool,
+        pub created: Option<chrono::DateTime<chrono::Utc>>,
+        #[serde(default)]
+        pub invited_by: Option<Uuid>,
+    }
+    #[derive(Clone, serde::Deserialize, serde::Serialize, Debug)]
+    pub struct RoleUpdate {
+        pub role: Role,
     }
     #[derive(Clone, serde::Deserialize, serde::Serialize, Debug, Default)]
     pub struct  PostData {
@@ -67,6 +275,10 @@ pub mod data_structures {
         pub user_id: String,
         pub posted: chrono::DateTime<chrono::Utc>,
         pub content: String,
+        #[serde(default)]
+        pub pfp_version: Option<String>,
+        #[serde(default)]
+        pub attachments: Vec<AttachmentData>,
     }
     #[derive(Clone, serde::Serialize, Debug)]
     pub struct ValidationErrorResponse {
@@ -77,7 +289,7 @@ pub mod data_structures {
     pub struct UserLogin {
         pub login: String,
     }
-    #[derive(Clone, serde::Serialize, serde::Deserialize, Debug)]
+    #[derive(Clone, serde::Serialize, serde::Deserialize)]
     #[cfg_attr(feature = "backend", derive(Validate))]
     pub struct UserCreationData {
         #[cfg_attr(feature = "backend",
@@ -89,16 +301,82 @@ pub mod data_structures {
             custom(function = "crate::validation::validate_user_password")
         ))]
         pub password: String,
+        #[serde(default)]
+        pub invite_code: Option<String>,
+        #[serde(default)]
+        pub pow: Option<PowSolution>,
     }
+    /// Leaves the password and the invite code out, so the data can be logged
+    impl std::fmt::Debug for UserCreationData {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("UserCreationData")
+                .field("login", &self.login)
+                .field("password", &"<redacted>")
+                .field("invite_code", &self.invite_code.as_ref().map(|_| "<redacted>"))
+                .field("pow", &self.pow)
+                .finish()
+        }
+    }
+    /// A registration puzzle, see [`crate::pow`]. The signature covers every other field
+    #[derive(Clone, serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq)]
+    pub struct PowChallenge {
+        pub salt: String,
+        pub difficulty: u8,
+        /// Unix timestamp in seconds
+        pub expires: i64,
+        pub signature: String,
+    }
+    #[derive(Clone, serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq)]
+    pub struct PowSolution {
+        pub challenge: PowChallenge,
+        pub nonce: u64,
+    }
+    /// Fields left out are kept as they are, an empty string clears
+    /// `pronouns`, `display_name`, `location` and `website`
     #[derive(Clone, serde::Deserialize)]
     #[cfg_attr(feature = "backend", derive(Validate))]
     pub struct UserUpdateData {
         #[cfg_attr(feature = "backend",
             validate(length(max = 250, message = "description was too long")))]
         pub description: Option<String>,
+        #[serde(default)]
+        #[cfg_attr(feature = "backend",
+            validate(length(max = 40, message = "pronouns were too long")))]
+        pub pronouns: Option<String>,
+        #[serde(default)]
+        #[cfg_attr(feature = "backend",
+            validate(length(max = 50, message = "display name was too long")))]
+        pub display_name: Option<String>,
+        #[serde(default)]
+        #[cfg_attr(feature = "backend",
+            validate(length(max = 100, message = "location was too long")))]
+        pub location: Option<String>,
+        #[serde(default)]
         #[cfg_attr(feature = "backend",
-            validate(length(min = 3, max = 15, message = "gender length was inproper")))]
-        pub gender: Option<String>,
+            validate(
+            length(max = 200, message = "website was too long"),
+            custom(function = "crate::validation::validate_website")
+        ))]
+        pub website: Option<String>,
+        /// Replaces all custom fields at once
+        #[serde(default)]
+        #[cfg_attr(feature = "backend",
+            validate(length(max = 4, message = "too many profile fields"), nested))]
+        pub fields: Option<Vec<ProfileField>>,
+        #[serde(default)]
+        pub visibility: Option<ProfileVisibility>,
+    }
+    pub const MAX_PROFILE_FIELDS: usize = 4;
+    /// A free form key/value pair shown on a profile
+    #[derive(Clone, serde::Serialize, serde::Deserialize, Debug, PartialEq, Default)]
+    #[cfg_attr(feature = "backend", derive(Validate))]
+    pub struct ProfileField {
+        #[cfg_attr(feature = "backend",
+            validate(length(min = 1, max = 32, message = "profile field name of disallowed size")))]
+        pub name: String,
+        #[cfg_attr(feature = "backend",
+            validate(length(min = 1, max = 200, message = "profile field value of disallowed size")))]
+        pub value: String,
     }
     #[derive(Clone, serde::Serialize)]
     pub struct LoginData {
@@ -113,5 +391,242 @@ pub mod data_structures {
         pub user_name: String,
         pub posted: chrono::DateTime<chrono::Utc>,
         pub content: String,
+        #[serde(default)]
+        pub pfp_version: Option<String>,
+    }
+    /// Query parameters of an OAuth2 authorization request, as sent by a relying party
+    #[derive(Clone, serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+    pub struct OAuthAuthorizeQuery {
+        pub response_type: String,
+        pub client_id: Uuid,
+        pub redirect_uri: String,
+        pub scope: Option<String>,
+        pub state: Option<String>,
+        pub code_challenge: String,
+        pub code_challenge_method: Option<String>,
+        pub nonce: Option<String>,
+    }
+    /// What the consent screen shows to the user
+    #[derive(Clone, serde::Serialize, serde::Deserialize, Debug)]
+    pub struct OAuthClientInfo {
+        pub client_id: Uuid,
+        pub name: String,
+        pub scopes: Vec<String>,
+    }
+    #[derive(Clone, serde::Serialize, serde::Deserialize, Debug)]
+    pub struct OAuthConsent {
+        pub request: OAuthAuthorizeQuery,
+        pub approve: bool,
+    }
+    /// Where the user agent should be sent after the consent screen
+    #[derive(Clone, serde::Serialize, serde::Deserialize, Debug)]
+    pub struct OAuthConsentResponse {
+        pub redirect: String,
+    }
+    #[derive(Clone, serde::Serialize, serde::Deserialize, Debug)]
+    #[cfg_attr(feature = "backend", derive(Validate))]
+    pub struct OAuthClientRegistration {
+        #[cfg_attr(feature = "backend",
+            validate(length(min = 1, max = 64, message = "client name of disallowed size")))]
+        pub name: String,
+        #[cfg_attr(feature = "backend",
+            validate(length(min = 1, max = 8, message = "between 1 and 8 redirect uris are allowed")))]
+        pub redirect_uris: Vec<String>,
+        /// Confidential clients get a secret, public ones rely on PKCE alone
+        pub confidential: bool,
+    }
+    #[derive(Clone, serde::Serialize, serde::Deserialize, Debug)]
+    pub struct OAuthClientCredentials {
+        pub client_id: Uuid,
+        pub name: String,
+        pub redirect_uris: Vec<String>,
+        /// Only present right after registration, it is never shown again
+        pub client_secret: Option<String>,
+    }
+    #[derive(Clone, Copy, serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq)]
+    #[serde(rename_all = "snake_case")]
+    pub enum ReportTargetKind {
+        Post,
+        Comment,
+        User,
+    }
+    #[derive(Clone, Copy, serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq)]
+    #[serde(rename_all = "snake_case")]
+    pub enum ReportCategory {
+        Spam,
+        Harassment,
+        Hate,
+        Violence,
+        Sexual,
+        Other,
+    }
+    #[derive(Clone, Copy, serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq)]
+    #[serde(rename_all = "snake_case")]
+    pub enum ReportStatus {
+        Open,
+        Claimed,
+        Resolved,
+    }
+    #[derive(Clone, Copy, serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq)]
+    #[serde(rename_all = "snake_case")]
+    pub enum ReportAction {
+        Dismiss,
+        DeleteContent,
+        SuspendUser,
+    }
+    macro_rules! snake_case_str {
+        ($($ty:ty { $($variant:ident => $s:literal),* $(,)? })*) => {
+            $(
+                impl $ty {
+                    pub fn as_str(&self) -> &'static str {
+                        match self {
+                            $(Self::$variant => $s,)*
+                        }
+                    }
+                }
+                impl std::str::FromStr for $ty {
+                    type Err = String;
+                    fn from_str(s: &str) -> Result<Self, Self::Err> {
+                        match s {
+                            $($s => Ok(Self::$variant),)*
+                            _ => Err(format!("unknown value `{}`", s)),
+                        }
+                    }
+                }
+            )*
+        };
+    }
+    snake_case_str! {
+        ReportTargetKind { Post => "post", Comment => "comment", User => "user" }
+        ReportCategory {
+            Spam => "spam",
+            Harassment => "harassment",
+            Hate => "hate",
+            Violence => "violence",
+            Sexual => "sexual",
+            Other => "other",
+        }
+        ReportStatus { Open => "open", Claimed => "claimed", Resolved => "resolved" }
+        ReportAction {
+            Dismiss => "dismiss",
+            DeleteContent => "delete_content",
+            SuspendUser => "suspend_user",
+        }
+    }
+    #[derive(Clone, serde::Serialize, serde::Deserialize, Debug)]
+    #[cfg_attr(feature = "backend", derive(Validate))]
+    pub struct ReportCreationData {
+        pub target_kind: ReportTargetKind,
+        pub target_id: Uuid,
+        pub category: ReportCategory,
+        #[cfg_attr(feature = "backend",
+            validate(length(max = 500, message = "report reason was too long")))]
+        pub reason: String,
+    }
+    #[derive(Clone, serde::Serialize, serde::Deserialize, Debug)]
+    #[cfg_attr(feature = "backend", derive(Validate))]
+    pub struct ReportResolution {
+        pub action: ReportAction,
+        #[cfg_attr(feature = "backend",
+            validate(length(max = 500, message = "resolution note was too long")))]
+        pub note: Option<String>,
+    }
+    #[derive(Clone, serde::Serialize, serde::Deserialize, Debug)]
+    pub struct ReportData {
+        pub report_id: Uuid,
+        pub reporter: String,
+        pub target_kind: ReportTargetKind,
+        pub target_id: Uuid,
+        pub category: ReportCategory,
+        pub reason: String,
+        pub created: chrono::DateTime<chrono::Utc>,
+        pub status: ReportStatus,
+        pub claimed_by: Option<Uuid>,
+        pub resolved_by: Option<Uuid>,
+        pub action: Option<ReportAction>,
+        pub note: Option<String>,
+        pub resolved: Option<chrono::DateTime<chrono::Utc>>,
+    }
+    #[derive(Clone, Copy, serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq, Default)]
+    #[serde(rename_all = "snake_case")]
+    pub enum RegistrationMode {
+        #[default]
+        Open,
+        InviteOnly,
+        Closed,
+    }
+    snake_case_str! {
+        RegistrationMode { Open => "open", InviteOnly => "invite_only", Closed => "closed" }
+    }
+    /// Returned by `GET /instance`, what a client should know before sending anything
+    #[derive(Clone, serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+    pub struct InstanceInfo {
+        pub name: String,
+        pub description: String,
+        pub registration_mode: RegistrationMode,
+        pub limits: InstanceLimits,
+    }
+    impl Default for InstanceInfo {
+        fn default() -> Self {
+            Self {
+                name: crate::vars::defaults::INSTANCE_NAME.to_owned(),
+                description: String::new(),
+                registration_mode: RegistrationMode::default(),
+                limits: InstanceLimits::default(),
+            }
+        }
+    }
+    /// Lengths count characters
+    #[derive(Clone, serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+    pub struct InstanceLimits {
+        pub post_length: usize,
+        pub comment_length: usize,
+        pub alt_text_length: usize,
+        pub post_attachments: usize,
+        pub require_alt_text: bool,
+        pub pfp_bytes: usize,
+        pub banner_bytes: usize,
+        pub attachment_bytes: usize,
+        /// Accepted for profile pictures, banners and attachments alike
+        pub image_content_types: Vec<String>,
+    }
+    impl Default for InstanceLimits {
+        /// What a server without a config file allows
+        fn default() -> Self {
+            use crate::vars::defaults;
+            Self {
+                post_length: defaults::POST_LENGTH,
+                comment_length: defaults::COMMENT_LENGTH,
+                alt_text_length: MAX_ALT_TEXT_LENGTH,
+                post_attachments: MAX_POST_ATTACHMENTS,
+                require_alt_text: false,
+                pfp_bytes: defaults::PFP_BYTES,
+                banner_bytes: defaults::BANNER_BYTES,
+                attachment_bytes: defaults::ATTACHMENT_BYTES,
+                image_content_types: ["image/jpeg", "image/png", "image/webp", "image/gif"]
+                    .map(str::to_owned)
+                    .to_vec(),
+            }
+        }
+    }
+    #[derive(Clone, serde::Serialize, serde::Deserialize, Debug, Default)]
+    #[cfg_attr(feature = "backend", derive(Validate))]
+    pub struct InviteCreationData {
+        #[cfg_attr(feature = "backend",
+            validate(range(min = 1, max = 100, message = "invite use limit out of range")))]
+        pub max_uses: Option<i32>,
+        #[cfg_attr(feature = "backend",
+            validate(range(min = 1, max = 2160, message = "invite expiry out of range")))]
+        pub expires_in_hours: Option<i64>,
+    }
+    #[derive(Clone, serde::Serialize, serde::Deserialize, Debug)]
+    pub struct InviteData {
+        pub code: String,
+        pub created_by: String,
+        pub created: chrono::DateTime<chrono::Utc>,
+        pub expires: Option<chrono::DateTime<chrono::Utc>>,
+        pub max_uses: i32,
+        pub uses: i32,
+        pub usable: bool,
     }
 }
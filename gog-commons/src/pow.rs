@@ -0,0 +1,42 @@
+//! Proof-of-work puzzle shared by the api, which checks solutions,
+//! and the frontend, which finds them.
+//!
+//! A solution is a nonce for which `sha256(salt ":" nonce)` starts with
+//! at least `difficulty` zero bits.
+
+use sha2::{Digest, Sha256};
+
+pub fn pow_hash(salt: &str, nonce: u64) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(salt.as_bytes());
+    hasher.update(b":");
+    hasher.update(nonce.to_string().as_bytes());
+    hasher.finalize().into()
+}
+
+pub fn leading_zero_bits(hash: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in hash {
+        if *byte == 0 {
+            bits += 8;
+        } else {
+            bits += byte.leading_zeros();
+            break;
+        }
+    }
+    bits
+}
+
+pub fn meets_difficulty(salt: &str, nonce: u64, difficulty: u8) -> bool {
+    leading_zero_bits(&pow_hash(salt, nonce)) >= u32::from(difficulty)
+}
+
+/// Tries `count` nonces starting at `start`, so callers can report progress between batches
+pub fn solve_range(salt: &str, difficulty: u8, start: u64, count: u64) -> Option<u64> {
+    (start..start.saturating_add(count)).find(|nonce| meets_difficulty(salt, *nonce, difficulty))
+}
+
+/// Average number of hashes needed to find a solution
+pub fn expected_attempts(difficulty: u8) -> u64 {
+    1u64.checked_shl(u32::from(difficulty)).unwrap_or(u64::MAX)
+}
@@ -17,3 +17,17 @@ pub fn validate_user_password(password: &str) -> Result<(), ValidationError> {
         Ok(())
     }
 }
+
+/// An empty website clears it, anything else has to be an http(s) url
+pub fn validate_website(website: &str) -> Result<(), ValidationError> {
+    use validator::ValidateUrl;
+    if website.is_empty()
+        || ((website.starts_with("https://") || website.starts_with("http://"))
+            && website.validate_url())
+    {
+        Ok(())
+    } else {
+        Err(ValidationError::new("website")
+            .with_message("website has to be an http or https url".into()))
+    }
+}
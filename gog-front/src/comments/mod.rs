@@ -2,9 +2,10 @@ use std::borrow::Borrow;
 
 use chrono::TimeZone;
 use gog_commons::data_structures::CommentCreationData;
-use leptos::{component, create_action, create_local_resource, create_node_ref, create_signal, view, Callable, ErrorBoundary, IntoView, NodeRef, SignalGet, SignalSet, SignalWith, Suspense};
+use leptos::{component, create_action, create_local_resource, create_node_ref, create_signal, expect_context, view, Callable, CollectView, ErrorBoundary, IntoView, NodeRef, RwSignal, SignalGet, SignalSet, SignalWith, Suspense};
 use crate::webworks;
-use crate::data::CommentData;
+use crate::data::{CommentData, InstanceInfo};
+use crate::errors::CreateCommentError;
 
 #[component]
 pub fn DisplayComment(data: CommentData) -> impl IntoView {
@@ -19,7 +20,7 @@ pub fn DisplayComment(data: CommentData) -> impl IntoView {
                     move||{get_data.get().user_name}
                 }</a>
                 <img
-                    src=move|| { webworks::get_pfp_url_for_login(get_data.get().user_name.as_str())}
+                    src=move|| { get_data.with(|d| webworks::get_pfp_url_for_login(&d.user_name, d.pfp_version.as_deref()))}
                     height="100"
                     width="100"
                     style="padding: 10px;display: block;
@@ -35,11 +36,13 @@ pub fn DisplayComment(data: CommentData) -> impl IntoView {
                         }
                     }
                 </p>
+                <crate::reports::ReportButton
+                    target_kind=crate::data::ReportTargetKind::Comment
+                    target_id=get_data.get_untracked().comment_id/>
             </div>
             <textarea type="text" wrap="hard" rows="5"
                     class="post-textbox"
                     prop:value=move||{get_data.get().content}
-                    maxlength="300"
                     readonly
             />
         </div>
@@ -48,6 +51,7 @@ pub fn DisplayComment(data: CommentData) -> impl IntoView {
 #[component]
 pub fn CommentForm(post_id: uuid::Uuid, #[prop(into)] on_posted: leptos::Callback<()>) -> impl IntoView {
     let comment_content: NodeRef<leptos::html::Textarea> = create_node_ref();
+    let instance = expect_context::<RwSignal<InstanceInfo>>();
     let comment_action = create_action(|comment_data: &CommentCreationData|{
         let comment_data = comment_data.to_owned();
         async move {
@@ -70,6 +74,10 @@ pub fn CommentForm(post_id: uuid::Uuid, #[prop(into)] on_posted: leptos::Callbac
                 None => view!{}.into_view(),
                 Some(res) => match res {
                     Ok(_) => view!{}.into_view(),
+                    Err(CreateCommentError::ValidationError(body)) => body.messages()
+                        .into_iter()
+                        .map(|m| view!{<p>"Error: " {m}</p>})
+                        .collect_view(),
                     Err(e) => view!{<p>"Error"</p>}.into_view()
                 }
             }
@@ -101,7 +109,7 @@ pub fn CommentForm(post_id: uuid::Uuid, #[prop(into)] on_posted: leptos::Callbac
             <textarea type="text" wrap="hard" rows="5"
                         class="post-textbox"
                         prop:value="Comment text"
-                        maxlength="300"
+                        maxlength=move || instance.with(|i| i.limits.comment_length)
                         node_ref=comment_content
                         on:click=move|_ev|{
                             if first_edit_get.get() {
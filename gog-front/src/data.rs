@@ -58,6 +58,32 @@ pub struct UserPostsQuery {
     pub login: Option<String>,
     pub limit: Option<u64>,
 }
+#[derive(leptos::Params, PartialEq, Clone)]
+pub struct OAuthAuthorizeParams {
+    pub response_type: Option<String>,
+    pub client_id: Option<uuid::Uuid>,
+    pub redirect_uri: Option<String>,
+    pub scope: Option<String>,
+    pub state: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+    pub nonce: Option<String>,
+}
+impl OAuthAuthorizeParams {
+    /// Returns None if a parameter required by the api is missing
+    pub fn into_request(self) -> Option<OAuthAuthorizeQuery> {
+        Some(OAuthAuthorizeQuery {
+            response_type: self.response_type?,
+            client_id: self.client_id?,
+            redirect_uri: self.redirect_uri?,
+            scope: self.scope,
+            state: self.state,
+            code_challenge: self.code_challenge?,
+            code_challenge_method: self.code_challenge_method,
+            nonce: self.nonce,
+        })
+    }
+}
 //#[derive(Clone, serde::Deserialize, Serialize, Debug)]
 //pub struct CommentData {
 //    pub comment_id: String,
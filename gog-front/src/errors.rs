@@ -9,6 +9,16 @@ pub struct ValidationErrorBody {
     pub errors: HashMap<String, Vec<FieldError>>
 }
 
+impl ValidationErrorBody {
+    /// Every field error message, for displaying next to a form
+    pub fn messages(&self) -> Vec<String> {
+        self.errors.values()
+            .flatten()
+            .filter_map(|e| e.message.clone())
+            .collect()
+    }
+}
+
 #[derive(serde::Deserialize, Debug)]
 pub struct FieldError {
     pub code: Option<String>,
@@ -47,6 +57,10 @@ pub enum WebworksError {
 pub enum RegisterError{
     #[error("validation error")]
     ValidationError(ValidationErrorBody),
+    #[error("registration rejected `{reason}`")]
+    Rejected{
+        reason: String
+    },
     #[error("webworks error")]
     Webworks{
         #[from]
@@ -112,3 +126,27 @@ pub enum CreatePostError {
         source: WebworksError
     }
 }
+
+#[derive(Error, Debug)]
+pub enum AttachmentUploadError {
+    #[error("file rejected `{reason}`")]
+    Rejected{
+        reason: String
+    },
+    #[error("webworks error")]
+    Webworks{
+        #[from]
+        source: WebworksError
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum CreateCommentError {
+    #[error("validation error")]
+    ValidationError(ValidationErrorBody),
+    #[error("webworks error")]
+    Webworks{
+        #[from]
+        source: WebworksError
+    }
+}
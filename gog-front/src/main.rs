@@ -10,17 +10,20 @@ pub(crate) mod macros {
 mod webworks;
 mod errors;
 mod posts;
+mod oauth;
+mod pow;
+pub(crate) mod reports;
 pub(crate) mod comments;
 pub(crate) mod util;
 pub(crate) mod loader;
 pub(crate) mod data;
 use std::str::FromStr;
 
-use data::UserData;
+use data::{InstanceInfo, RegistrationMode, UserData};
 use errors::{LoginError, PfpUploadError, RegisterError, UpdateUserError};
 use gog_commons::data_structures::PostsFilter;
 use leptos::leptos_dom::logging::{self, console_error};
-use leptos::{component, create_resource, create_action, create_node_ref, event_target, event_target_value, expect_context, prelude::*, provide_context, spawn_local, with, CollectView, IntoView, NodeRef};
+use leptos::{component, create_resource, create_action, create_node_ref, event_target, event_target_value, expect_context, prelude::*, provide_context, spawn_local, with, Callable, CollectView, IntoView, NodeRef, Show, Signal};
 use leptos::view;
 use leptos_router::{use_navigate, use_query, NavigateOptions, Route, Router, Routes};
 use leptos::logging::*;
@@ -42,6 +45,18 @@ fn NotFound()  -> impl IntoView {
 #[component]
 fn App() -> impl IntoView {
     provide_context(create_rw_signal::<Option<UserData>>(None));
+    // the defaults of the api until it answers
+    let instance = create_rw_signal(InstanceInfo::default());
+    provide_context(instance);
+    spawn_local(async move {
+        match webworks::get_instance().await {
+            Ok(info) => {
+                leptos::document().set_title(&info.name);
+                instance.set(info);
+            },
+            Err(e) => console_error(&format!("failed to get the instance info: {}", e)),
+        }
+    });
     let logged_user_posts = move||{
         view!{
             <UserPosts />
@@ -63,6 +78,8 @@ fn App() -> impl IntoView {
                             <Route path="/register" view=RegisterForm></Route>
                             <Route path="/posts" view=PostsFrontPage />
                             <Route path="/post" view=posts::Post />
+                            <Route path="/oauth/authorize" view=oauth::OAuthAuthorize />
+                            <Route path="/moderation" view=reports::ModerationQueue />
                             <Route path="*any" view=NotFound/>
                     </Routes>
                 </div>
@@ -74,8 +91,12 @@ fn App() -> impl IntoView {
 #[component]
 fn Navigation() -> impl IntoView {
     use leptos_router::A;
+    let instance = expect_context::<RwSignal<InstanceInfo>>();
     view!{
         <ul>
+            <li title=move || instance.with(|i| i.description.clone())>
+                <strong>{move || instance.with(|i| i.name.clone())}</strong>
+            </li>
             <li style="float: right;"><A href="register">"Register"</A></li>
             <li style="float: right;"><A href="login">"Login"</A></li>
             <li><A href="user">"User"</A></li>
@@ -96,12 +117,41 @@ fn RegisterForm() -> impl IntoView {
     let (login, set_login) = create_signal("".to_string());
     let (password, set_password) = create_signal("".to_string());
     let (rep_password, set_rep_password) = create_signal("".to_string());
+    // invite links point at `/register?invite=<code>`
+    let invite_query = leptos_router::use_query_map()
+        .get_untracked()
+        .get("invite")
+        .cloned()
+        .unwrap_or_default();
+    let (invite_code, set_invite_code) = create_signal(invite_query);
+    let instance = expect_context::<RwSignal<InstanceInfo>>();
+    let mode_notice = move || match instance.with(|i| i.registration_mode) {
+        RegistrationMode::Open => None,
+        RegistrationMode::InviteOnly => Some(view!{
+            <p>"Registration on this instance needs an invite code"</p>
+        }),
+        RegistrationMode::Closed => Some(view!{
+            <p>"Registration on this instance is closed"</p>
+        }),
+    };
 
 
     let (validation_msg , set_validation_msg) = create_signal("");
+    let (pow_progress, set_pow_progress) = create_signal(None::<u8>);
     let register_action = create_action(move|usr: &data::UserCreationData|{
-        let data = usr.clone();
-        async move { webworks::register(&data).await }
+        let mut data = usr.clone();
+        async move {
+            let challenge = webworks::get_registration_challenge().await?;
+            set_pow_progress.set(Some(0));
+            let solution = pow::solve(challenge, move |p| set_pow_progress.set(Some(p))).await;
+            set_pow_progress.set(None);
+            data.pow = Some(solution);
+            webworks::register(&data).await
+        }
+    });
+    let pow_indicator = move || pow_progress.get().map(|p| view!{
+        <p>"Checking you are not a bot... " {p} "%"</p>
+        <progress max="100" value=p></progress>
     });
 
     let on_submit = move |ev: leptos::ev::SubmitEvent| {
@@ -116,9 +166,12 @@ fn RegisterForm() -> impl IntoView {
             return;
         }
 
+        let invite_code = invite_code.get();
         register_action.dispatch(data::UserCreationData{
             login,
             password,
+            invite_code: (!invite_code.trim().is_empty()).then_some(invite_code),
+            pow: None,
         });
         set_validation_msg.set("");
     };
@@ -161,6 +214,9 @@ fn RegisterForm() -> impl IntoView {
                             </ul>
                         }.into_view()
                     },
+                    RegisterError::Rejected { reason } => view!{
+                        <p>"Registration refused: " {reason}</p>
+                    }.into_view(),
                     RegisterError::Webworks { source } => {
                         logging::console_error(&source.to_string());
                         view!{
@@ -192,6 +248,7 @@ fn RegisterForm() -> impl IntoView {
     view! {
         <div>
             <h3 style="text-align: center;">"Register a new account"</h3>
+            {mode_notice}
             <Form method="GET" action="" class="formcenter"
                 on:submit=on_submit>
                 <label for="reg-login">"Login:"</label><br/>
@@ -215,9 +272,17 @@ fn RegisterForm() -> impl IntoView {
                     }/>
                 {valid}
                 <br/>
-                <input type="submit"/><br/>
+                <label for="reg-invite">"Invite code:"</label><br/>
+                <input type="text" id="reg-invite"
+                    prop:value=invite_code
+                    on:input=move |ev| {
+                        set_invite_code.set(event_target_value(&ev));
+                    }/>
+                <br/>
+                <input type="submit" disabled=move || register_action.pending().get()/><br/>
             </Form>
             <p>{validation_msg}</p>
+            {pow_indicator}
             <p>{register_result}</p>
         </div>
     }
@@ -338,13 +403,49 @@ fn EditUser() -> impl IntoView {
         return view! {}.into_view()
     };
     let (data, set_data) = create_signal(user_data);
+    let instance = expect_context::<RwSignal<InstanceInfo>>();
 
     let edit_desc = move |ev: leptos::ev::Event| {
         set_data.update(|d| d.description = event_target_value(&ev));
     };
-    let edit_gender = move|ev: leptos::ev::Event| {
-        set_data.update(|d| d.gender = event_target_value(&ev));
+    // an empty string clears these on the api side
+    let edit_pronouns = move|ev: leptos::ev::Event| {
+        set_data.update(|d| d.pronouns = Some(event_target_value(&ev)));
     };
+    let edit_display_name = move|ev: leptos::ev::Event| {
+        set_data.update(|d| d.display_name = Some(event_target_value(&ev)));
+    };
+    let edit_location = move|ev: leptos::ev::Event| {
+        set_data.update(|d| d.location = Some(event_target_value(&ev)));
+    };
+    let edit_website = move|ev: leptos::ev::Event| {
+        set_data.update(|d| d.website = Some(event_target_value(&ev)));
+    };
+    let field_rows = move || data.with(|d| d.fields.iter().enumerate().map(|(i, f)| view!{
+        <tr>
+            <td>
+                <input type="text" placeholder="Name" maxlength="32"
+                    prop:value=f.name.clone()
+                    on:input=move|ev| {
+                        let value = event_target_value(&ev);
+                        set_data.update(|d| if let Some(f) = d.fields.get_mut(i) { f.name = value });
+                    }/>
+            </td>
+            <td>
+                <input type="text" placeholder="Value" maxlength="200"
+                    prop:value=f.value.clone()
+                    on:input=move|ev| {
+                        let value = event_target_value(&ev);
+                        set_data.update(|d| if let Some(f) = d.fields.get_mut(i) { f.value = value });
+                    }/>
+            </td>
+            <td>
+                <button type="button" on:click=move|_| set_data.update(|d| { d.fields.remove(i); })>
+                    "Remove"
+                </button>
+            </td>
+        </tr>
+    }).collect_view());
 
     let update_action = create_action(|input: &data::UserData|{
         let input = input.clone();
@@ -416,15 +517,27 @@ fn EditUser() -> impl IntoView {
         update_action.dispatch(user_data);
     };
 
-    let update_pfp_action = create_action(|file: &web_sys::File| {
+    let update_pfp_action = create_action(move |file: &web_sys::File| {
         let input = file.clone();
+        let limits = instance.with_untracked(|i| i.limits.clone());
         async move {
-            let mut rec = webworks::upload_new_pfp(input).await;
+            let mut rec = webworks::upload_new_pfp(input, limits).await;
             rec.recv().await
         }
     });
     let pfp_pending = update_pfp_action.pending();
     let update_pfp_value = update_pfp_action.value();
+    create_effect(move |_| {
+        let version = update_pfp_value.with(|v| match v {
+            Some(Some(Ok(version))) => Some(version.clone()),
+            _ => None,
+        });
+        if let Some(version) = version {
+            // a new version means a new url, so the uploaded picture shows up right away
+            set_data.update(|d| d.pfp_version = Some(version.clone()));
+            user_data_state.update(|d| if let Some(d) = d { d.pfp_version = Some(version) });
+        }
+    });
     let pfp_outcome = move || {
         update_pfp_value.with(|v| {
             let Some(v) = v else {
@@ -454,6 +567,43 @@ fn EditUser() -> impl IntoView {
         })
     };
 
+    let update_banner_action = create_action(move |file: &web_sys::File| {
+        let input = file.clone();
+        let limits = instance.with_untracked(|i| i.limits.clone());
+        async move { webworks::upload_banner(input, limits).await }
+    });
+    let banner_pending = update_banner_action.pending();
+    let update_banner_value = update_banner_action.value();
+    create_effect(move |_| {
+        let version = update_banner_value.with(|v| match v {
+            Some(Ok(version)) => Some(version.clone()),
+            _ => None,
+        });
+        if let Some(version) = version {
+            set_data.update(|d| d.banner_version = Some(version.clone()));
+            user_data_state.update(|d| if let Some(d) = d { d.banner_version = Some(version) });
+        }
+    });
+    let banner_outcome = move || {
+        update_banner_value.with(|v| match v {
+            None => view!{}.into_view(),
+            Some(Ok(_)) => view!{<p>"Uploaded!"</p>}.into_view(),
+            Some(Err(PfpUploadError::Rejected { reason })) => {
+                view!{<p>"File rejected: " {reason}</p>}.into_view()
+            },
+            Some(Err(e)) => {
+                console_error(&e.to_string());
+                view!{<p>"An error has occured"</p>}.into_view()
+            }
+        })
+    };
+    let on_input_banner = move |ev: web_sys::Event| {
+        let target: web_sys::HtmlInputElement = event_target(&ev);
+        if let Some(file) = target.files().and_then(|f| f.get(0)) {
+            update_banner_action.dispatch(file);
+        }
+    };
+
     let on_input_image = move |ev: web_sys::Event| {
         use leptos::web_sys;
         let target: web_sys::HtmlInputElement = event_target(&ev);
@@ -468,17 +618,66 @@ fn EditUser() -> impl IntoView {
             <table style="width:100%;table-layout:fixed;border: 1px dotted white; padding:10px;" >
                 <tr>
                     <td>
-                        <label for="genderinput">"Gender: "</label>
+                        <label for="pronounsinput">"Pronouns: "</label>
                         <input type="text"
-                            name="gender"
-                            id="generinput"
-                            list="genders"
-                            on:input=edit_gender
-                            prop:value={data.get().gender}/>
-                        <datalist id="genders">
-                            <option value="male"/>
-                            <option value="female"/>
+                            name="pronouns"
+                            id="pronounsinput"
+                            list="pronouns"
+                            maxlength="40"
+                            on:input=edit_pronouns
+                            prop:value={data.get().pronouns.unwrap_or_default()}/>
+                        <datalist id="pronouns">
+                            {data::PRONOUN_PRESETS.iter().map(|p| view!{<option value=*p/>}).collect_view()}
                         </datalist>
+                        <VisibilitySelect
+                            value=Signal::derive(move || data.with(|d| d.visibility.unwrap_or_default().pronouns))
+                            on_change=move |v: data::Visibility| set_data.update(|d| d.visibility.get_or_insert_with(Default::default).pronouns = v)
+                        />
+                        <br/>
+                        <label for="displaynameinput">"Display name: "</label>
+                        <input type="text"
+                            id="displaynameinput"
+                            maxlength="50"
+                            on:input=edit_display_name
+                            prop:value={data.get().display_name.unwrap_or_default()}/>
+                        <br/>
+                        <label for="locationinput">"Location: "</label>
+                        <input type="text"
+                            id="locationinput"
+                            maxlength="100"
+                            on:input=edit_location
+                            prop:value={data.get().location.unwrap_or_default()}/>
+                        <VisibilitySelect
+                            value=Signal::derive(move || data.with(|d| d.visibility.unwrap_or_default().location))
+                            on_change=move |v: data::Visibility| set_data.update(|d| d.visibility.get_or_insert_with(Default::default).location = v)
+                        />
+                        <br/>
+                        <label for="websiteinput">"Website: "</label>
+                        <input type="url"
+                            id="websiteinput"
+                            maxlength="200"
+                            placeholder="https://"
+                            on:input=edit_website
+                            prop:value={data.get().website.unwrap_or_default()}/>
+                        <VisibilitySelect
+                            value=Signal::derive(move || data.with(|d| d.visibility.unwrap_or_default().website))
+                            on_change=move |v: data::Visibility| set_data.update(|d| d.visibility.get_or_insert_with(Default::default).website = v)
+                        />
+                        <p>"Profile fields: "
+                            <VisibilitySelect
+                                value=Signal::derive(move || data.with(|d| d.visibility.unwrap_or_default().fields))
+                                on_change=move |v: data::Visibility| set_data.update(|d| d.visibility.get_or_insert_with(Default::default).fields = v)
+                            />
+                        </p>
+                        <table class="profile-fields">
+                            {field_rows}
+                        </table>
+                        <Show when=move || data.with(|d| d.fields.len() < data::MAX_PROFILE_FIELDS)>
+                            <button type="button"
+                                on:click=move|_| set_data.update(|d| d.fields.push(data::ProfileField::default()))>
+                                "Add field"
+                            </button>
+                        </Show>
                         <p>"Edit description:"</p>
                         <textarea type="text" wrap="hard" rows="20"
                         on:input=edit_desc
@@ -488,7 +687,7 @@ fn EditUser() -> impl IntoView {
                     </td>
                     <td>
                         <div style="text-align: center">
-                        <img src={webworks::get_pfp_url_for_login(&data.get().login)}
+                        <img src=move || data.with(|d| webworks::get_pfp_url_for_login(&d.login, d.pfp_version.as_deref()))
                             alt="User profile picture"
                             style="width:200px;height:200px;"
                             />
@@ -501,7 +700,7 @@ fn EditUser() -> impl IntoView {
                         <input type="file"
                             name="pfp-file"
                             id="pfp"
-                            accept="image/jpeg"
+                            accept=move || instance.with(|i| i.limits.image_content_types.join(","))
                             on:change=on_input_image
                         />
                         { move || {
@@ -510,6 +709,29 @@ fn EditUser() -> impl IntoView {
                             })
                         }}
                         {pfp_outcome}
+                        <br/>
+                        {move || data.with(|d| d.banner_version.clone().map(|v| view!{
+                            <img class="profile-banner"
+                                src=webworks::get_banner_url_for_login(&d.login, &v)
+                                alt="Profile banner"/>
+                        }))}
+                        <br/>
+                        <label for="banner">
+                            "Choose a new banner, it is cropped to 1500x500"
+                        </label>
+                        <br/>
+                        <input type="file"
+                            name="banner-file"
+                            id="banner"
+                            accept=move || instance.with(|i| i.limits.image_content_types.join(","))
+                            on:change=on_input_banner
+                        />
+                        { move || {
+                            banner_pending.get().then(|| view!{
+                                <p>"Uploading file..."</p>
+                            })
+                        }}
+                        {banner_outcome}
                         </div>
                     </td>
                 </tr>
@@ -547,19 +769,21 @@ fn DisplayUser(user_data: Option<data::UserData>) -> impl IntoView {
     data.set(Some(user_data.clone()));
     view! {
         <div>
+        <ProfileBanner data=user_data.clone()/>
         <table style="width:100%;table-layout:fixed;">
             <tr>
                 <td style="border: 1px dotted white; padding:10px;">
-                    <h1>{&user_data.login}</h1>
+                    <ProfileName data=user_data.clone()/>
                     <p>"Joined: " {
                         format!("{}", &user_data.created.unwrap_or_default().format("%Y-%m-%d"))
                     }</p>
-                    <p>"Gender: " {&user_data.gender}</p>
-                    <p>"Description: " {user_data.description}</p>
+                    {user_data.pronouns.clone().map(|p| view!{<p>"Pronouns: " {p}</p>})}
+                    <p>"Description: " {user_data.description.clone()}</p>
+                    <ProfileDetails data=user_data.clone()/>
                 </td>
                 <td style="text-align: right">
                     <div>
-                    <img src={webworks::get_pfp_url_for_login(&user_data.login)}
+                    <img src={webworks::get_pfp_url_for_login(&user_data.login, user_data.pfp_version.as_deref())}
                         alt="User profile picture"
                         style="width:200px;height:200px;"/>
                     </div>
@@ -587,6 +811,15 @@ fn DisplayUser(user_data: Option<data::UserData>) -> impl IntoView {
             }>
             "Your posts"
         </button>
+        {(user_data.role >= data::Role::Moderator).then(|| view!{
+            <button
+                on:click=move|_| {
+                    let nav = use_navigate();
+                    nav("/moderation", NavigateOptions::default());
+                }>
+                "Moderation queue"
+            </button>
+        })}
         <button
             on:click=move|_| {
                 logout_action.dispatch(());
@@ -601,6 +834,76 @@ fn DisplayUser(user_data: Option<data::UserData>) -> impl IntoView {
 
 }
 
+/// Picks who can see one profile field
+#[component]
+fn VisibilitySelect(
+    value: Signal<data::Visibility>,
+    #[prop(into)] on_change: leptos::Callback<data::Visibility>,
+) -> impl IntoView {
+    use data::Visibility;
+    let options = [
+        (Visibility::Public, "public", "Everyone"),
+        (Visibility::Followers, "followers", "Followers"),
+        (Visibility::OnlyMe, "only_me", "Only me"),
+    ];
+    view!{
+        <select
+            title="Who can see this"
+            on:change=move |ev| {
+                let picked = event_target_value(&ev);
+                if let Some((v, _, _)) = options.iter().find(|(_, name, _)| *name == picked) {
+                    on_change.call(*v);
+                }
+            }
+        >
+            {options.iter().map(|(v, name, label)| {
+                let v = *v;
+                view!{
+                    <option value=*name selected=move || value.get() == v>{*label}</option>
+                }
+            }).collect_view()}
+        </select>
+    }
+}
+
+#[component]
+fn ProfileBanner(data: UserData) -> impl IntoView {
+    data.banner_version.map(|version| view!{
+        <img class="profile-banner"
+            src=webworks::get_banner_url_for_login(&data.login, &version)
+            alt="Profile banner"/>
+    })
+}
+
+/// The display name when there is one, with the login below it
+#[component]
+fn ProfileName(data: UserData) -> impl IntoView {
+    match data.display_name {
+        Some(name) => view!{
+            <h1 style="margin-bottom:0;">{name}</h1>
+            <p style="margin-top:0;">"@" {data.login}</p>
+        }.into_view(),
+        None => view!{ <h1>{data.login}</h1> }.into_view(),
+    }
+}
+
+#[component]
+fn ProfileDetails(data: UserData) -> impl IntoView {
+    let fields = (!data.fields.is_empty()).then(|| {
+        let rows = data.fields.into_iter()
+            .map(|f| view!{ <tr><th>{f.name}</th><td>{f.value}</td></tr> })
+            .collect_view();
+        view!{ <table class="profile-fields">{rows}</table> }
+    });
+    view!{
+        {data.location.map(|l| view!{ <p>"Location: " {l}</p> })}
+        {data.website.map(|w| view!{
+            <p>"Website: " <a href=w.clone() target="_blank" rel="nofollow noopener noreferrer">{w}</a></p>
+        })}
+        {fields}
+    }
+}
+
 #[component]
 fn DisplayOtherUser() -> impl IntoView {
     use data::UserProfileQuery;
@@ -620,19 +923,21 @@ fn DisplayOtherUser() -> impl IntoView {
     let display = move|data: UserData| {
         view!{
             <div>
+            <ProfileBanner data=data.clone()/>
             <table style="width:100%;table-layout:fixed;">
                 <tr>
                     <td style="border: 1px dotted white; padding:10px;">
-                        <h1>{data.login.clone()}</h1>
+                        <ProfileName data=data.clone()/>
                         <p>"Joined: " {
                             format!("{}", data.created.unwrap_or_default().format("%Y-%m-%d"))
                         }</p>
-                        <p>"Gender: " {data.gender}</p>
-                        <p>"Description: " {data.description}</p>
+                        {data.pronouns.clone().map(|p| view!{<p>"Pronouns: " {p}</p>})}
+                        <p>"Description: " {data.description.clone()}</p>
+                        <ProfileDetails data=data.clone()/>
                     </td>
                     <td style="text-align: right">
                         <div>
-                        <img src={webworks::get_pfp_url_for_login(&data.login)}
+                        <img src={webworks::get_pfp_url_for_login(&data.login, data.pfp_version.as_deref())}
                             alt="User profile picture"
                             style="width:200px;height:200px;"/>
                         </div>
@@ -658,6 +963,9 @@ fn DisplayOtherUser() -> impl IntoView {
                 }>
                 "User posts"
             </button>
+            {uuid::Uuid::from_str(&data.id).ok().map(|id| view!{
+                <reports::ReportButton target_kind=data::ReportTargetKind::User target_id=id/>
+            })}
             </div>
         }
     };
@@ -0,0 +1,92 @@
+use leptos::{component, create_action, create_local_resource, create_resource, view, CollectView, IntoView, Suspense, prelude::*};
+use leptos_router::{use_query, A};
+use crate::data::{OAuthAuthorizeParams, OAuthConsent};
+use crate::webworks;
+
+/// Consent screen that other apps send the user to when they "Sign in with gog-magog"
+#[component]
+pub fn OAuthAuthorize() -> impl IntoView {
+    let query = use_query::<OAuthAuthorizeParams>();
+    let Some(request) = query.get_untracked().ok().and_then(|q| q.into_request()) else {
+        return view!{
+            <p style="text-align:center;">"The application sent an invalid sign in request"</p>
+        }.into_view();
+    };
+
+    let user_data = create_resource(|| (), |_| async move { webworks::get_user_data().await });
+    let info_request = request.clone();
+    let client_info = create_local_resource(|| (), move |_| {
+        let request = info_request.clone();
+        async move { webworks::get_oauth_client_info(&request).await }
+    });
+
+    let consent_action = create_action(|consent: &OAuthConsent| {
+        let consent = consent.clone();
+        async move { webworks::send_oauth_consent(&consent).await }
+    });
+    let consent_value = consent_action.value();
+    let consent_outcome = move || {
+        consent_value.with(|v| match v {
+            None => view!{}.into_view(),
+            Some(Ok(resp)) => {
+                let _ = leptos::window().location().set_href(&resp.redirect);
+                view!{<p>"Redirecting..."</p>}.into_view()
+            },
+            Some(Err(e)) => {
+                leptos::logging::error!("{}", e);
+                view!{<p>"An error has occured"</p>}.into_view()
+            }
+        })
+    };
+
+    let approve_request = request.clone();
+    let deny_request = request.clone();
+    let consent_form = move || {
+        let approve_request = approve_request.clone();
+        let deny_request = deny_request.clone();
+        match (user_data.get(), client_info.get()) {
+            (None, _) | (_, None) => view!{}.into_view(),
+            (Some(None), _) => view!{
+                <p>"You have to be logged in to continue"</p>
+                <A href="/login">"Log in"</A>
+            }.into_view(),
+            (_, Some(Err(e))) => {
+                leptos::logging::error!("{}", e);
+                view!{<p>"The application requesting access is not registered"</p>}.into_view()
+            },
+            (Some(Some(user)), Some(Ok(info))) => view!{
+                <h3>{info.name.clone()}" wants to access your account"</h3>
+                <p>"Signed in as " {user.login}</p>
+                <p>"Requested permissions:"</p>
+                <ul>
+                    {info.scopes.iter().map(|s| view!{<li>{s.clone()}</li>}).collect_view()}
+                </ul>
+                <button
+                    type="button"
+                    on:click=move|_| consent_action.dispatch(OAuthConsent {
+                        request: approve_request.clone(),
+                        approve: true,
+                    })>
+                    "Allow"
+                </button>
+                <button
+                    type="button"
+                    on:click=move|_| consent_action.dispatch(OAuthConsent {
+                        request: deny_request.clone(),
+                        approve: false,
+                    })>
+                    "Deny"
+                </button>
+            }.into_view()
+        }
+    };
+
+    view!{
+        <div class="formcenter">
+            <Suspense fallback=move|| view!{<p>"Loading..."</p>}>
+                {consent_form}
+            </Suspense>
+            {consent_outcome}
+        </div>
+    }.into_view()
+}
@@ -0,0 +1,72 @@
+use leptos::{component, create_signal, on_cleanup, view, window_event_listener, CollectView, IntoView, Show, SignalGet, SignalSet, SignalUpdate, SignalWith};
+use crate::data::AttachmentData;
+use crate::webworks;
+
+/// Thumbnails of a post's images, clicking one opens it in a lightbox
+#[component]
+pub fn Gallery(attachments: Vec<AttachmentData>) -> impl IntoView {
+    let count = attachments.len();
+    let (get_attachments, _) = create_signal(attachments);
+    let (open, set_open) = create_signal::<Option<usize>>(None);
+
+    let step = move |by: usize| set_open.update(|o| if let Some(i) = o { *i = (*i + by) % count });
+    let keys = window_event_listener(leptos::ev::keydown, move |ev| {
+        if open.get_untracked().is_none() {
+            return;
+        }
+        match ev.key().as_str() {
+            "Escape" => set_open.set(None),
+            "ArrowLeft" => step(count - 1),
+            "ArrowRight" => step(1),
+            _ => (),
+        }
+    });
+    on_cleanup(move || keys.remove());
+
+    let thumbnails = move || get_attachments.with(|attachments| attachments.iter().enumerate().map(|(i, a)| {
+        let alt = a.alt_text.clone().unwrap_or_default();
+        view!{
+            <img
+                class="gallery-thumbnail"
+                src=webworks::get_attachment_url(a.attachment_id, true)
+                alt=alt.clone()
+                title=alt
+                on:click=move|_| set_open.set(Some(i))
+            />
+        }
+    }).collect_view());
+
+    let current = move || open.get().and_then(|i| get_attachments.with(|a| a.get(i).cloned()));
+
+    view!{
+        <div class="gallery">
+            {thumbnails}
+        </div>
+        <Show when=move || open.with(Option::is_some)>
+            <div class="lightbox" on:click=move|_| set_open.set(None)>
+                {move || current().map(|a| {
+                    let alt = a.alt_text.clone().unwrap_or_default();
+                    view!{
+                        <figure class="lightbox-content" on:click=|ev| ev.stop_propagation()>
+                            <img
+                                src=webworks::get_attachment_url(a.attachment_id, false)
+                                alt=alt.clone()
+                                width=a.width
+                                height=a.height
+                            />
+                            <figcaption>{alt}</figcaption>
+                            <div class="lightbox-controls">
+                                <Show when=move || { count > 1 }>
+                                    <button on:click=move|_| step(count - 1)>"<"</button>
+                                    <span>{move || open.get().map_or(0, |i| i + 1)} " / " {count}</span>
+                                    <button on:click=move|_| step(1)>">"</button>
+                                </Show>
+                                <button on:click=move|_| set_open.set(None)>"Close"</button>
+                            </div>
+                        </figure>
+                    }
+                })}
+            </div>
+        </Show>
+    }
+}
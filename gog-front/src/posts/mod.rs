@@ -1,11 +1,13 @@
+mod gallery;
+
 use super::loader::*;
 use chrono::TimeZone;
 use leptos_router::{use_query, NavigateOptions};
 use std::str::FromStr;
 
-use leptos::{create_action,  Callable};
+use leptos::{create_action, event_target, event_target_value, Callable, CollectView};
 use leptos::{component, IntoView, Suspense, view, prelude::*, expect_context, create_resource, Show, NodeRef, create_node_ref};
-use crate::errors::{CreatePostError, WebworksError};
+use crate::errors::{AttachmentUploadError, CreatePostError, WebworksError};
 use super::data::*;
 use super::webworks;
 
@@ -102,6 +104,11 @@ async fn user_posts_loader(filter: PostsFilter, v: i32) -> Result<Vec<PostData>,
 fn PostForm(user_data: Option<UserData>, #[prop(into)] on_posted: leptos::Callback<()>) -> impl IntoView {
     let (get, set) = create_signal(user_data);
     let post_content: NodeRef<leptos::html::Textarea> = create_node_ref();
+    // uploaded images in the order they will be shown, each with the alt text typed so far
+    let (attachments, set_attachments) = create_signal(Vec::<(AttachmentData, String)>::new());
+    let (uploading, set_uploading) = create_signal(0usize);
+    let (upload_error, set_upload_error) = create_signal::<Option<String>>(None);
+    let instance = expect_context::<RwSignal<InstanceInfo>>();
 
     let post_action = create_action(|post_data: &PostCreationData|{
         let post_data = post_data.to_owned();
@@ -119,6 +126,7 @@ fn PostForm(user_data: Option<UserData>, #[prop(into)] on_posted: leptos::Callba
                     Ok(_) => {
                         on_posted.call(());
                         post_content.get().unwrap().set_value("");
+                        set_attachments.set(vec![]);
                         view!{
                             <p style="text-align:center;">"Uploaded!"</p>
                         }.into_view()
@@ -129,9 +137,12 @@ fn PostForm(user_data: Option<UserData>, #[prop(into)] on_posted: leptos::Callba
                         CreatePostError::NotLoggedIn => view!{
                             <p style="text-align:center;">"error: User not logged in!"</p>
                         }.into_view(),
-                        CreatePostError::ValidationError(val_b) => view!{
-                            <p style="text-align:center;">"error: Post data validation error!"</p>
-                        }.into_view(),
+                        CreatePostError::ValidationError(val_b) => val_b.messages()
+                            .into_iter()
+                            .map(|m| view!{
+                                <p style="text-align:center;">"error: " {m}</p>
+                            })
+                            .collect_view(),
                         CreatePostError::Webworks { source } => view!{
                             <p style="text-align:center;">"error: An unknown error has occured!"</p>
                         }.into_view(),
@@ -144,12 +155,83 @@ fn PostForm(user_data: Option<UserData>, #[prop(into)] on_posted: leptos::Callba
     let (first_edit_get, first_edit_set) = create_signal(true);
     let on_click_post = move|_| {
         let content = post_content.get().unwrap().value();
+        if instance.with_untracked(|i| i.limits.require_alt_text)
+            && attachments.with_untracked(|a| a.iter().any(|(_, alt)| alt.trim().is_empty())) {
+            set_upload_error.set(Some("every image needs a description on this instance".to_string()));
+            return;
+        }
+        let attachments = attachments.get().into_iter()
+            .map(|(a, alt)| AttachmentRef {
+                attachment_id: a.attachment_id,
+                alt_text: (!alt.trim().is_empty()).then_some(alt),
+            })
+            .collect();
         let pcdata = PostCreationData {
-            content
+            content,
+            attachments
         };
         post_action.dispatch(pcdata);
     };
 
+    let on_input_images = move |ev: leptos::ev::Event| {
+        use leptos::web_sys;
+        let target: web_sys::HtmlInputElement = event_target(&ev);
+        let Some(file_list) = target.files() else { return };
+        let max_attachments = instance.with_untracked(|i| i.limits.post_attachments);
+        let free = max_attachments
+            .saturating_sub(attachments.with_untracked(|a| a.len()) + uploading.get_untracked());
+        if file_list.length() as usize > free {
+            set_upload_error.set(Some(format!("a post can have at most {} images", max_attachments)));
+        } else {
+            set_upload_error.set(None);
+        }
+        for i in 0..(file_list.length() as usize).min(free) {
+            let Some(file) = file_list.get(i as u32) else { continue };
+            set_uploading.update(|n| *n += 1);
+            let limits = instance.with_untracked(|i| i.limits.clone());
+            leptos::spawn_local(async move {
+                match webworks::upload_attachment(file, limits).await {
+                    Ok(data) => set_attachments.update(|a| a.push((data, String::new()))),
+                    Err(AttachmentUploadError::Rejected { reason }) => set_upload_error.set(Some(reason)),
+                    Err(err) => {
+                        leptos::logging::error!("{:?}", err);
+                        set_upload_error.set(Some("could not upload the image".to_string()));
+                    }
+                }
+                set_uploading.update(|n| *n -= 1);
+            });
+        }
+        // the same file can be picked again after it was removed
+        target.set_value("");
+    };
+    let move_attachment = move |from: usize, to: usize| set_attachments.update(|a| {
+        if to < a.len() {
+            a.swap(from, to);
+        }
+    });
+    let attachment_list = move || attachments.with(|list| list.iter().enumerate().map(|(i, (a, alt))| {
+        let id = a.attachment_id;
+        view!{
+            <div class="attachment-item">
+                <img class="gallery-thumbnail" src=webworks::get_attachment_url(id, true)/>
+                <input type="text"
+                    placeholder="Image description"
+                    maxlength=move || instance.with(|i| i.limits.alt_text_length)
+                    prop:value=alt.clone()
+                    on:input=move|ev| {
+                        let value = event_target_value(&ev);
+                        set_attachments.update(|a| if let Some(item) = a.get_mut(i) { item.1 = value });
+                    }
+                />
+                <div>
+                    <button type="button" on:click=move|_| { if i > 0 { move_attachment(i, i - 1) } }>"<"</button>
+                    <button type="button" on:click=move|_| move_attachment(i, i + 1)>">"</button>
+                    <button type="button" on:click=move|_| set_attachments.update(|a| a.retain(|(a, _)| a.attachment_id != id))>"Remove"</button>
+                </div>
+            </div>
+        }
+    }).collect_view());
+
     view!{
         <Show
         when=move||{get.get().is_some()}
@@ -163,7 +245,7 @@ fn PostForm(user_data: Option<UserData>, #[prop(into)] on_posted: leptos::Callba
                     {move||{get.get().unwrap().login}}
                     </p>
                     <img
-                        src=move|| { webworks::get_pfp_url_for_login(&get.get().unwrap().login)}
+                        src=move|| { let data = get.get().unwrap(); webworks::get_pfp_url_for_login(&data.login, data.pfp_version.as_deref())}
                         height="100"
                         width="100"
                         style="padding: 10px;"
@@ -179,7 +261,7 @@ fn PostForm(user_data: Option<UserData>, #[prop(into)] on_posted: leptos::Callba
                 <textarea type="text" wrap="hard" rows="5"
                             class="post-textbox"
                             prop:value="Post text"
-                            maxlength="300"
+                            maxlength=move || instance.with(|i| i.limits.post_length)
                             node_ref=post_content
                             on:click=move|_ev|{
                                 if first_edit_get.get() {
@@ -189,6 +271,17 @@ fn PostForm(user_data: Option<UserData>, #[prop(into)] on_posted: leptos::Callba
                             }
                             />
             </div>
+            <div class="attachment-list">
+                {attachment_list}
+            </div>
+            <Show when=move || { attachments.with(|a| a.len()) + uploading.get() < instance.with(|i| i.limits.post_attachments) }>
+                <label>
+                    "Add images: "
+                    <input type="file" accept=move || instance.with(|i| i.limits.image_content_types.join(",")) multiple on:change=on_input_images/>
+                </label>
+            </Show>
+            {move||{(uploading.get() > 0).then(||view!{<p style="text-align:center;">"Uploading images"</p>})}}
+            {move||{upload_error.get().map(|e|view!{<p style="text-align:center;">"error: " {e}</p>})}}
             {move||{post_action_pending.get().then(||view!{<p style="text-align:center;">"Uploading your post"</p>})}}
             {post_action_outcome}
         </Show>
@@ -209,7 +302,7 @@ fn DisplayPost(data: PostData, #[prop(default = true)] comment_button: bool) ->
                     move||{get_data.get().login}
                 }</a>
                 <img
-                    src=move|| { webworks::get_pfp_url_for_login(get_data.get().login.as_str())}
+                    src=move|| { get_data.with(|d| webworks::get_pfp_url_for_login(&d.login, d.pfp_version.as_deref()))}
                     height="100"
                     width="100"
                     style="padding: 10px;display: block;
@@ -239,13 +332,25 @@ fn DisplayPost(data: PostData, #[prop(default = true)] comment_button: bool) ->
                            })
                        }
                 }
+                {move||{
+                    uuid::Uuid::from_str(&get_data.get().post_id).ok().map(|id| view!{
+                        <crate::reports::ReportButton target_kind=ReportTargetKind::Post target_id=id/>
+                    })
+                }}
+            </div>
+            <div class="post-body">
+                <textarea type="text" wrap="hard" rows="5"
+                        class="post-textbox"
+                        prop:value=move||{get_data.get().content}
+                        readonly
+                />
+                {move||{
+                    let attachments = get_data.with(|d| d.attachments.clone());
+                    (!attachments.is_empty()).then(|| view!{
+                        <gallery::Gallery attachments=attachments/>
+                    })
+                }}
             </div>
-            <textarea type="text" wrap="hard" rows="5"
-                    class="post-textbox"
-                    prop:value=move||{get_data.get().content}
-                    maxlength="300"
-                    readonly
-            />
         </div>
 
     }
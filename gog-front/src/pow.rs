@@ -0,0 +1,21 @@
+use gog_commons::pow;
+use crate::data::{PowChallenge, PowSolution};
+
+/// Nonces tried between yields to the browser, small enough to keep the page responsive
+const BATCH: u64 = 20_000;
+
+/// Solves a registration challenge, reporting progress in percent of the expected work
+pub async fn solve(challenge: PowChallenge, on_progress: impl Fn(u8)) -> PowSolution {
+    let expected = pow::expected_attempts(challenge.difficulty);
+    let mut start = 0;
+    loop {
+        if let Some(nonce) = pow::solve_range(&challenge.salt, challenge.difficulty, start, BATCH) {
+            on_progress(100);
+            return PowSolution { challenge, nonce };
+        }
+        start += BATCH;
+        // an unlucky salt takes longer than expected, so never claim to be done
+        on_progress((start.saturating_mul(100) / expected).min(99) as u8);
+        gloo_timers::future::TimeoutFuture::new(0).await;
+    }
+}
@@ -0,0 +1,144 @@
+use leptos::{component, create_action, create_local_resource, create_node_ref, create_signal, view, CollectView, IntoView, NodeRef, Show, Suspense, prelude::*};
+use std::str::FromStr;
+use uuid::Uuid;
+use crate::data::{ReportAction, ReportCategory, ReportCreationData, ReportData, ReportResolution, ReportTargetKind};
+use crate::webworks;
+
+const CATEGORIES: [ReportCategory; 6] = [
+    ReportCategory::Spam,
+    ReportCategory::Harassment,
+    ReportCategory::Hate,
+    ReportCategory::Violence,
+    ReportCategory::Sexual,
+    ReportCategory::Other,
+];
+
+/// A "Report" button that unfolds into a small report form
+#[component]
+pub fn ReportButton(target_kind: ReportTargetKind, target_id: Uuid) -> impl IntoView {
+    let (open, set_open) = create_signal(false);
+    let category: NodeRef<leptos::html::Select> = create_node_ref();
+    let reason: NodeRef<leptos::html::Textarea> = create_node_ref();
+
+    let report_action = create_action(|report: &ReportCreationData| {
+        let report = report.clone();
+        async move { webworks::report_content(&report).await }
+    });
+    let report_value = report_action.value();
+    let report_outcome = move || {
+        report_value.with(|v| match v {
+            None => view!{}.into_view(),
+            Some(Ok(_)) => view!{<p>"Reported, thank you"</p>}.into_view(),
+            Some(Err(e)) => {
+                leptos::logging::error!("{}", e);
+                view!{<p>"Could not send the report"</p>}.into_view()
+            }
+        })
+    };
+
+    let on_send = move |_| {
+        let category = category.get()
+            .and_then(|c| ReportCategory::from_str(&c.value()).ok())
+            .unwrap_or(ReportCategory::Other);
+        let reason = reason.get().map(|r| r.value()).unwrap_or_default();
+        report_action.dispatch(ReportCreationData {
+            target_kind,
+            target_id,
+            category,
+            reason,
+        });
+        set_open.set(false);
+    };
+
+    view!{
+        <button type="button" on:click=move|_| set_open.update(|o| *o = !*o)>
+            "Report"
+        </button>
+        <Show when=move|| open.get() fallback=|| view!{}>
+            <div class="report-form">
+                <select node_ref=category>
+                    {CATEGORIES.iter().map(|c| view!{
+                        <option value={c.as_str()}>{c.as_str()}</option>
+                    }).collect_view()}
+                </select><br/>
+                <textarea rows="3" maxlength="500" node_ref=reason
+                    placeholder="What is wrong with it?"/><br/>
+                <button type="button" on:click=on_send>"Send report"</button>
+            </div>
+        </Show>
+        {report_outcome}
+    }
+}
+
+/// Moderator queue of open and claimed reports
+#[component]
+pub fn ModerationQueue() -> impl IntoView {
+    let (refresh, set_refresh) = create_signal(());
+    let queue = create_local_resource(move || refresh.get(), |_| async move {
+        webworks::load_report_queue().await
+    });
+
+    let claim_action = create_action(move |id: &Uuid| {
+        let id = *id;
+        async move {
+            let res = webworks::claim_report(id).await;
+            set_refresh.set(());
+            res
+        }
+    });
+    let resolve_action = create_action(move |input: &(Uuid, ReportResolution)| {
+        let (id, resolution) = input.clone();
+        async move {
+            let res = webworks::resolve_report(id, &resolution).await;
+            set_refresh.set(());
+            res
+        }
+    });
+
+    let display_report = move |report: ReportData| {
+        let id = report.report_id;
+        let target_link = match report.target_kind {
+            ReportTargetKind::Post => format!("/post?id={}", report.target_id),
+            ReportTargetKind::Comment => String::new(),
+            ReportTargetKind::User => format!("/users?id={}", report.target_id),
+        };
+        let resolve = move |action: ReportAction| {
+            resolve_action.dispatch((id, ReportResolution { action, note: None }));
+        };
+        view!{
+            <div class="posts-section">
+                <p>
+                    <b>{report.category.as_str()}</b>
+                    " " {report.target_kind.as_str()} " "
+                    {if target_link.is_empty() {
+                        view!{<span>{report.target_id.to_string()}</span>}.into_view()
+                    } else {
+                        view!{<a href=target_link>{report.target_id.to_string()}</a>}.into_view()
+                    }}
+                </p>
+                <p>"Reported by " {report.reporter.clone()} " on "
+                    {report.created.format("%Y-%m-%d %H:%M").to_string()}</p>
+                <p>{report.reason.clone()}</p>
+                <p>"Status: " {report.status.as_str()}</p>
+                <button type="button" on:click=move|_| claim_action.dispatch(id)>"Claim"</button>
+                <button type="button" on:click=move|_| resolve(ReportAction::Dismiss)>"Dismiss"</button>
+                <button type="button" on:click=move|_| resolve(ReportAction::DeleteContent)>"Delete content"</button>
+                <button type="button" on:click=move|_| resolve(ReportAction::SuspendUser)>"Suspend user"</button>
+            </div>
+        }
+    };
+
+    view!{
+        <h1 style="text-align:center;">"Moderation queue"</h1>
+        <Suspense fallback=move|| view!{<p>"Loading reports..."</p>}>
+            {move || queue.get().map(|q| match q {
+                Ok(reports) if reports.is_empty() => view!{<p>"Nothing to moderate"</p>}.into_view(),
+                Ok(reports) => reports.into_iter().map(display_report).collect_view(),
+                Err(e) => {
+                    leptos::logging::error!("{}", e);
+                    view!{<p>"Could not load the queue, are you a moderator?"</p>}.into_view()
+                }
+            })}
+        </Suspense>
+    }
+}
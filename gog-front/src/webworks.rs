@@ -1,30 +1,93 @@
 use uuid::Uuid;
 use tokio::sync::mpsc;
 use anyhow::{Result, anyhow};
-use gloo_net::http::Request;
+use gloo_net::http::{Request, RequestBuilder};
+use std::cell::RefCell;
 use super::data::*;
 use super::errors::*;
 use leptos::{web_sys, wasm_bindgen};
 
 
 pub type WebworksResult<T> = Result<T, WebworksError>;
-const URL_BASE: &str = "http://localhost:8081/";
+/// Set `MAGOG_API_URL` when building, e.g. to `/api/` when gog-api serves the frontend itself
+const URL_BASE: &str = match option_env!("MAGOG_API_URL") {
+    Some(url) => url,
+    None => "http://localhost:8081/",
+};
+/// Profile pictures are shown at 100px, the api picks the closest stored size above it
+const PFP_DISPLAY_SIZE: u32 = 100;
+const CSRF_HEADER: &str = "X-CSRF-Token";
+
+thread_local! {
+    /// Handed out at login, or by `user/csrf` after the page was reloaded
+    static CSRF_TOKEN: RefCell<Option<String>> = RefCell::new(None);
+}
+
+/// Fetched once when the app starts, the limits of the api drive the checks of the client
+pub async fn get_instance() -> WebworksResult<InstanceInfo> {
+    Ok(Request::get(&format!("{}instance", URL_BASE))
+        .send()
+        .await?
+        .json::<InstanceInfo>()
+        .await?)
+}
+
+/// Checks an image before it is sent, the reason it would be refused otherwise
+fn check_image(file: &web_sys::File, limits: &InstanceLimits, max_bytes: usize) -> Result<(), String> {
+    if !limits.image_content_types.contains(&file.type_()) {
+        return Err(format!("the file is not one of {}", limits.image_content_types.join(", ")));
+    }
+    if file.size() > max_bytes as f64 {
+        return Err(format!("this file is bigger than {} KiB and will not be sent", max_bytes / 1024));
+    }
+    Ok(())
+}
+
+fn set_csrf_token(token: Option<String>) {
+    CSRF_TOKEN.with(|t| *t.borrow_mut() = token);
+}
+
+async fn csrf_token() -> Option<String> {
+    if let Some(token) = CSRF_TOKEN.with(|t| t.borrow().clone()) {
+        return Some(token);
+    }
+    let resp = Request::get(&format!("{}user/csrf", URL_BASE))
+        .credentials(leptos::web_sys::RequestCredentials::Include)
+        .send()
+        .await.ok()?;
+    let token = resp.json::<CsrfTokenResponse>().await.ok()?.token;
+    set_csrf_token(Some(token.clone()));
+    Some(token)
+}
+
+/// A POST carrying the session cookie, along with the CSRF token the api wants next to it
+async fn post_with_session(url: &str) -> RequestBuilder {
+    let request = Request::post(url)
+        .credentials(leptos::web_sys::RequestCredentials::Include);
+    match csrf_token().await {
+        Some(token) => request.header(CSRF_HEADER, &token),
+        None => request,
+    }
+}
 
 pub async fn get_token(data: &LoginData) -> Result<(), LoginError> {
 
     let body = serde_json::to_string(data).map_err(|e| WebworksError::Other { source: Box::new(e) })?;
 
 
-    let resp = Request::post(&(URL_BASE.to_owned() + "user/token"))
+    // a session that is still signed in has to send its token to sign in again
+    let resp = post_with_session(&(URL_BASE.to_owned() + "user/token")).await
         .header("Content-Type", "application/json")
-        .credentials(leptos::web_sys::RequestCredentials::Include)
         .body(body).map_err(|e| WebworksError::GlooError { err: e })?
         .send()
         .await;
     match resp {
         Ok(response) => {
             match response.status() {
-                202 => Ok(()),
+                202 => {
+                    set_csrf_token(response.headers().get(CSRF_HEADER));
+                    Ok(())
+                },
                 500 => Err(WebworksError::ServerError { status: response.status_text() })?,
                 403 => Err(LoginError::IncorrectPassword),
                 400 => Err(LoginError::NoSuchUser),
@@ -35,6 +98,16 @@ pub async fn get_token(data: &LoginData) -> Result<(), LoginError> {
     }
 }
 
+pub async fn get_registration_challenge() -> Result<PowChallenge, WebworksError> {
+    let resp = Request::get(&format!("{}user/challenge", URL_BASE))
+        .send()
+        .await?;
+    if !resp.ok() {
+        return Err(WebworksError::ServerError { status: resp.status_text() });
+    }
+    Ok(resp.json::<PowChallenge>().await?)
+}
+
 pub async fn register(user_creation: &UserCreationData) -> Result<(), RegisterError> {
     let body = serde_json::to_string(&user_creation).unwrap();
     let resp = Request::post(&(URL_BASE.to_owned()+ "user/create"))
@@ -52,6 +125,7 @@ pub async fn register(user_creation: &UserCreationData) -> Result<(), RegisterEr
                     Err(RegisterError::ValidationError(body))
                 },
                 201 => Ok(()),
+                403 | 409 => Err(RegisterError::Rejected { reason: resp.status_text() }),
                 _ => Err(WebworksError::ServerError { status: resp.status_text() })?,
             }
         },
@@ -82,8 +156,7 @@ pub async fn get_user_data() -> Option<UserData> {
 
 pub async fn update_user_data(data: &UserData) -> Result<(), UpdateUserError> {
     // let body = serde_json::to_string(&data);
-    let response = Request::post(&(URL_BASE.to_owned()+ "user/update"))
-        .credentials(leptos::web_sys::RequestCredentials::Include)
+    let response = post_with_session(&(URL_BASE.to_owned()+ "user/update")).await
         .header("Content-Type", "application/json")
         .json(data).map_err(|e| WebworksError::Other { source: Box::new(e) })?
         .send()
@@ -102,38 +175,61 @@ pub async fn update_user_data(data: &UserData) -> Result<(), UpdateUserError> {
 }
 
 pub async fn logout_user() -> Result<()> {
-    let _resp = Request::post(&(URL_BASE.to_owned()+ "user/logout"))
-        .credentials(leptos::web_sys::RequestCredentials::Include)
+    let _resp = post_with_session(&(URL_BASE.to_owned()+ "user/logout")).await
         .header("Content-Type", "application/json")
         .send()
         .await.map_err(|e| anyhow!(e))?;
+    set_csrf_token(None);
     Ok(())
 }
 
-pub fn get_pfp_url_for_login(login: &str) -> String {
-    let r= format!("{}user/get_pfp/{}#{}", URL_BASE, login, chrono::Utc::now().timestamp());
-    r
-    // format!("{}user/get_pfp/{}", URL_BASE, login)
+/// With a version the url changes on every upload, so the browser may keep the picture for good.
+/// Users that never uploaded one get the default picture, revalidated on each load
+pub fn get_pfp_url_for_login(login: &str, version: Option<&str>) -> String {
+    match version {
+        Some(v) => format!("{}user/get_pfp/{}?size={}&v={}", URL_BASE, login, PFP_DISPLAY_SIZE, v),
+        None => format!("{}user/get_pfp/{}?size={}", URL_BASE, login, PFP_DISPLAY_SIZE),
+    }
+}
+
+pub fn get_banner_url_for_login(login: &str, version: &str) -> String {
+    format!("{}user/get_banner/{}?v={}", URL_BASE, login, version)
+}
+
+/// Returns the new `banner_version` of the user
+pub async fn upload_banner(file: web_sys::File, limits: InstanceLimits) -> Result<String, PfpUploadError> {
+    let content_type = file.type_();
+    check_image(&file, &limits, limits.banner_bytes)
+        .map_err(|reason| PfpUploadError::Rejected { reason })?;
+    let resp = post_with_session(&format!("{}user/upload_banner", URL_BASE)).await
+        .header("Content-Type", &content_type)
+        .body(file).map_err(|e| WebworksError::Other { source: Box::new(e) })?
+        .send()
+        .await.map_err(|e| WebworksError::Other { source: Box::new(e) })?;
+    match resp.status() {
+        200 => Ok(resp.json::<BannerUploadResponse>().await
+            .map_err(|e| WebworksError::Other { source: Box::new(e) })?
+            .banner_version),
+        400 => Err(PfpUploadError::Rejected { reason: resp.status_text() }),
+        _ => Err(WebworksError::Unknown { msg: "banner upload error".to_string() })?,
+    }
 }
 
-pub async fn upload_new_pfp(file: web_sys::File) -> mpsc::Receiver<Result<(), PfpUploadError>> {
+/// Resolves to the new `pfp_version` of the user
+pub async fn upload_new_pfp(file: web_sys::File, limits: InstanceLimits) -> mpsc::Receiver<Result<String, PfpUploadError>> {
     use wasm_bindgen::prelude::*;
 
     use web_sys::{Request, RequestInit, Response};
 
-    let (sender, reciever) = mpsc::channel::<Result<(), PfpUploadError>>(1);
-    let file_name = file.name();
-    if !file_name.ends_with(".jpg") && !file_name.ends_with(".jpeg") {
-        sender.send(Err(PfpUploadError::Rejected { reason: "the file is not a valid jpg/jpeg".to_string() })).await
-            .unwrap();
-        return reciever;
-    }
-    if file.size() > 25_000.0 {
-        sender.send(Err(PfpUploadError::Rejected { reason: "this file is to big and will not be sent".to_string() })).await
+    let (sender, reciever) = mpsc::channel::<Result<String, PfpUploadError>>(1);
+    let content_type = file.type_();
+    if let Err(reason) = check_image(&file, &limits, limits.pfp_bytes) {
+        sender.send(Err(PfpUploadError::Rejected { reason })).await
             .unwrap();
         return reciever;
     }
 
+    let csrf = csrf_token().await;
     let reader = web_sys::FileReader::new().map_err(|e| PfpUploadError::Websys { js_value: e }).unwrap();
 
     reader.read_as_array_buffer(&file).unwrap();
@@ -146,7 +242,22 @@ pub async fn upload_new_pfp(file: web_sys::File) -> mpsc::Receiver<Result<(), Pf
     let resolve = js_closure!(move |v: JsValue|{
         let response = Response::from(v);
         match response.status() {
-            200 => sender_clone2.blocking_send(Ok(())).unwrap(),
+            200 => {
+                let sender = sender_clone2.clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    let text = match response.text() {
+                        Ok(p) => wasm_bindgen_futures::JsFuture::from(p).await,
+                        Err(e) => Err(e),
+                    };
+                    let res = match text {
+                        Ok(text) => serde_json::from_str::<PfpUploadResponse>(&text.as_string().unwrap_or_default())
+                            .map(|r| r.pfp_version)
+                            .map_err(|e| PfpUploadError::from(WebworksError::Unknown { msg: e.to_string() })),
+                        Err(e) => Err(PfpUploadError::Websys { js_value: e }),
+                    };
+                    sender.send(res).await.unwrap();
+                });
+            },
             400 => sender_clone2.blocking_send(Err(PfpUploadError::Rejected{ reason: response.status_text()})).unwrap(),
             _ => sender_clone2.blocking_send(Err(PfpUploadError::Webworks { source: WebworksError::Unknown { msg: "unknown error".to_owned() } })).unwrap()
         };
@@ -165,7 +276,10 @@ pub async fn upload_new_pfp(file: web_sys::File) -> mpsc::Receiver<Result<(), Pf
         let url = format!("{}user/upload_pfp", URL_BASE);
         let request = Request::new_with_str_and_init(&url, &opts).unwrap();
         request.headers()
-            .set("content-type", "image/jpg").unwrap();
+            .set("content-type", &content_type).unwrap();
+        if let Some(token) = &csrf {
+            request.headers().set(CSRF_HEADER, token).unwrap();
+        }
 
         let window = web_sys::window().unwrap();
 
@@ -212,8 +326,7 @@ pub async fn get_post(pid: uuid::Uuid) -> Result<PostData, WebworksError> {
 }
 
 pub async fn create_post(data: PostCreationData) -> Result<(), CreatePostError> {
-    let resp = Request::post(&format!("{}posts/create", URL_BASE))
-        .credentials(leptos::web_sys::RequestCredentials::Include)
+    let resp = post_with_session(&format!("{}posts/create", URL_BASE)).await
         .json(&data).map_err(|e| WebworksError::Other { source: Box::new(e) })?
         .send()
         .await.map_err(|e| WebworksError::Other { source: Box::new(e) })?;
@@ -224,6 +337,31 @@ pub async fn create_post(data: PostCreationData) -> Result<(), CreatePostError>
     }
 }
 
+pub fn get_attachment_url(attachment_id: Uuid, thumb: bool) -> String {
+    if thumb {
+        format!("{}posts/attachments/{}?thumb=true", URL_BASE, attachment_id)
+    } else {
+        format!("{}posts/attachments/{}", URL_BASE, attachment_id)
+    }
+}
+
+/// Uploads an image for a post that is still being written
+pub async fn upload_attachment(file: web_sys::File, limits: InstanceLimits) -> Result<AttachmentData, AttachmentUploadError> {
+    let content_type = file.type_();
+    check_image(&file, &limits, limits.attachment_bytes)
+        .map_err(|reason| AttachmentUploadError::Rejected { reason })?;
+    let resp = post_with_session(&format!("{}posts/attachments", URL_BASE)).await
+        .header("Content-Type", &content_type)
+        .body(file).map_err(|e| WebworksError::Other { source: Box::new(e) })?
+        .send()
+        .await.map_err(|e| WebworksError::Other { source: Box::new(e) })?;
+    match resp.status() {
+        201 => Ok(resp.json::<AttachmentData>().await.map_err(|e| WebworksError::Other { source: Box::new(e) })?),
+        400 | 429 => Err(AttachmentUploadError::Rejected { reason: resp.status_text() }),
+        _ => Err(WebworksError::Unknown { msg: "attachment upload error".to_string() })?,
+    }
+}
+
 pub async fn get_user_profile(query: super::data::UserProfileQuery) -> WebworksResult<UserData> {
 
     let req_str = format!("{}{}{}{}", URL_BASE, "user/profile?",
@@ -254,12 +392,91 @@ pub async fn load_comments(pid: Uuid, limit: i32) -> Result<Vec<CommentData>, We
     let json = serde_json::from_str::<Vec<CommentData>>(&text).expect("expected comment data list response json from api");
     Ok(json)
 }
-pub async fn leave_comment(ccdata: CommentCreationData) -> Result<(), WebworksError> {
+pub async fn leave_comment(ccdata: CommentCreationData) -> Result<(), CreateCommentError> {
     let request_str = format!("{}{}", URL_BASE, "comments/post");
-    Request::post(&request_str)
+    let resp = post_with_session(&request_str).await
+        .json(&ccdata).map_err(WebworksError::from)?
+        .send()
+        .await.map_err(WebworksError::from)?;
+    match resp.status() {
+        400 => Err(CreateCommentError::ValidationError(resp.json::<ValidationErrorBody>().await.map_err(WebworksError::from)?)),
+        _ => Ok(())
+    }
+}
+
+pub async fn get_oauth_client_info(request: &OAuthAuthorizeQuery) -> Result<OAuthClientInfo, WebworksError> {
+    let mut params = vec![
+        ("response_type", request.response_type.clone()),
+        ("client_id", request.client_id.to_string()),
+        ("redirect_uri", request.redirect_uri.clone()),
+        ("code_challenge", request.code_challenge.clone()),
+    ];
+    if let Some(scope) = &request.scope {
+        params.push(("scope", scope.clone()));
+    }
+    if let Some(method) = &request.code_challenge_method {
+        params.push(("code_challenge_method", method.clone()));
+    }
+    let response = Request::get(&format!("{}oauth/authorize", URL_BASE))
+        .query(params)
+        .send()
+        .await?;
+    match response.status() {
+        200 => Ok(response.json::<OAuthClientInfo>().await?),
+        _ => Err(WebworksError::Unknown { msg: response.text().await.unwrap_or_default() })
+    }
+}
+
+pub async fn send_oauth_consent(consent: &OAuthConsent) -> Result<OAuthConsentResponse, WebworksError> {
+    let response = post_with_session(&format!("{}oauth/authorize", URL_BASE)).await
+        .json(consent)?
+        .send()
+        .await?;
+    match response.status() {
+        200 => Ok(response.json::<OAuthConsentResponse>().await?),
+        _ => Err(WebworksError::ServerError { status: response.status_text() })
+    }
+}
+
+pub async fn report_content(report: &ReportCreationData) -> Result<(), WebworksError> {
+    let response = post_with_session(&format!("{}reports", URL_BASE)).await
+        .json(report)?
+        .send()
+        .await?;
+    match response.status() {
+        201 => Ok(()),
+        _ => Err(WebworksError::Unknown { msg: response.status_text() })
+    }
+}
+
+pub async fn load_report_queue() -> Result<Vec<ReportData>, WebworksError> {
+    let response = Request::get(&format!("{}reports/queue", URL_BASE))
         .credentials(leptos::web_sys::RequestCredentials::Include)
-        .json(&ccdata)?
         .send()
         .await?;
-    Ok(())
+    match response.status() {
+        200 => Ok(response.json::<Vec<ReportData>>().await?),
+        _ => Err(WebworksError::Unknown { msg: response.status_text() })
+    }
+}
+
+pub async fn claim_report(report_id: Uuid) -> Result<(), WebworksError> {
+    let response = post_with_session(&format!("{}reports/{}/claim", URL_BASE, report_id)).await
+        .send()
+        .await?;
+    match response.status() {
+        200 => Ok(()),
+        _ => Err(WebworksError::Unknown { msg: response.status_text() })
+    }
+}
+
+pub async fn resolve_report(report_id: Uuid, resolution: &ReportResolution) -> Result<(), WebworksError> {
+    let response = post_with_session(&format!("{}reports/{}/resolve", URL_BASE, report_id)).await
+        .json(resolution)?
+        .send()
+        .await?;
+    match response.status() {
+        200 => Ok(()),
+        _ => Err(WebworksError::Unknown { msg: response.status_text() })
+    }
 }